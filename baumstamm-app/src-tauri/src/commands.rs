@@ -1,159 +1,207 @@
-use crate::error::Error;
+use crate::{error::Error, settings, watcher, windows, AppState};
 use baumstamm_grid::{self, GridItem};
-use baumstamm_lib::{FamilyTree, Person, Relationship};
+use baumstamm_lib::{
+    changelog,
+    command::{Command, CommandResult},
+    place::Place,
+    schema::KeyDefinition,
+    storage::{self, Backup},
+    FamilyTree, Person, PersonId, Relationship,
+};
 use specta::specta;
 use std::path::PathBuf;
+use tauri::{Manager, Window};
 
 type State<'a> = tauri::State<'a, crate::State>;
-type Rid = baumstamm_lib::RelationshipId;
-type Pid = baumstamm_lib::PersonId;
 
 // io
-pub(crate) fn open_file(path: PathBuf, state: State) -> Result<(), Error> {
-    let data = std::fs::read_to_string(&path)?;
-    let tree = if data.is_empty() {
+/// Read a file into the tree of a window.
+pub(crate) fn open_file(path: PathBuf, label: &str, state: &crate::State) -> Result<(), Error> {
+    let data = std::fs::read(&path)?;
+    let (tree, data) = if data.is_empty() {
         let tree = FamilyTree::new();
-        let data = tree.save()?;
-        std::fs::write(&path, data)?;
-        tree
+        let data = tree.save_bytes()?;
+        storage::save(&path, &data)?;
+        (tree, data)
     } else {
-        FamilyTree::try_from(&data)?
+        (FamilyTree::load(&data)?, data)
     };
-    let mut lock = state.0.lock().unwrap();
-    lock.path = Some(path);
-    lock.tree = tree;
+    state.with(label, |state| {
+        state.path = Some(path);
+        state.tree = tree;
+        state.disk = data;
+        state.dirty = false;
+    });
     Ok(())
 }
 
-pub(crate) fn save_file(path: PathBuf, state: State) -> Result<(), Error> {
-    let mut lock = state.0.lock().unwrap();
-    let data = lock.tree.save()?;
-    std::fs::write(&path, data)?;
-    lock.path = Some(path);
+/// Save the tree of a window to a file.
+pub(crate) fn save_file(path: PathBuf, label: &str, state: &crate::State) -> Result<(), Error> {
+    state.with(label, |state| save(state, path))
+}
+
+fn save(state: &mut AppState, path: PathBuf) -> Result<(), Error> {
+    let data = state.tree.save_bytes()?;
+    storage::save(&path, &data)?;
+    let log = state.tree.take_log();
+    changelog::append_to_file(&changelog::log_path(&path), &log)?;
+    state.path = Some(path);
+    state.disk = data;
+    state.dirty = false;
     Ok(())
 }
 
-// get datastructures
+/// Save the tree of a window to its file, unless it was modified by another program.
+///
+/// Returns `false`, if the tree has no file yet.
+pub(crate) fn save_current(label: &str, state: &crate::State) -> Result<bool, Error> {
+    state.with(label, |state| {
+        let Some(path) = state.path.clone() else {
+            return Ok(false);
+        };
+        // keep the changes in memory, the watcher reports the conflict
+        if watcher::modified_externally(state) {
+            return Err(watcher::Conflict(path).into());
+        }
+        save(state, path)?;
+        Ok(true)
+    })
+}
+
+/// Discard local changes and read the opened file again.
 #[tauri::command]
 #[specta]
-pub(crate) fn get_persons(state: State) -> Result<Vec<Person>, ()> {
-    let persons = state.0.lock().unwrap().tree.get_persons().to_vec();
-    Ok(persons)
+pub(crate) fn reload_file(window: Window, state: State) -> Result<(), Error> {
+    let path = state.with(window.label(), |state| state.path.clone());
+    if let Some(path) = path {
+        open_file(path, window.label(), &state)?;
+    }
+    windows::update_title(&window)?;
+    Ok(())
 }
 
+/// Previous versions of the opened file, the newest first.
 #[tauri::command]
 #[specta]
-pub(crate) fn get_relationships(state: State) -> Result<Vec<Relationship>, ()> {
-    let persons = state.0.lock().unwrap().tree.get_relationships().to_vec();
-    Ok(persons)
+pub(crate) fn get_backups(window: Window, state: State) -> Result<Vec<Backup>, Error> {
+    match state.with(window.label(), |state| state.path.clone()) {
+        Some(path) => Ok(storage::backups(&path)?),
+        None => Ok(Vec::new()),
+    }
 }
 
+/// Replace the opened file with a backup, given by its number or timestamp.
+///
+/// Local changes are discarded, the replaced version is kept as backup.
 #[tauri::command]
 #[specta]
-pub(crate) fn get_grid(state: State) -> Result<Vec<Vec<GridItem>>, ()> {
-    let tree = &state.0.lock().unwrap().tree;
-    let grid = baumstamm_grid::generate(tree);
-    Ok(grid)
+pub(crate) fn restore_backup(backup: String, window: Window, state: State) -> Result<(), Error> {
+    state.with(window.label(), |state| {
+        let Some(path) = state.path.clone() else {
+            return Ok(());
+        };
+        let backup = storage::find_backup(&path, &backup)?;
+        state.tree = storage::restore(&path, &backup)?;
+        state.disk = std::fs::read(&path)?;
+        state.dirty = false;
+        Ok(())
+    })?;
+    windows::update_title(&window)?;
+    Ok(())
 }
 
-// adding nodes
+// get datastructures
 #[tauri::command]
 #[specta]
-pub(crate) fn add_parent(rid: Rid, state: State) -> Result<(Pid, Rid), Error> {
-    let mut lock = state.0.lock().unwrap();
-    let result = lock.tree.add_parent(rid)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(result)
+pub(crate) fn get_persons(window: Window, state: State) -> Result<Vec<Person>, ()> {
+    let persons = state.with(window.label(), |state| state.tree.get_persons().to_vec());
+    Ok(persons)
 }
 
+/// Display names of all persons, which have a name.
 #[tauri::command]
 #[specta]
-pub(crate) fn add_child(rid: Rid, state: State) -> Result<Pid, Error> {
-    let mut lock = state.0.lock().unwrap();
-    let result = lock.tree.add_child(rid)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(result)
+pub(crate) fn get_display_names(
+    window: Window,
+    state: State,
+) -> Result<Vec<(PersonId, String)>, ()> {
+    let names = state.with(window.label(), |state| {
+        state
+            .tree
+            .get_persons()
+            .iter()
+            .filter_map(|person| Some((person.id, person.display_name()?)))
+            .collect()
+    });
+    Ok(names)
 }
 
 #[tauri::command]
 #[specta]
-pub(crate) fn add_new_relationship(pid: Pid, state: State) -> Result<Rid, Error> {
-    let mut lock = state.0.lock().unwrap();
-    let result = lock.tree.add_new_relationship(pid)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(result)
+pub(crate) fn get_relationships(window: Window, state: State) -> Result<Vec<Relationship>, ()> {
+    let rels = state.with(window.label(), |state| {
+        state.tree.get_relationships().to_vec()
+    });
+    Ok(rels)
 }
 
 #[tauri::command]
 #[specta]
-pub(crate) fn add_relationship_with_partner(
-    pid: Pid,
-    partner_pid: Pid,
-    state: State,
-) -> Result<Rid, Error> {
-    let mut lock = state.0.lock().unwrap();
-    let result = lock.tree.add_relationship_with_partner(pid, partner_pid)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(result)
+pub(crate) fn get_places(window: Window, state: State) -> Result<Vec<Place>, ()> {
+    let places = state.with(window.label(), |state| state.tree.get_places().to_vec());
+    Ok(places)
 }
 
 #[tauri::command]
 #[specta]
-pub(crate) fn remove_person(pid: Pid, state: State) -> Result<(), Error> {
-    let mut lock = state.0.lock().unwrap();
-    lock.tree.remove_person(pid)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(())
+pub(crate) fn get_grid(window: Window, state: State) -> Result<Vec<Vec<GridItem>>, ()> {
+    let grid = state.with(window.label(), |state| {
+        baumstamm_grid::generate(&state.tree)
+    });
+    Ok(grid)
 }
 
 #[tauri::command]
 #[specta]
-pub(crate) fn merge_person(pid1: Pid, pid2: Pid, state: State) -> Result<(), Error> {
-    let mut lock = state.0.lock().unwrap();
-    lock.tree.merge_person(pid1, pid2)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(())
+pub(crate) fn get_info_keys() -> Result<Vec<KeyDefinition>, ()> {
+    Ok(baumstamm_lib::schema::KEYS.to_vec())
 }
 
-// info
+// mutations
 #[tauri::command]
 #[specta]
-pub(crate) fn insert_info(pid: Pid, key: String, value: String, state: State) -> Result<(), Error> {
-    let mut lock = state.0.lock().unwrap();
-    lock.tree.insert_info(pid, key, value)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(())
+pub(crate) fn apply_command(
+    command: Command,
+    window: Window,
+    state: State,
+) -> Result<CommandResult, Error> {
+    let result = state.with(window.label(), |state| state.tree.apply(command))?;
+    changed(&window, &state)?;
+    Ok(result)
 }
 
+/// Apply several commands as one transaction, saving only once.
 #[tauri::command]
 #[specta]
-pub(crate) fn remove_info(pid: Pid, key: &str, state: State) -> Result<String, Error> {
-    let mut lock = state.0.lock().unwrap();
-    let result = lock.tree.remove_info(pid, key)?;
-    if let Some(path) = lock.path.clone() {
-        drop(lock);
-        save_file(path, state)?;
-    }
-    Ok(result)
+pub(crate) fn apply_commands(
+    commands: Vec<Command>,
+    window: Window,
+    state: State,
+) -> Result<Vec<CommandResult>, Error> {
+    let results = state.with(window.label(), |state| state.tree.apply_all(commands))?;
+    changed(&window, &state)?;
+    Ok(results)
+}
+
+/// Mark the tree of a window as changed and save it, if changes are saved automatically.
+fn changed(window: &Window, state: &crate::State) -> Result<(), Error> {
+    state.with(window.label(), |state| state.dirty = true);
+    let saved = if settings::auto_save(&window.app_handle()) {
+        save_current(window.label(), state).map(|_| ())
+    } else {
+        Ok(())
+    };
+    // the title shows unsaved changes, even if saving failed
+    windows::update_title(window)?;
+    saved
 }
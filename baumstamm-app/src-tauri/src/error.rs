@@ -10,6 +10,15 @@ pub enum Error {
     #[serde(serialize_with = "serialize_to_string")]
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[serde(serialize_with = "serialize_to_string")]
+    #[error(transparent)]
+    Watch(#[from] notify::Error),
+    #[serde(serialize_with = "serialize_to_string")]
+    #[error(transparent)]
+    Tauri(#[from] tauri::Error),
+    #[serde(serialize_with = "serialize_to_string")]
+    #[error(transparent)]
+    Conflict(#[from] crate::watcher::Conflict),
 }
 
 fn serialize_to_string<E: ToString, S>(error: &E, serializer: S) -> Result<S::Ok, S::Error>
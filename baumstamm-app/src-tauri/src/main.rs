@@ -5,26 +5,48 @@
 
 use baumstamm_lib::FamilyTree;
 use color_eyre::eyre::Result;
+use notify::RecommendedWatcher;
 #[cfg(debug_assertions)]
 use specta::{
     collect_types,
     ts::{BigIntExportBehavior, ExportConfiguration},
 };
-use std::{path::PathBuf, sync::Mutex};
-use tauri::{api::dialog::FileDialogBuilder, CustomMenuItem, Manager, Menu, MenuItem, Submenu};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+use tauri::{
+    api::dialog::FileDialogBuilder, CustomMenuItem, Manager, Menu, MenuItem, Submenu, Window,
+    WindowEvent,
+};
 #[cfg(debug_assertions)]
 use tauri_specta::ts;
 
 mod commands;
 mod error;
+mod recent;
+mod settings;
+mod watcher;
+mod windows;
 
+/// The trees of all windows by window label.
 #[derive(Debug, Default)]
-struct State(Mutex<AppState>);
+struct State(Mutex<HashMap<String, AppState>>);
+
+impl State {
+    /// Run a function on the tree of a window, which starts with a new tree.
+    fn with<T>(&self, label: &str, f: impl FnOnce(&mut AppState) -> T) -> T {
+        let mut lock = self.0.lock().unwrap();
+        f(lock.entry(label.to_string()).or_default())
+    }
+}
 
 #[derive(Debug, Default)]
 struct AppState {
     tree: FamilyTree,
     path: Option<PathBuf>,
+    /// Content of the file, when it was last read or written.
+    disk: Vec<u8>,
+    watcher: Option<RecommendedWatcher>,
+    /// Whether the tree has changes, which are not saved yet.
+    dirty: bool,
 }
 
 fn main() -> Result<()> {
@@ -35,59 +57,118 @@ fn main() -> Result<()> {
     tauri::Builder::default()
         .manage(State::default())
         .menu(build_menu())
+        .setup(|app| {
+            let settings = settings::load(&app.handle());
+            let recent = recent::load(&app.handle());
+            for window in app.windows().values() {
+                recent::update_menu(window, &recent);
+                settings::update_menu(window, settings.auto_save);
+            }
+            app.manage(Mutex::new(settings));
+            Ok(())
+        })
+        .on_window_event(|event| match event.event() {
+            WindowEvent::CloseRequested { api, .. } => {
+                windows::close_requested(event.window(), api)
+            }
+            WindowEvent::Destroyed => windows::forget(event.window()),
+            _ => {}
+        })
         .on_menu_event(|event| {
-            let window = event.window();
+            let window = event.window().clone();
             let app = window.app_handle();
             match event.menu_item_id() {
+                "new" => {
+                    if let Err(err) = windows::new_tree(&app) {
+                        window
+                            .emit("open-error", err.to_string())
+                            .expect("open-error event failed");
+                    }
+                }
                 "open" => {
                     FileDialogBuilder::new()
                         .add_filter("Application", &["json"])
                         .pick_file(move |path| {
                             if let Some(path) = path {
-                                let state = app.state();
-                                match commands::open_file(path, state) {
-                                    Ok(_) => app.emit_all("open", ()).expect("open event failed"),
-                                    Err(err) => app
-                                        .emit_all("open-error", err.to_string())
-                                        .expect("open-error event failed"),
-                                };
-                            }
-                        });
-                }
-                "save_as" => {
-                    FileDialogBuilder::new()
-                        .add_filter("Application", &["json"])
-                        .save_file(move |path| {
-                            if let Some(path) = path {
-                                let state = app.state();
-                                if let Err(err) = commands::save_file(path, state) {
-                                    app.emit_all("save-as-error", err.to_string())
-                                        .expect("save-as-error event failed");
+                                if let Err(err) = windows::open(&window, path) {
+                                    window
+                                        .emit("open-error", err.to_string())
+                                        .expect("open-error event failed");
                                 }
                             }
                         });
                 }
-                _ => {}
+                "save" => match windows::save(&window) {
+                    Ok(true) => {}
+                    // a new tree has no file yet
+                    Ok(false) => save_as(window),
+                    Err(err) => window
+                        .emit("save-error", err.to_string())
+                        .expect("save-error event failed"),
+                },
+                "save_as" => save_as(window),
+                "auto_save" => {
+                    if let Err(err) = settings::toggle_auto_save(&app) {
+                        window
+                            .emit("save-error", err.to_string())
+                            .expect("save-error event failed");
+                    }
+                }
+                "restore_backup" => window
+                    .emit("restore-backup", ())
+                    .expect("restore-backup event failed"),
+                "close" => windows::close(window),
+                "clear_recent" => {
+                    if let Err(err) = recent::clear(&app) {
+                        window
+                            .emit("open-error", err.to_string())
+                            .expect("open-error event failed");
+                    }
+                }
+                id => {
+                    if let Some(path) = recent::get(&app, id) {
+                        if let Err(err) = windows::open(&window, path) {
+                            window
+                                .emit("open-error", err.to_string())
+                                .expect("open-error event failed");
+                        }
+                    }
+                }
             };
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_persons,
+            commands::get_display_names,
             commands::get_relationships,
+            commands::get_places,
             commands::get_grid,
-            commands::add_parent,
-            commands::add_child,
-            commands::add_new_relationship,
-            commands::add_relationship_with_partner,
-            commands::remove_person,
-            commands::merge_person,
-            commands::insert_info,
-            commands::remove_info,
+            commands::get_info_keys,
+            commands::apply_command,
+            commands::apply_commands,
+            commands::reload_file,
+            commands::get_backups,
+            commands::restore_backup,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
     Ok(())
 }
 
+/// Ask for a file and save the tree of the window to it.
+fn save_as(window: Window) {
+    FileDialogBuilder::new()
+        .add_filter("Application", &["json"])
+        .save_file(move |path| {
+            if let Some(path) = path {
+                if let Err(err) = windows::save_as(&window, path) {
+                    window
+                        .emit("save-as-error", err.to_string())
+                        .expect("save-as-error event failed");
+                }
+            }
+        });
+}
+
 fn build_menu() -> Menu {
     let about_menu = Submenu::new(
         "App",
@@ -99,9 +180,27 @@ fn build_menu() -> Menu {
             .add_native_item(MenuItem::Quit),
     );
 
+    let new = CustomMenuItem::new("new", "New Tree").accelerator("cmdOrControl+N");
     let open = CustomMenuItem::new("open", "Open").accelerator("cmdOrControl+O");
+    let save = CustomMenuItem::new("save", "Save").accelerator("cmdOrControl+S");
     let save_as = CustomMenuItem::new("save_as", "Save As").accelerator("cmdOrControl+Shift+S");
-    let file = Submenu::new("File", Menu::new().add_item(open).add_item(save_as));
+    let auto_save = CustomMenuItem::new("auto_save", "Save Automatically").selected();
+    let restore_backup = CustomMenuItem::new("restore_backup", "Restore Backup…");
+    let close = CustomMenuItem::new("close", "Close").accelerator("cmdOrControl+W");
+    let file = Submenu::new(
+        "File",
+        Menu::new()
+            .add_item(new)
+            .add_item(open)
+            .add_submenu(recent::submenu())
+            .add_native_item(MenuItem::Separator)
+            .add_item(save)
+            .add_item(save_as)
+            .add_item(auto_save)
+            .add_item(restore_backup)
+            .add_native_item(MenuItem::Separator)
+            .add_item(close),
+    );
 
     let edit_menu = Submenu::new(
         "Edit",
@@ -146,16 +245,16 @@ fn export() {
     ts::export_with_cfg(
         collect_types![
             commands::get_persons,
+            commands::get_display_names,
             commands::get_relationships,
+            commands::get_places,
             commands::get_grid,
-            commands::add_parent,
-            commands::add_child,
-            commands::add_new_relationship,
-            commands::add_relationship_with_partner,
-            commands::remove_person,
-            commands::merge_person,
-            commands::insert_info,
-            commands::remove_info,
+            commands::get_info_keys,
+            commands::apply_command,
+            commands::apply_commands,
+            commands::reload_file,
+            commands::get_backups,
+            commands::restore_backup,
         ]
         .expect("type collection failed"),
         ExportConfiguration::default().bigint(BigIntExportBehavior::String),
@@ -0,0 +1,79 @@
+//! Recently opened files, which are listed in the File menu and kept in the config directory.
+
+use crate::{
+    error::Error,
+    settings::{config_file, store_config},
+};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, CustomMenuItem, Manager, Menu, MenuItem, Submenu, Window};
+
+/// Number of remembered files.
+const MAX_RECENT: usize = 10;
+
+const EMPTY_SLOT: &str = "No Recent File";
+
+/// Recent files, the most recent first.
+pub(crate) fn load(app: &AppHandle) -> Vec<PathBuf> {
+    config_file(app, "recent.json")
+        .and_then(|file| std::fs::read(file).ok())
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn store(app: &AppHandle, recent: &[PathBuf]) -> Result<(), Error> {
+    store_config(app, "recent.json", recent)?;
+    for window in app.windows().values() {
+        update_menu(window, recent);
+    }
+    Ok(())
+}
+
+/// Move the file to the top of the recent files.
+pub(crate) fn add(app: &AppHandle, path: &Path) -> Result<(), Error> {
+    let mut recent = load(app);
+    recent.retain(|other| other != path);
+    recent.insert(0, path.to_path_buf());
+    recent.truncate(MAX_RECENT);
+    store(app, &recent)
+}
+
+pub(crate) fn clear(app: &AppHandle) -> Result<(), Error> {
+    store(app, &[])
+}
+
+/// The recent file shown in the menu item, which has the id `recent-<index>`.
+pub(crate) fn get(app: &AppHandle, menu_id: &str) -> Option<PathBuf> {
+    let index = menu_id.strip_prefix("recent-")?.parse::<usize>().ok()?;
+    load(app).get(index).cloned()
+}
+
+/// The Open Recent submenu.
+///
+/// Menus cannot grow after they are built, so there is a slot for every remembered file.
+pub(crate) fn submenu() -> Submenu {
+    let slots = (0..MAX_RECENT).fold(Menu::new(), |menu, index| {
+        menu.add_item(CustomMenuItem::new(format!("recent-{}", index), EMPTY_SLOT).disabled())
+    });
+    let clear = CustomMenuItem::new("clear_recent", "Clear Recent");
+    Submenu::new(
+        "Open Recent",
+        slots.add_native_item(MenuItem::Separator).add_item(clear),
+    )
+}
+
+/// Show the recent files in the slots of the window's menu.
+pub(crate) fn update_menu(window: &Window, recent: &[PathBuf]) {
+    let menu = window.menu_handle();
+    for index in 0..MAX_RECENT {
+        let item = menu.get_item(&format!("recent-{}", index));
+        let updated = match recent.get(index) {
+            Some(path) => item
+                .set_title(path.display().to_string())
+                .and_then(|_| item.set_enabled(true)),
+            None => item
+                .set_title(EMPTY_SLOT)
+                .and_then(|_| item.set_enabled(false)),
+        };
+        updated.expect("updating the recent files menu failed");
+    }
+}
@@ -0,0 +1,77 @@
+//! Settings of the app, which are kept in the config directory.
+
+use crate::error::Error;
+use baumstamm_lib::storage;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Mutex};
+use tauri::{AppHandle, Manager, Window};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Save every change right away instead of only on request.
+    pub(crate) auto_save: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { auto_save: true }
+    }
+}
+
+/// A file in the config directory of the app.
+pub(crate) fn config_file(app: &AppHandle, name: &str) -> Option<PathBuf> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join(name))
+}
+
+/// Write a file to the config directory of the app.
+pub(crate) fn store_config<T: Serialize + ?Sized>(
+    app: &AppHandle,
+    name: &str,
+    value: &T,
+) -> Result<(), Error> {
+    if let Some(file) = config_file(app, name) {
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_vec(value).map_err(std::io::Error::from)?;
+        storage::write_atomic(&file, &data)?;
+    }
+    Ok(())
+}
+
+/// Stored settings or the defaults.
+pub(crate) fn load(app: &AppHandle) -> Settings {
+    config_file(app, "settings.json")
+        .and_then(|file| std::fs::read(file).ok())
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Whether changes are saved right away.
+pub(crate) fn auto_save(app: &AppHandle) -> bool {
+    app.state::<Mutex<Settings>>().lock().unwrap().auto_save
+}
+
+/// Switch between saving every change and saving on request.
+pub(crate) fn toggle_auto_save(app: &AppHandle) -> Result<(), Error> {
+    let state = app.state::<Mutex<Settings>>();
+    let mut settings = state.lock().unwrap();
+    settings.auto_save = !settings.auto_save;
+    store_config(app, "settings.json", &*settings)?;
+    for window in app.windows().values() {
+        update_menu(window, settings.auto_save);
+    }
+    Ok(())
+}
+
+/// Check the Save Automatically item of the window's menu.
+pub(crate) fn update_menu(window: &Window, auto_save: bool) {
+    window
+        .menu_handle()
+        .get_item("auto_save")
+        .set_selected(auto_save)
+        .expect("updating the auto save menu item failed");
+}
@@ -0,0 +1,83 @@
+//! Watching of the opened file for modifications by other programs.
+
+use crate::{error::Error, AppState};
+use baumstamm_lib::FamilyTree;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tauri::{Manager, Window};
+use thiserror::Error;
+
+/// The file was modified by another program, while there were local changes.
+#[derive(Debug, Error)]
+#[error("{} was modified by another program", .0.display())]
+pub(crate) struct Conflict(pub PathBuf);
+
+/// Start watching the file of a window, replacing the previous watcher.
+pub(crate) fn watch(window: &Window, path: &Path) -> Result<(), Error> {
+    let handle = window.clone();
+    let file = path.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let modified = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+        if modified
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == file.file_name())
+        {
+            changed(&handle, &file);
+        }
+    })?;
+    // editors often replace the file instead of writing to it, so the directory is watched
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let state = window.state::<crate::State>();
+    let previous = state.with(window.label(), |tree| tree.watcher.replace(watcher));
+    // dropping a watcher may wait for its event handler, which needs the lock
+    drop(previous);
+    Ok(())
+}
+
+/// Whether the file on disk differs from the last version read or written by the app.
+pub(crate) fn modified_externally(state: &AppState) -> bool {
+    match &state.path {
+        Some(path) => std::fs::read(path).is_ok_and(|data| data != state.disk),
+        None => false,
+    }
+}
+
+/// Reload the tree, if there are no local changes, otherwise report a conflict.
+fn changed(window: &Window, path: &Path) {
+    let Ok(data) = std::fs::read(path) else {
+        return;
+    };
+    let state = window.state::<crate::State>();
+    let mut lock = state.0.lock().unwrap();
+    // the window may have been closed in the meantime
+    let Some(state) = lock.get_mut(window.label()) else {
+        return;
+    };
+    if state.path.as_deref() != Some(path) || state.disk == data {
+        return;
+    }
+    if state.dirty {
+        drop(lock);
+        window
+            .emit("file-conflict", path.display().to_string())
+            .expect("file-conflict event failed");
+        return;
+    }
+    // the file may still be written to, the next event brings the rest
+    let Ok(tree) = FamilyTree::load(&data) else {
+        return;
+    };
+    state.tree = tree;
+    state.disk = data;
+    drop(lock);
+    window.emit("reload", ()).expect("reload event failed");
+}
@@ -0,0 +1,145 @@
+//! Windows, each of which shows one tree.
+
+use crate::{commands, error::Error, recent, settings, watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tauri::{
+    api::dialog::ask, AppHandle, CloseRequestApi, Manager, Window, WindowBuilder, WindowUrl,
+};
+
+static NEXT_WINDOW: AtomicUsize = AtomicUsize::new(1);
+
+/// Open a window, after its tree has been prepared with the function.
+///
+/// The tree must be ready, before the window asks for it.
+fn new_window(
+    app: &AppHandle,
+    prepare: impl FnOnce(&str) -> Result<(), Error>,
+) -> Result<Window, Error> {
+    let label = format!("tree-{}", NEXT_WINDOW.fetch_add(1, Ordering::Relaxed));
+    prepare(&label)?;
+    let window = WindowBuilder::new(app, &label, WindowUrl::default())
+        .title("Baumstamm")
+        .inner_size(800.0, 600.0)
+        .menu(crate::build_menu())
+        .build()?;
+    recent::update_menu(&window, &recent::load(app));
+    settings::update_menu(&window, settings::auto_save(app));
+    Ok(window)
+}
+
+/// Open a window with a new tree.
+pub(crate) fn new_tree(app: &AppHandle) -> Result<(), Error> {
+    new_window(app, |_| Ok(()))?;
+    Ok(())
+}
+
+/// Open a file in the window, if its tree is empty, and in a new window otherwise.
+///
+/// A file, which is already open, is brought to the front instead.
+pub(crate) fn open(window: &Window, path: PathBuf) -> Result<(), Error> {
+    let app = window.app_handle();
+    let state = app.state::<crate::State>();
+    let path = path.canonicalize().unwrap_or(path);
+    let open_in = state
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, tree)| tree.path.as_ref() == Some(&path))
+        .map(|(label, _)| label.clone());
+    if let Some(other) = open_in.and_then(|label| app.get_window(&label)) {
+        other.set_focus()?;
+        return Ok(());
+    }
+    let empty = state.with(window.label(), |tree| tree.path.is_none() && !tree.dirty);
+    let target = if empty {
+        commands::open_file(path.clone(), window.label(), &state)?;
+        window.emit("open", ())?;
+        window.clone()
+    } else {
+        new_window(&app, |label| {
+            commands::open_file(path.clone(), label, &state)
+        })?
+    };
+    opened(&target, &path)
+}
+
+/// Save the tree of the window to its file and return `false`, if it has none yet.
+pub(crate) fn save(window: &Window) -> Result<bool, Error> {
+    let state = window.state::<crate::State>();
+    let saved = commands::save_current(window.label(), &state)?;
+    update_title(window)?;
+    Ok(saved)
+}
+
+/// Save the tree of the window to another file.
+pub(crate) fn save_as(window: &Window, path: PathBuf) -> Result<(), Error> {
+    let state = window.state::<crate::State>();
+    commands::save_file(path.clone(), window.label(), &state)?;
+    opened(window, &path)
+}
+
+/// Watch the file of the window, show its name and remember it.
+fn opened(window: &Window, path: &Path) -> Result<(), Error> {
+    watcher::watch(window, path)?;
+    update_title(window)?;
+    recent::add(&window.app_handle(), path)
+}
+
+/// Show the name of the window's file and whether it is modified in the title.
+pub(crate) fn update_title(window: &Window) -> Result<(), Error> {
+    let state = window.state::<crate::State>();
+    let (path, dirty) = state.with(window.label(), |tree| (tree.path.clone(), tree.dirty));
+    let name = path
+        .as_deref()
+        .and_then(Path::file_name)
+        .map_or("Untitled".into(), |name| name.to_string_lossy());
+    let modified = if dirty { "*" } else { "" };
+    window.set_title(&format!("{}{} - Baumstamm", modified, name))?;
+    Ok(())
+}
+
+/// Ask, before a window with unsaved changes is closed.
+pub(crate) fn close_requested(window: &Window, api: &CloseRequestApi) {
+    let state = window.state::<crate::State>();
+    let unsaved = state
+        .0
+        .lock()
+        .unwrap()
+        .get(window.label())
+        .is_some_and(|tree| tree.dirty);
+    if !unsaved {
+        return;
+    }
+    api.prevent_close();
+    let handle = window.clone();
+    ask(
+        Some(window),
+        "Unsaved Changes",
+        "The tree has unsaved changes, which are lost when the window is closed. Close anyway?",
+        move |close| {
+            if close {
+                // without its tree, the window closes without asking again
+                forget(&handle);
+                close(handle);
+            }
+        },
+    );
+}
+
+/// Close the window, which asks about unsaved changes first.
+pub(crate) fn close(window: Window) {
+    // windows cannot be closed from the main thread, on which menu events are handled
+    std::thread::spawn(move || window.close().expect("closing the window failed"));
+}
+
+/// Drop the tree of a window.
+pub(crate) fn forget(window: &Window) {
+    let state = window.state::<crate::State>();
+    let removed = state.0.lock().unwrap().remove(window.label());
+    // dropping a watcher may wait for its event handler, which needs the lock
+    drop(removed);
+}
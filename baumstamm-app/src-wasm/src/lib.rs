@@ -1,4 +1,4 @@
-use baumstamm_lib::FamilyTree;
+use baumstamm_lib::{command::Command, FamilyTree};
 use serde_wasm_bindgen as bind;
 use wasm_bindgen::prelude::*;
 
@@ -9,8 +9,6 @@ pub struct State {
 }
 
 type JResult = std::result::Result<JsValue, JsValue>;
-type Pid = baumstamm_lib::PersonId;
-type Rid = baumstamm_lib::RelationshipId;
 
 #[wasm_bindgen]
 pub fn init_state() -> State {
@@ -30,106 +28,80 @@ pub fn save_tree(state: &State) -> JResult {
     Ok(JsValue::from(string))
 }
 
-// get datastructures
+/// Load a tree in JSON or CBOR format.
 #[wasm_bindgen]
-pub fn get_persons(state: &State) -> JResult {
-    let persons = state.tree.get_persons().to_vec();
-    Ok(bind::to_value(&persons)?)
+pub fn load_tree_bytes(input: &[u8], state: &mut State) -> JResult {
+    let tree = FamilyTree::load(input).map_err(|err| err.to_string())?;
+    state.tree = tree;
+    Ok(JsValue::NULL)
 }
 
+/// Save the tree in the format it was loaded in.
 #[wasm_bindgen]
-pub fn get_relationships(state: &State) -> JResult {
-    let persons = state.tree.get_relationships().to_vec();
-    Ok(bind::to_value(&persons)?)
+pub fn save_tree_bytes(state: &State) -> Result<Vec<u8>, JsValue> {
+    let bytes = state.tree.save_bytes().map_err(|err| err.to_string())?;
+    Ok(bytes)
 }
 
+// get datastructures
 #[wasm_bindgen]
-pub fn get_grid(state: &State) -> JResult {
-    let tree = &state.tree;
-    let grid = baumstamm_grid::generate(tree);
-    Ok(bind::to_value(&grid)?)
-}
-
-// adding nodes
-#[wasm_bindgen]
-pub fn add_parent(rid: &str, state: &mut State) -> JResult {
-    let result = state
-        .tree
-        .add_parent(parse_rid(rid)?)
-        .map_err(|err| err.to_string())?;
-    Ok(bind::to_value(&result)?)
+pub fn get_persons(state: &State) -> JResult {
+    let persons = state.tree.get_persons().to_vec();
+    Ok(bind::to_value(&persons)?)
 }
 
+/// Display names of all persons, which have a name.
 #[wasm_bindgen]
-pub fn add_child(rid: &str, state: &mut State) -> JResult {
-    let result = state
+pub fn get_display_names(state: &State) -> JResult {
+    let names = state
         .tree
-        .add_child(parse_rid(rid)?)
-        .map_err(|err| err.to_string())?;
-    Ok(bind::to_value(&result)?)
+        .get_persons()
+        .iter()
+        .filter_map(|person| Some((person.id, person.display_name()?)))
+        .collect::<Vec<_>>();
+    Ok(bind::to_value(&names)?)
 }
 
 #[wasm_bindgen]
-pub fn add_new_relationship(pid: &str, state: &mut State) -> JResult {
-    let result = state
-        .tree
-        .add_new_relationship(parse_pid(pid)?)
-        .map_err(|err| err.to_string())?;
-    Ok(bind::to_value(&result)?)
+pub fn get_relationships(state: &State) -> JResult {
+    let persons = state.tree.get_relationships().to_vec();
+    Ok(bind::to_value(&persons)?)
 }
 
 #[wasm_bindgen]
-pub fn add_relationship_with_partner(pid: &str, partner_pid: &str, state: &mut State) -> JResult {
-    let result = state
-        .tree
-        .add_relationship_with_partner(parse_pid(pid)?, parse_pid(partner_pid)?)
-        .map_err(|err| err.to_string())?;
-    Ok(bind::to_value(&result)?)
+pub fn get_places(state: &State) -> JResult {
+    let places = state.tree.get_places().to_vec();
+    Ok(bind::to_value(&places)?)
 }
 
 #[wasm_bindgen]
-pub fn remove_person(pid: &str, state: &mut State) -> JResult {
-    state
-        .tree
-        .remove_person(parse_pid(pid)?)
-        .map_err(|err| err.to_string())?;
-    Ok(JsValue::NULL)
+pub fn get_grid(state: &State) -> JResult {
+    let tree = &state.tree;
+    let grid = baumstamm_grid::generate(tree);
+    Ok(bind::to_value(&grid)?)
 }
 
 #[wasm_bindgen]
-pub fn merge_person(pid1: &str, pid2: &str, state: &mut State) -> JResult {
-    state
-        .tree
-        .merge_person(parse_pid(pid1)?, parse_pid(pid2)?)
-        .map_err(|err| err.to_string())?;
-    Ok(JsValue::NULL)
+pub fn get_info_keys() -> JResult {
+    Ok(bind::to_value(baumstamm_lib::schema::KEYS)?)
 }
 
-// info
+// mutations
+/// Apply a serialized `Command` and return its result.
 #[wasm_bindgen]
-pub fn insert_info(pid: &str, key: &str, value: &str, state: &mut State) -> JResult {
-    state
-        .tree
-        .insert_info(parse_pid(pid)?, key.to_string(), value.to_string())
-        .map_err(|err| err.to_string())?;
-    Ok(JsValue::NULL)
+pub fn apply_command(command: JsValue, state: &mut State) -> JResult {
+    let command: Command = bind::from_value(command)?;
+    let result = state.tree.apply(command).map_err(|err| err.to_string())?;
+    Ok(bind::to_value(&result)?)
 }
 
+/// Apply serialized commands as one transaction and return their results.
 #[wasm_bindgen]
-pub fn remove_info(pid: &str, key: &str, state: &mut State) -> JResult {
-    let result = state
+pub fn apply_commands(commands: JsValue, state: &mut State) -> JResult {
+    let commands: Vec<Command> = bind::from_value(commands)?;
+    let results = state
         .tree
-        .remove_info(parse_pid(pid)?, key)
+        .apply_all(commands)
         .map_err(|err| err.to_string())?;
-    Ok(bind::to_value(&result)?)
-}
-
-fn parse_rid(rid: &str) -> Result<Rid, JsValue> {
-    rid.try_into()
-        .map_err(|err: std::num::ParseIntError| JsValue::from_str(&err.to_string()))
-}
-
-fn parse_pid(pid: &str) -> Result<Pid, JsValue> {
-    pid.try_into()
-        .map_err(|err: std::num::ParseIntError| JsValue::from_str(&err.to_string()))
+    Ok(bind::to_value(&results)?)
 }
@@ -14,44 +14,44 @@ export function getPersons() {
     return invoke()<Person[]>("get_persons")
 }
 
-export function getRelationships() {
-    return invoke()<Relationship[]>("get_relationships")
+export function getDisplayNames() {
+    return invoke()<[PersonId, string][]>("get_display_names")
 }
 
-export function getGrid() {
-    return invoke()<GridItem[][]>("get_grid")
+export function getRelationships() {
+    return invoke()<Relationship[]>("get_relationships")
 }
 
-export function addParent(rid: RelationshipId) {
-    return invoke()<[PersonId, RelationshipId]>("add_parent", { rid })
+export function getPlaces() {
+    return invoke()<Place[]>("get_places")
 }
 
-export function addChild(rid: RelationshipId) {
-    return invoke()<PersonId>("add_child", { rid })
+export function getGrid() {
+    return invoke()<GridItem[][]>("get_grid")
 }
 
-export function addNewRelationship(pid: PersonId) {
-    return invoke()<RelationshipId>("add_new_relationship", { pid })
+export function getInfoKeys() {
+    return invoke()<KeyDefinition[]>("get_info_keys")
 }
 
-export function addRelationshipWithPartner(pid: PersonId, partnerPid: PersonId) {
-    return invoke()<RelationshipId>("add_relationship_with_partner", { pid,partnerPid })
+export function applyCommand(command: Command) {
+    return invoke()<CommandResult>("apply_command", { command })
 }
 
-export function removePerson(pid: PersonId) {
-    return invoke()<null>("remove_person", { pid })
+export function applyCommands(commands: Command[]) {
+    return invoke()<CommandResult[]>("apply_commands", { commands })
 }
 
-export function mergePerson(pid1: PersonId, pid2: PersonId) {
-    return invoke()<null>("merge_person", { pid1,pid2 })
+export function reloadFile() {
+    return invoke()<null>("reload_file")
 }
 
-export function insertInfo(pid: PersonId, key: string, value: string) {
-    return invoke()<null>("insert_info", { pid,key,value })
+export function getBackups() {
+    return invoke()<Backup[]>("get_backups")
 }
 
-export function removeInfo(pid: PersonId, key: string) {
-    return invoke()<string>("remove_info", { pid,key })
+export function restoreBackup(backup: string) {
+    return invoke()<null>("restore_backup", { backup })
 }
 
 /**
@@ -67,13 +67,63 @@ export type Origin = "Left" | "Right" | "None"
 /**
  * A person with a unique identifier and arbitrary attached information
  */
-export type Person = { id: PersonId; info: { [key: string]: string } | null }
+export type Person = { id: PersonId; info: { [key: string]: string } | null; names?: Name[] }
+/**
+ * A name of a person, valid in a certain period.
+ */
+export type Name = { given: string | null; surname: string | null; prefix: string | null; suffix: string | null; name_type: NameType; valid_from: string | null; valid_until: string | null; primary: boolean }
+/**
+ * The kind of a `Name`.
+ */
+export type NameType = "Birth" | "Married" | "Nickname" | "Patronymic" | "Religious" | "Adopted" | "Other"
 export type Connections = { orientation: Orientation; total_x: number; total_y: number; passing: Passing[]; ending: Ending[]; crossing: Crossing[] }
 export type Crossing = { connection: number; color: [number, number, number]; origin: Origin; x_index: number; y_index: number }
 /**
  * A relationship referencing two optional parents and the resulting children.
  */
 export type Relationship = { id: RelationshipId; parents: (PersonId | null)[]; children: PersonId[] }
+/**
+ * Declaration of a known info key.
+ */
+export type KeyDefinition = { key: string; label: string; value_type: ValueType }
+/**
+ * Type of the value stored under an info key.
+ */
+export type ValueType = "Text" | "Date" | "Place" | { Enum: string[] } | "Url"
 export type Orientation = "Up" | "Down"
 export type GridItem = { Person: PersonId } | { Connections: Connections }
 export type Ending = { connection: number; color: [number, number, number]; origin: Origin; x_index: number; y_index: number }
+/**
+ * A mutation of a `FamilyTree` with its arguments.
+ */
+export type Command = { AddParent: { relationship: RelationshipId } } | { AddChild: { relationship: RelationshipId } } | { AddNewRelationship: { person: PersonId } } | { AddRelationshipWithPartner: { person: PersonId; partner: PersonId } } | { RemovePerson: { person: PersonId } } | { MergePerson: { person1: PersonId; person2: PersonId } } | { InsertInfo: { person: PersonId; key: string; value: string } } | { RemoveInfo: { person: PersonId; key: string } } | { AddName: { person: PersonId; name: Name } } | { UpdateName: { person: PersonId; index: number; name: Name } } | { RemoveName: { person: PersonId; index: number } } | { SetPrimaryName: { person: PersonId; index: number } } | { AddPlace: { name: string; parent: PlaceId | null } } | { SetPlaceCoordinates: { place: PlaceId; coordinates: Coordinates | null } } | { AddPlaceName: { place: PlaceId; name: AlternateName } } | { RemovePlace: { place: PlaceId } } | { SetPlace: { person: PersonId; key: string; place: PlaceId } } | "NormalizePlaces"
+/**
+ * Return value of an applied `Command`.
+ * 
+ * Serialized without a tag, so front-ends receive the plain value of the underlying method.
+ */
+export type CommandResult = null | PersonId | RelationshipId | [PersonId, RelationshipId] | string | number | Name | PlaceId | PlaceMerge[]
+/**
+ * UUID for a `Place`, stored as u128.
+ */
+export type PlaceId = string
+/**
+ * A named place, which may be part of a larger place (village → county → country).
+ */
+export type Place = { id: PlaceId; name: string; parent: PlaceId | null; coordinates: Coordinates | null; alternate_names?: AlternateName[] }
+/**
+ * Geographical coordinates in decimal degrees.
+ */
+export type Coordinates = { latitude: number; longitude: number }
+/**
+ * Another name, under which a place is or was known.
+ */
+export type AlternateName = { name: string; historical: boolean }
+/**
+ * Result of merging a duplicate place into another one.
+ */
+export type PlaceMerge = { merged: PlaceId; into: PlaceId }
+/**
+ * A previous version of a tree file.
+ */
+export type Backup = { path: string; timestamp: number }
@@ -5,10 +5,12 @@ export class Person {
 	info: Map<string, string>;
 	firstName: string | null = null;
 	lastName: string | null = null;
+	displayName: string | null;
 	image: string | null = null;
 
-	constructor(id: string, info: Map<string, string>) {
+	constructor(id: string, info: Map<string, string>, displayName: string | null = null) {
 		this.id = id;
+		this.displayName = displayName;
 		const firstName = info.get('@firstName');
 		if (firstName !== undefined) {
 			this.firstName = firstName;
@@ -27,7 +29,10 @@ export class Person {
 		this.info = info;
 	}
 
-	static from(person: RPerson): Person {
+	/**
+	 * The display name is chosen by the backend, see `getDisplayNames`.
+	 */
+	static from(person: RPerson, displayName: string | null = null): Person {
 		let info: Map<string, string>;
 		if (person.info != null) {
 			// wasm returns a map, tauri a dict
@@ -39,25 +44,19 @@ export class Person {
 		} else {
 			info = new Map();
 		}
-		return new Person(person.id, info);
+		return new Person(person.id, info, displayName);
 	}
 
 	public name(): string {
-		if (this.firstName != null && this.lastName != null) {
-			return this.firstName + ' ' + this.lastName;
-		} else if (this.firstName != null && this.lastName == null) {
-			return this.firstName;
-		} else if (this.firstName == null && this.lastName != null) {
-			return this.lastName;
-		} else {
-			return 'Unknown';
-		}
+		return this.displayName ?? 'Unknown';
 	}
 
 	public initials(): string {
-		let firstLetter = this.firstName?.at(0);
-		let secondLetter = this.lastName
-			? [...this.lastName].find((char) => char === char.toUpperCase()) ?? this.lastName?.at(0)
+		const words = this.displayName?.split(' ') ?? [];
+		const lastWord = words.length > 1 ? words[words.length - 1] : undefined;
+		let firstLetter = words.at(0)?.at(0);
+		let secondLetter = lastWord
+			? [...lastWord].find((char) => char === char.toUpperCase()) ?? lastWord.at(0)
 			: undefined;
 		if (firstLetter == null && secondLetter == null) {
 			return '?';
@@ -2,37 +2,40 @@ import {
 	type PersonId,
 	type RelationshipId,
 	getPersons as tauriGetPersons,
+	getDisplayNames as tauriGetDisplayNames,
 	getRelationships as tauriGetRelationships,
+	getPlaces as tauriGetPlaces,
 	getGrid as tauriGetGrid,
-	addParent as tauriAddParent,
-	addChild as tauriAddChild,
-	addNewRelationship as tauriAddNewRelationship,
-	addRelationshipWithPartner as tauriAddRelationshipWithPartner,
-	removePerson as tauriRemovePerson,
-	mergePerson as tauriMergePerson,
-	insertInfo as tauriInsertInfo,
-	removeInfo as tauriRemoveInfo,
+	getInfoKeys as tauriGetInfoKeys,
+	applyCommand as tauriApplyCommand,
+	applyCommands as tauriApplyCommands,
+	reloadFile as tauriReloadFile,
+	getBackups as tauriGetBackups,
+	restoreBackup as tauriRestoreBackup,
+	type Backup,
+	type Command,
+	type CommandResult,
+	type Name,
 	type Person,
+	type Place,
 	type Relationship,
-	type GridItem
+	type GridItem,
+	type KeyDefinition
 } from '../bindings-tauri';
 import {
-	add_child as wasmAddChild,
-	add_new_relationship as wasmAddNewRelationship,
-	add_parent as wasmAddParent,
-	add_relationship_with_partner as wasmAddRelationshipWithPartner,
+	apply_command as wasmApplyCommand,
+	apply_commands as wasmApplyCommands,
 	get_grid as wasmGetGrid,
+	get_info_keys as wasmGetInfoKeys,
+	get_display_names as wasmGetDisplayNames,
 	get_persons as wasmGetPersons,
 	get_relationships as wasmGetRelationships,
-	merge_person as wasmMergePersons,
-	remove_info as wasmRemoveInfo,
-	remove_person as wasmRemovePerson,
-	insert_info as wasmInsertInfo,
+	get_places as wasmGetPlaces,
 	load_tree as wasmLoadTree,
 	save_tree as wasmSaveTree
 } from '$lib/baumstamm-wasm/baumstamm_wasm';
 import { listen as tauriListen, type UnlistenFn } from '@tauri-apps/api/event';
-import { toastStore, type ToastSettings } from '@skeletonlabs/skeleton';
+import { modalStore, toastStore, type ToastSettings } from '@skeletonlabs/skeleton';
 import { update } from '$lib/store';
 
 export async function listen() {
@@ -51,6 +54,41 @@ export async function listen() {
 				toastStore.trigger(toast);
 			})
 		);
+		unlisten.push(
+			await tauriListen('reload', () => {
+				update();
+				const toast: ToastSettings = {
+					message: 'The file was changed by another program and has been reloaded.'
+				};
+				toastStore.trigger(toast);
+			})
+		);
+		unlisten.push(
+			await tauriListen('file-conflict', (e) => {
+				const toast: ToastSettings = {
+					message: `${e.payload as string} was changed by another program.`,
+					autohide: false,
+					action: {
+						label: 'Discard changes and reload',
+						response: () => tauriReloadFile().then(update)
+					}
+				};
+				toastStore.trigger(toast);
+			})
+		);
+		unlisten.push(
+			await tauriListen('restore-backup', () => {
+				modalStore.trigger({ type: 'component', component: 'backups' });
+			})
+		);
+		unlisten.push(
+			await tauriListen('save-error', (e) => {
+				const toast: ToastSettings = {
+					message: e.payload as string
+				};
+				toastStore.trigger(toast);
+			})
+		);
 		unlisten.push(
 			await tauriListen('save-as-error', (e) => {
 				const toast: ToastSettings = {
@@ -107,6 +145,17 @@ export async function getPersons(): Promise<Person[]> {
 	}
 }
 
+/**
+ * Display names of all persons, which have a name, as chosen by the primary name rule of the backend.
+ */
+export async function getDisplayNames(): Promise<Map<PersonId, string>> {
+	if ('__TAURI__' in window) {
+		return new Map(await tauriGetDisplayNames());
+	} else {
+		return new Map(wasmGetDisplayNames(window.state));
+	}
+}
+
 export async function getRelationships(): Promise<Relationship[]> {
 	if ('__TAURI__' in window) {
 		return tauriGetRelationships();
@@ -115,6 +164,14 @@ export async function getRelationships(): Promise<Relationship[]> {
 	}
 }
 
+export async function getPlaces(): Promise<Place[]> {
+	if ('__TAURI__' in window) {
+		return tauriGetPlaces();
+	} else {
+		return wasmGetPlaces(window.state);
+	}
+}
+
 export async function getGrid(): Promise<GridItem[][]> {
 	if ('__TAURI__' in window) {
 		return tauriGetGrid();
@@ -123,83 +180,122 @@ export async function getGrid(): Promise<GridItem[][]> {
 	}
 }
 
-export async function addParent(rid: RelationshipId): Promise<[PersonId, RelationshipId]> {
+export async function getInfoKeys(): Promise<KeyDefinition[]> {
 	if ('__TAURI__' in window) {
-		return tauriAddParent(rid);
+		return tauriGetInfoKeys();
 	} else {
-		return wasmAddParent(rid, window.state);
+		return wasmGetInfoKeys();
 	}
 }
 
-export async function addChild(rid: RelationshipId): Promise<PersonId> {
+export async function applyCommand(command: Command): Promise<CommandResult> {
 	if ('__TAURI__' in window) {
-		return tauriAddChild(rid);
+		return tauriApplyCommand(command);
 	} else {
-		return wasmAddChild(rid, window.state);
+		return wasmApplyCommand(command, window.state);
 	}
 }
 
-export async function addNewRelationship(pid: PersonId): Promise<RelationshipId> {
+/**
+ * Apply commands as one transaction, either all of them succeed or the tree stays unchanged.
+ */
+export async function applyCommands(commands: Command[]): Promise<CommandResult[]> {
 	if ('__TAURI__' in window) {
-		return tauriAddNewRelationship(pid);
+		return tauriApplyCommands(commands);
 	} else {
-		return wasmAddNewRelationship(pid, window.state);
+		return wasmApplyCommands(commands, window.state);
 	}
 }
 
-export async function addRelationshipWithPartner(
-	pid: PersonId,
-	partnerPid: PersonId
-): Promise<RelationshipId> {
+/**
+ * Previous versions of the opened file, the newest first.
+ */
+export async function getBackups(): Promise<Backup[]> {
 	if ('__TAURI__' in window) {
-		return tauriAddRelationshipWithPartner(pid, partnerPid);
+		return tauriGetBackups();
 	} else {
-		return wasmAddRelationshipWithPartner(pid, partnerPid, window.state);
+		return [];
 	}
 }
 
-export async function removePerson(pid: PersonId): Promise<null> {
+/**
+ * Replace the opened file with a backup, discarding local changes.
+ */
+export async function restoreBackup(backup: Backup) {
 	if ('__TAURI__' in window) {
-		return tauriRemovePerson(pid);
-	} else {
-		return wasmRemovePerson(pid, window.state);
+		await tauriRestoreBackup(backup.timestamp.toString());
+		update();
 	}
 }
 
+export async function addParent(rid: RelationshipId): Promise<[PersonId, RelationshipId]> {
+	return applyCommand({ AddParent: { relationship: rid } }) as Promise<[PersonId, RelationshipId]>;
+}
+
+export async function addChild(rid: RelationshipId): Promise<PersonId> {
+	return applyCommand({ AddChild: { relationship: rid } }) as Promise<PersonId>;
+}
+
+export async function addNewRelationship(pid: PersonId): Promise<RelationshipId> {
+	return applyCommand({ AddNewRelationship: { person: pid } }) as Promise<RelationshipId>;
+}
+
+export async function addRelationshipWithPartner(
+	pid: PersonId,
+	partnerPid: PersonId
+): Promise<RelationshipId> {
+	return applyCommand({
+		AddRelationshipWithPartner: { person: pid, partner: partnerPid }
+	}) as Promise<RelationshipId>;
+}
+
+export async function removePerson(pid: PersonId): Promise<null> {
+	return applyCommand({ RemovePerson: { person: pid } }) as Promise<null>;
+}
+
 export async function mergePerson(pid1: PersonId, pid2: PersonId): Promise<null> {
-	if ('__TAURI__' in window) {
-		return tauriMergePerson(pid1, pid2);
-	} else {
-		return wasmMergePersons(pid1, pid2, window.state);
-	}
+	return applyCommand({ MergePerson: { person1: pid1, person2: pid2 } }) as Promise<null>;
 }
 
 export async function insertInfo(pid: PersonId, key: string, value: string): Promise<null> {
-	if ('__TAURI__' in window) {
-		return tauriInsertInfo(pid, key, value);
-	} else {
-		return wasmInsertInfo(pid, key, value, window.state);
-	}
+	return applyCommand({ InsertInfo: { person: pid, key, value } }) as Promise<null>;
 }
 
 export async function removeInfo(pid: PersonId, key: string): Promise<string> {
-	if ('__TAURI__' in window) {
-		return tauriRemoveInfo(pid, key);
-	} else {
-		return wasmRemoveInfo(pid, key, window.state);
-	}
+	return applyCommand({ RemoveInfo: { person: pid, key } }) as Promise<string>;
+}
+
+export async function addName(pid: PersonId, name: Name): Promise<number> {
+	return applyCommand({ AddName: { person: pid, name } }) as Promise<number>;
+}
+
+export async function removeName(pid: PersonId, index: number): Promise<Name> {
+	return applyCommand({ RemoveName: { person: pid, index } }) as Promise<Name>;
+}
+
+export async function setPrimaryName(pid: PersonId, index: number): Promise<null> {
+	return applyCommand({ SetPrimaryName: { person: pid, index } }) as Promise<null>;
 }
 
 export type {
+	Backup,
+	Command,
+	CommandResult,
 	Connections,
 	Crossing,
 	Ending,
 	GridItem,
+	KeyDefinition,
+	Name,
+	NameType,
 	Orientation,
 	Origin,
 	Passing,
 	Person,
 	PersonId,
+	Place,
+	PlaceId,
 	Relationship,
-	RelationshipId
+	RelationshipId,
+	ValueType
 } from '../bindings-tauri';
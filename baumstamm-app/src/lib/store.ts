@@ -1,16 +1,20 @@
 import { writable, get } from 'svelte/store';
 import { Person } from './Person';
 import {
+	getDisplayNames,
 	getGrid,
 	getPersons,
+	getPlaces,
 	getRelationships,
 	type GridItem,
 	type PersonId,
+	type Place,
 	type Relationship
 } from '$lib/api';
 
 export const persons = writable<Person[]>([]);
 export const relationships = writable<Relationship[]>([]);
+export const places = writable<Place[]>([]);
 export const selected = writable<Person | null>(null);
 export const target = writable<Person | null>(null);
 export const grid = writable<GridItem[][]>([]);
@@ -36,7 +40,10 @@ export async function updateTarget(pid: PersonId | null) {
 export async function update() {
 	// update persons
 	const newPersons = await getPersons();
-	const mappedPersons = newPersons.map((person) => Person.from(person));
+	const displayNames = await getDisplayNames();
+	const mappedPersons = newPersons.map((person) =>
+		Person.from(person, displayNames.get(person.id) ?? null)
+	);
 	persons.update(() => mappedPersons);
 
 	// update selectedStore when necessary and possible
@@ -54,6 +61,10 @@ export async function update() {
 	const newRelationships = await getRelationships();
 	relationships.update(() => newRelationships);
 
+	// update places
+	const newPlaces = await getPlaces();
+	places.update(() => newPlaces);
+
 	// update grid
 	const newGrid = await getGrid();
 	grid.update(() => newGrid);
@@ -1,16 +1,54 @@
-use baumstamm_lib::{graph::Graph, FamilyTree, PersonId, RelationshipId};
+use baumstamm_lib::{
+    changelog,
+    command::{self, Command, CommandResult},
+    error::{Error as LibError, InputError, MergeConflict},
+    graph::Graph,
+    name::{Name as PersonName, NameType},
+    place::{Coordinates, PlaceId},
+    reference::RelationshipRole,
+    spreadsheet::{self, Import as SheetImport},
+    storage, FamilyTree, Format, PersonId,
+};
 use clap::{Args, Parser, Subcommand};
-use std::{error::Error, fs, path::Path};
+use output::{OutputFormat, Printer};
+use render::{RenderOptions, Renderer};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::Path,
+    process::ExitCode,
+};
+
+mod output;
+mod render;
+mod script;
+mod shell;
 
 #[derive(Parser)]
+#[command(
+    after_help = "Persons are referenced by id, unique id prefix or name, e.g. \"Anna Müller\".\n\
+    Relationships also by both partners, e.g. \"Anna & Karl\"."
+)]
 struct Cli {
-    file: String,
+    file: Option<String>,
 
     #[command(subcommand)]
     action: Option<Action>,
 
     #[arg(short, long)]
     new: bool,
+
+    /// Author recorded in the change log
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Format of results and errors
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
 }
 
 #[derive(Subcommand)]
@@ -21,6 +59,40 @@ enum Action {
     Info(Info),
     #[command(subcommand)]
     Show(Show),
+    #[command(subcommand)]
+    Place(Place),
+    #[command(subcommand)]
+    Name(Name),
+    /// Remove a person, which must not connect other persons
+    Remove(Remove),
+    /// Merge two persons, which are the same, into a new one
+    Merge(Merge),
+    Search(Search),
+    /// Convert the file to another format
+    Convert(Convert),
+    #[command(subcommand)]
+    Export(Export),
+    /// Replace the tree with an imported one
+    #[command(subcommand)]
+    Import(Import),
+    /// Compare two versions of a tree
+    Diff(Diff),
+    /// Three-way merge for use as git merge driver
+    ///
+    /// Configure it with `git config merge.baumstamm.driver "baumstamm-cli merge-driver %O %A %B"`
+    /// and `*.json merge=baumstamm` in `.gitattributes`.
+    MergeDriver(MergeDriver),
+    /// List the recorded changes of the tree
+    Log(Log),
+    /// List and restore previous versions of the tree file
+    #[command(subcommand)]
+    Backup(Backup),
+    /// Apply a batch of commands stored as JSON lines as one transaction
+    Apply(Apply),
+    /// Run a script of actions as one transaction, e.g. `$child = add child $rel`
+    Script(Script),
+    /// Start an interactive shell, which keeps the tree loaded
+    Shell,
 }
 
 #[derive(Subcommand)]
@@ -28,6 +100,8 @@ enum Add {
     Child(Child),
     Parent(Parent),
     NewRelationship(NewRelationship),
+    /// Add a relationship between two existing persons
+    #[command(visible_alias = "relationship")]
     RelationshipWithPartner(RelationshipWithPartner),
 }
 
@@ -38,11 +112,13 @@ struct NewRelationship {
 
 #[derive(Args)]
 struct Child {
+    /// Relationship or a parent with only one relationship
     rel_id: String,
 }
 
 #[derive(Args)]
 struct Parent {
+    /// Relationship or the child, whose parents it is
     rel_id: String,
 }
 
@@ -56,6 +132,23 @@ struct RelationshipWithPartner {
 enum Info {
     Insert(InsertInfo),
     Remove(RemoveInfo),
+    /// List the info of a person
+    List(ListInfo),
+    /// Print the value of an info key of a person
+    Get(GetInfo),
+    /// List the keys declared in the schema
+    Keys,
+}
+
+#[derive(Args)]
+struct ListInfo {
+    person_id: String,
+}
+
+#[derive(Args)]
+struct GetInfo {
+    person_id: String,
+    key: String,
 }
 
 #[derive(Args)]
@@ -71,106 +164,1075 @@ struct RemoveInfo {
     key: String,
 }
 
+#[derive(Subcommand)]
+enum Name {
+    Add(AddName),
+    /// Change a name, fields which are not given keep their value and empty values remove them
+    Update(UpdateName),
+    Remove(NameIndex),
+    Primary(NameIndex),
+}
+
+#[derive(Args)]
+struct AddName {
+    person_id: String,
+    #[arg(long)]
+    given: Option<String>,
+    #[arg(long)]
+    surname: Option<String>,
+    #[arg(long)]
+    prefix: Option<String>,
+    #[arg(long)]
+    suffix: Option<String>,
+    #[arg(long = "type", default_value = "birth")]
+    name_type: NameType,
+    #[arg(long)]
+    from: Option<String>,
+    #[arg(long)]
+    until: Option<String>,
+    #[arg(long)]
+    primary: bool,
+}
+
+#[derive(Args)]
+struct UpdateName {
+    person_id: String,
+    index: usize,
+    #[arg(long)]
+    given: Option<String>,
+    #[arg(long)]
+    surname: Option<String>,
+    #[arg(long)]
+    prefix: Option<String>,
+    #[arg(long)]
+    suffix: Option<String>,
+    #[arg(long = "type")]
+    name_type: Option<NameType>,
+    #[arg(long)]
+    from: Option<String>,
+    #[arg(long)]
+    until: Option<String>,
+    #[arg(long)]
+    primary: bool,
+}
+
+#[derive(Args)]
+struct NameIndex {
+    person_id: String,
+    index: usize,
+}
+
+#[derive(Args)]
+struct Convert {
+    /// "json" or "cbor"
+    format: Format,
+}
+
+#[derive(Subcommand)]
+enum Export {
+    /// Export persons as CSV with one column per info key
+    Persons(ExportFile),
+    /// Export relationships as CSV with parent and child ids
+    Relationships(ExportFile),
+    /// Export the tree in the human-editable text format
+    Text(ExportFile),
+}
+
+#[derive(Args)]
+struct ExportFile {
+    /// Output file, prints to stdout if omitted
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Import {
+    /// Import a CSV sheet with "id", "father" and "mother" columns and one column per info key
+    Persons(ImportFile),
+    /// Import a tree in the human-editable text format
+    Text(ImportFile),
+}
+
+#[derive(Args)]
+struct ImportFile {
+    input: String,
+    /// Replace an existing tree file
+    #[arg(short, long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct Diff {
+    old: String,
+    new: String,
+}
+
+#[derive(Args)]
+struct MergeDriver {
+    base: String,
+    /// Our version, which is replaced with the merged tree
+    ours: String,
+    theirs: String,
+}
+
+#[derive(Args)]
+struct Log {
+    /// Apply the recorded changes to an older snapshot of the tree and save it
+    #[arg(long)]
+    replay: Option<String>,
+    /// Number of the first entry to replay
+    #[arg(long, default_value_t = 1, requires = "replay")]
+    from: usize,
+}
+
+#[derive(Subcommand)]
+enum Backup {
+    /// List the backups, the newest first
+    List,
+    /// Replace the tree file with a backup, which is kept as backup as well
+    Restore(RestoreBackup),
+}
+
+#[derive(Args)]
+struct RestoreBackup {
+    /// Number in the list of backups or timestamp
+    backup: String,
+}
+
+#[derive(Args)]
+struct Apply {
+    input: String,
+}
+
+#[derive(Args)]
+struct Script {
+    /// Script file, reads from stdin if omitted or "-"
+    input: Option<String>,
+}
+
+#[derive(Args)]
+struct Remove {
+    person_id: String,
+}
+
+#[derive(Args)]
+struct Merge {
+    person_id: String,
+    other_id: String,
+    /// Show the resulting relationships without saving
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct Search {
+    query: String,
+}
+
+#[derive(Subcommand)]
+enum Place {
+    Add(AddPlace),
+    Set(SetPlace),
+    List,
+    Normalize,
+}
+
+#[derive(Args)]
+struct AddPlace {
+    name: String,
+    #[arg(long)]
+    parent: Option<String>,
+    #[arg(long, requires = "longitude", allow_negative_numbers = true)]
+    latitude: Option<f64>,
+    #[arg(long, requires = "latitude", allow_negative_numbers = true)]
+    longitude: Option<f64>,
+}
+
+#[derive(Args)]
+struct SetPlace {
+    person_id: String,
+    key: String,
+    place_id: String,
+}
+
 #[derive(Subcommand)]
 enum Show {
     Persons,
     Relationships,
     Layers,
     PersonLayers,
+    /// Draw the descendants of all persons without parents
+    Tree(ShowTree),
+    /// Draw the ancestors of a person
+    Ancestors(Ancestors),
+    /// Draw the descendants of a person
+    Descendants(Descendants),
 }
 
+#[derive(Args)]
+struct ShowTree {
+    /// Show partners with the children of each relationship
+    #[arg(long)]
+    partners: bool,
+    #[command(flatten)]
+    options: RenderOptions,
+}
+
+#[derive(Args)]
+struct Ancestors {
+    person_id: String,
+    #[command(flatten)]
+    options: RenderOptions,
+}
+
+#[derive(Args)]
+struct Descendants {
+    person_id: String,
+    /// Show partners with the children of each relationship
+    #[arg(long)]
+    partners: bool,
+    #[command(flatten)]
+    options: RenderOptions,
+}
+
+/// Save the tree and append its recorded changes to the log next to it.
 fn save<P: AsRef<Path>>(path: P, tree: &FamilyTree) -> Result<(), Box<dyn Error>> {
-    fs::write(path, tree.save()?)?;
+    storage::save(path.as_ref(), &tree.save_bytes()?)?;
+    changelog::append_to_file(&changelog::log_path(path.as_ref()), tree.log())?;
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+impl Action {
+    /// Whether the action changes the tree by applying commands.
+    fn is_mutation(&self) -> bool {
+        match self {
+            Action::Add(_) | Action::Name(_) | Action::Remove(_) => true,
+            Action::Merge(merge) => !merge.dry_run,
+            Action::Info(info) => matches!(info, Info::Insert(_) | Info::Remove(_)),
+            Action::Place(place) => !matches!(place, Place::List),
+            _ => false,
+        }
+    }
+
+    /// Whether the action only shows the tree.
+    fn is_query(&self) -> bool {
+        match self {
+            Action::Search(_) | Action::Show(_) | Action::Place(Place::List) => true,
+            Action::Merge(merge) => merge.dry_run,
+            Action::Info(info) => matches!(info, Info::List(_) | Info::Get(_) | Info::Keys),
+            _ => false,
+        }
+    }
+}
+
+/// Show the tree as requested by the action.
+fn query(tree: &FamilyTree, action: Action, printer: Printer) -> Result<(), Box<dyn Error>> {
+    match action {
+        Action::Info(Info::Keys) => {
+            let keys = baumstamm_lib::schema::KEYS;
+            printer.print(&keys, || {
+                keys.iter()
+                    .map(|definition| {
+                        format!(
+                            "{}: {} ({:?})",
+                            definition.key, definition.label, definition.value_type
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        Action::Info(Info::List(list)) => {
+            let pid = tree.resolve_person(&list.person_id)?;
+            let info = person_info(tree, pid);
+            printer.print(&info, || {
+                info.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        Action::Info(Info::Get(get)) => {
+            let pid = tree.resolve_person(&get.person_id)?;
+            let value = person_info(tree, pid)
+                .remove(&get.key)
+                .ok_or(LibError::Input(InputError::InvalidKey))?;
+            printer.print(&value, || value.clone())
+        }
+        Action::Merge(merge) => {
+            // merge a copy of the tree to show the result
+            let mut merged = tree.clone();
+            let command = Command::MergePerson {
+                person1: tree.resolve_person(&merge.person_id)?,
+                person2: tree.resolve_person(&merge.other_id)?,
+            };
+            merged.apply(command.clone())?;
+            // both persons are replaced by a new one
+            let person = merged
+                .get_persons()
+                .iter()
+                .map(|person| person.id)
+                .find(|pid| !tree.get_persons().iter().any(|person| person.id == *pid))
+                .expect("Merging adds a person");
+            let rels = merged
+                .get_relationships()
+                .iter()
+                .filter(|rel| rel.parents.contains(&Some(person)) || rel.children.contains(&person))
+                .collect::<Vec<_>>();
+            printer.print(
+                &json!({ "command": command, "person": person, "relationships": rels }),
+                || {
+                    let mut lines = vec![format!(
+                        "Merging would result in these relationships of the new person {}:",
+                        label(&merged, person)
+                    )];
+                    for rel in &rels {
+                        let labels = |pids: &mut dyn Iterator<Item = &PersonId>| {
+                            pids.map(|pid| label(&merged, *pid))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        };
+                        lines.push(format!("  {}", rel.id));
+                        lines.push(format!(
+                            "    parents: {}",
+                            labels(&mut rel.parents.iter().flatten())
+                        ));
+                        lines.push(format!(
+                            "    children: {}",
+                            labels(&mut rel.children.iter())
+                        ));
+                    }
+                    lines.join("\n")
+                },
+            )
+        }
+        Action::Place(Place::List) => {
+            let places = tree.get_places();
+            printer.print(&places, || format!("Places: {:#?}", places))
+        }
+        Action::Search(search) => {
+            let persons = tree.search(&search.query);
+            printer.print(&persons, || {
+                persons
+                    .iter()
+                    .map(|person| {
+                        format!(
+                            "{}: {}",
+                            person.id,
+                            person.display_name().unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        Action::Show(show) => match show {
+            Show::Persons => {
+                let persons = tree.sorted_persons();
+                printer.print(&persons, || format!("Persons: {:#?}", persons))
+            }
+            Show::Relationships => {
+                let rels = tree.get_relationships();
+                printer.print(&rels, || format!("Relationships: {:#?}", rels))
+            }
+            Show::Layers => {
+                let layers = Graph::new(tree.get_relationships()).cut().layers();
+                printer.print(&layers, || format!("Layers: {:#?}", layers))
+            }
+            Show::PersonLayers => {
+                let graph = Graph::new(tree.get_relationships()).cut();
+                let layers = graph.person_layers(tree.get_relationships());
+                printer.print(&layers, || format!("Person Layers: {:#?}", layers))
+            }
+            Show::Tree(show) => {
+                let nodes = Renderer::new(tree, &show.options, show.partners).forest();
+                printer.print(&nodes, || render::draw(&nodes, &show.options))
+            }
+            Show::Ancestors(ancestors) => {
+                let pid = tree.resolve_person(&ancestors.person_id)?;
+                let node = Renderer::new(tree, &ancestors.options, false).ancestors(pid);
+                printer.print(&node, || {
+                    render::draw(std::slice::from_ref(&node), &ancestors.options)
+                })
+            }
+            Show::Descendants(descendants) => {
+                let pid = tree.resolve_person(&descendants.person_id)?;
+                let node = Renderer::new(tree, &descendants.options, descendants.partners)
+                    .descendants(pid);
+                printer.print(&node, || {
+                    render::draw(std::slice::from_ref(&node), &descendants.options)
+                })
+            }
+        },
+        _ => Err("The action does not show the tree".into()),
+    }
+}
+
+/// Info of a person sorted by key.
+fn person_info(tree: &FamilyTree, pid: PersonId) -> BTreeMap<String, String> {
+    tree.get_persons()
+        .iter()
+        .find(|person| person.id == pid)
+        .and_then(|person| person.info.clone())
+        .map(|info| info.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// Id and display name of a person.
+fn label(tree: &FamilyTree, pid: PersonId) -> String {
+    let name = tree
+        .get_persons()
+        .iter()
+        .find(|person| person.id == pid)
+        .and_then(|person| person.display_name());
+    match name {
+        Some(name) => format!("{} ({})", pid, name),
+        None => pid.to_string(),
+    }
+}
+
+/// A command applied by an action with its result.
+#[derive(Serialize)]
+struct Applied {
+    command: Command,
+    result: CommandResult,
+}
+
+impl Applied {
+    fn apply(tree: &mut FamilyTree, command: Command) -> Result<Self, Box<dyn Error>> {
+        let result = tree.apply(command.clone())?;
+        Ok(Self { command, result })
+    }
+}
+
+/// Print applied commands.
+fn print_applied(printer: Printer, applied: &[Applied]) -> Result<(), Box<dyn Error>> {
+    printer.print(&applied, || {
+        applied
+            .iter()
+            .map(|applied| describe(&applied.command, &applied.result))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+}
+
+/// Apply an action, which changes the tree, and return the applied commands.
+fn mutate(tree: &mut FamilyTree, action: Action) -> Result<Vec<Applied>, Box<dyn Error>> {
+    let command = match action {
+        Action::Add(add) => match add {
+            Add::Child(child) => Command::AddChild {
+                relationship: tree.resolve_relationship(&child.rel_id, RelationshipRole::Parent)?,
+            },
+            Add::Parent(parent) => Command::AddParent {
+                relationship: tree.resolve_relationship(&parent.rel_id, RelationshipRole::Child)?,
+            },
+            Add::NewRelationship(rel) => Command::AddNewRelationship {
+                person: tree.resolve_person(&rel.person_id)?,
+            },
+            Add::RelationshipWithPartner(rel) => Command::AddRelationshipWithPartner {
+                person: tree.resolve_person(&rel.person_id)?,
+                partner: tree.resolve_person(&rel.partner_id)?,
+            },
+        },
+        Action::Info(Info::Insert(insert)) => Command::InsertInfo {
+            person: tree.resolve_person(&insert.person_id)?,
+            key: insert.key,
+            value: insert.value,
+        },
+        Action::Remove(remove) => Command::RemovePerson {
+            person: tree.resolve_person(&remove.person_id)?,
+        },
+        Action::Merge(merge) => Command::MergePerson {
+            person1: tree.resolve_person(&merge.person_id)?,
+            person2: tree.resolve_person(&merge.other_id)?,
+        },
+        Action::Info(Info::Remove(remove)) => Command::RemoveInfo {
+            person: tree.resolve_person(&remove.person_id)?,
+            key: remove.key,
+        },
+        Action::Place(Place::Add(add)) => {
+            let parent = add
+                .parent
+                .map(|parent| u128::from_str_radix(&parent, 16))
+                .transpose()?
+                .map(PlaceId);
+            let command = Command::AddPlace {
+                name: add.name,
+                parent,
+            };
+            let added = Applied::apply(tree, command)?;
+            let CommandResult::Place(place) = added.result else {
+                unreachable!("Adding a place returns its id")
+            };
+            let mut applied = vec![added];
+            if let (Some(latitude), Some(longitude)) = (add.latitude, add.longitude) {
+                let coordinates = Some(Coordinates {
+                    latitude,
+                    longitude,
+                });
+                let command = Command::SetPlaceCoordinates { place, coordinates };
+                applied.push(Applied::apply(tree, command)?);
+            }
+            return Ok(applied);
+        }
+        Action::Place(Place::Set(set)) => Command::SetPlace {
+            person: tree.resolve_person(&set.person_id)?,
+            key: set.key,
+            place: PlaceId(u128::from_str_radix(&set.place_id, 16)?),
+        },
+        Action::Place(Place::Normalize) => Command::NormalizePlaces,
+        Action::Name(name) => match name {
+            Name::Add(add) => Command::AddName {
+                person: tree.resolve_person(&add.person_id)?,
+                name: PersonName {
+                    given: add.given,
+                    surname: add.surname,
+                    prefix: add.prefix,
+                    suffix: add.suffix,
+                    name_type: add.name_type,
+                    valid_from: add.from,
+                    valid_until: add.until,
+                    primary: add.primary,
+                },
+            },
+            Name::Update(update) => {
+                let person = tree.resolve_person(&update.person_id)?;
+                let old = tree
+                    .get_persons()
+                    .iter()
+                    .find(|p| p.id == person)
+                    .and_then(|p| p.names.get(update.index))
+                    .ok_or(LibError::Input(InputError::InvalidNameIndex))?;
+                // an empty value removes the field
+                let field = |new: Option<String>, old: &Option<String>| match new {
+                    Some(new) => Some(new).filter(|new| !new.is_empty()),
+                    None => old.clone(),
+                };
+                let name = PersonName {
+                    given: field(update.given, &old.given),
+                    surname: field(update.surname, &old.surname),
+                    prefix: field(update.prefix, &old.prefix),
+                    suffix: field(update.suffix, &old.suffix),
+                    name_type: update.name_type.unwrap_or(old.name_type),
+                    valid_from: field(update.from, &old.valid_from),
+                    valid_until: field(update.until, &old.valid_until),
+                    primary: update.primary || old.primary,
+                };
+                Command::UpdateName {
+                    person,
+                    index: update.index,
+                    name,
+                }
+            }
+            Name::Remove(remove) => Command::RemoveName {
+                person: tree.resolve_person(&remove.person_id)?,
+                index: remove.index,
+            },
+            Name::Primary(primary) => Command::SetPrimaryName {
+                person: tree.resolve_person(&primary.person_id)?,
+                index: primary.index,
+            },
+        },
+        _ => return Err("The action does not change the tree".into()),
+    };
+    Ok(vec![Applied::apply(tree, command)?])
+}
+
+/// Human-readable message for an applied command.
+fn describe(command: &Command, result: &CommandResult) -> String {
+    match (command, result) {
+        (Command::AddParent { .. }, CommandResult::ParentAndRelationship(pid, rid)) => format!(
+            "Added parent as \"{}\" and child of relationship \"{}\"",
+            pid, rid
+        ),
+        (Command::AddChild { .. }, CommandResult::Person(pid)) => {
+            format!("Added child as \"{}\"", pid)
+        }
+        (Command::AddNewRelationship { .. }, CommandResult::Relationship(rid)) => {
+            format!("Added new relationship \"{}\"", rid)
+        }
+        (Command::AddRelationshipWithPartner { .. }, CommandResult::Relationship(rid)) => {
+            format!("Added relationship \"{}\"", rid)
+        }
+        (Command::RemovePerson { person }, _) => format!("Removed person \"{}\"", person),
+        (Command::MergePerson { person1, person2 }, _) => {
+            format!("Merged \"{}\" and \"{}\"", person1, person2)
+        }
+        (Command::InsertInfo { person, key, value }, _) => {
+            format!("Inserted \"{}\": \"{}\" to \"{}\"", key, value, person)
+        }
+        (Command::RemoveInfo { person, key }, CommandResult::Info(value)) => {
+            format!("Removed \"{}\": \"{}\" from \"{}\"", key, value, person)
+        }
+        (Command::AddName { person, name }, CommandResult::Index(index)) => format!(
+            "Added name \"{}\" with index {} to \"{}\"",
+            name, index, person
+        ),
+        (
+            Command::UpdateName {
+                person,
+                index,
+                name,
+            },
+            _,
+        ) => {
+            format!("Updated name {} of \"{}\" to \"{}\"", index, person, name)
+        }
+        (Command::RemoveName { person, .. }, CommandResult::Name(name)) => {
+            format!("Removed name \"{}\" from \"{}\"", name, person)
+        }
+        (Command::SetPrimaryName { person, index }, _) => {
+            format!("Set name {} as primary name of \"{}\"", index, person)
+        }
+        (Command::AddPlace { .. }, CommandResult::Place(place)) => {
+            format!("Added place \"{}\"", place)
+        }
+        (Command::SetPlaceCoordinates { place, .. }, _) => {
+            format!("Set coordinates of place \"{}\"", place)
+        }
+        (Command::AddPlaceName { place, name }, _) => {
+            format!("Added name \"{}\" to place \"{}\"", name.name, place)
+        }
+        (Command::RemovePlace { place }, _) => format!("Removed place \"{}\"", place),
+        (Command::SetPlace { person, key, place }, _) => {
+            format!("Set \"{}\" of \"{}\" to place \"{}\"", key, person, place)
+        }
+        (Command::NormalizePlaces, CommandResult::PlaceMerges(merges)) => merges
+            .iter()
+            .map(|merge| {
+                format!(
+                    "Merged place \"{}\" into \"{}\"\n",
+                    merge.merged, merge.into
+                )
+            })
+            .chain(std::iter::once(format!(
+                "Normalized places, merged {} duplicates",
+                merges.len()
+            )))
+            .collect(),
+        (command, result) => format!("{:?}: {:?}", command.operation(), result),
+    }
+}
+
+fn export(content: String, output: Option<String>, printer: Printer) -> Result<(), Box<dyn Error>> {
+    match (output, printer.0) {
+        (Some(path), _) => {
+            fs::write(&path, content)?;
+            printer.print(&json!({ "output": path }), String::new)
+        }
+        (None, OutputFormat::Text) => {
+            print!("{}", content);
+            Ok(())
+        }
+        (None, OutputFormat::Json) => printer.print(&json!({ "content": content }), String::new),
+    }
+}
+
+fn load<P: AsRef<Path>>(path: P) -> Result<FamilyTree, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(FamilyTree::read_from(reader)?)
+}
+
+fn print_diff(diff: Diff, printer: Printer) -> Result<(), Box<dyn Error>> {
+    let old = load(diff.old)?;
+    let new = load(diff.new)?;
+    let tree_diff = old.diff(&new);
+    if printer.0 == OutputFormat::Json {
+        return printer.print(&tree_diff, String::new);
+    }
+    let name = |pid: &PersonId| {
+        let person = new
+            .get_persons()
+            .iter()
+            .chain(old.get_persons())
+            .find(|person| person.id == *pid);
+        match person.and_then(|person| person.display_name()) {
+            Some(name) => format!("{} ({})", pid, name),
+            None => pid.to_string(),
+        }
+    };
+    let parents = |parents: [Option<PersonId>; 2]| {
+        parents
+            .iter()
+            .flatten()
+            .map(name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for pid in &tree_diff.added_persons {
+        println!("+ person {}", name(pid));
+    }
+    for pid in &tree_diff.removed_persons {
+        println!("- person {}", name(pid));
+    }
+    for change in &tree_diff.changed_info {
+        let value = |value: &Option<String>| match value {
+            Some(value) => format!("\"{}\"", value),
+            None => "none".to_string(),
+        };
+        println!(
+            "~ person {}: {} {} -> {}",
+            name(&change.person),
+            change.key,
+            value(&change.old),
+            value(&change.new)
+        );
+    }
+    for change in &tree_diff.changed_names {
+        let names = |names: &[PersonName]| {
+            names
+                .iter()
+                .map(|name| format!("\"{}\"", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        println!(
+            "~ person {}: names [{}] -> [{}]",
+            name(&change.person),
+            names(&change.old),
+            names(&change.new)
+        );
+    }
+    for rid in &tree_diff.added_relationships {
+        println!("+ relationship {}", rid);
+    }
+    for rid in &tree_diff.removed_relationships {
+        println!("- relationship {}", rid);
+    }
+    for change in &tree_diff.changed_parents {
+        println!(
+            "~ relationship {}: parents [{}] -> [{}]",
+            change.relationship,
+            parents(change.old),
+            parents(change.new)
+        );
+    }
+    for moved in &tree_diff.moved_children {
+        println!(
+            "~ person {}: moved from relationship {} to {}",
+            name(&moved.child),
+            moved.from,
+            moved.to
+        );
+    }
+    let place = |id: &PlaceId| {
+        let place = new
+            .get_places()
+            .iter()
+            .chain(old.get_places())
+            .find(|place| place.id == *id);
+        match place {
+            Some(place) => format!("{} ({})", id, place.name),
+            None => id.to_string(),
+        }
+    };
+    for id in &tree_diff.added_places {
+        println!("+ place {}", place(id));
+    }
+    for id in &tree_diff.removed_places {
+        println!("- place {}", place(id));
+    }
+    for change in &tree_diff.changed_places {
+        let (before, after) = (&change.old, &change.new);
+        let mut changes = Vec::new();
+        if before.name != after.name {
+            changes.push(format!("name \"{}\" -> \"{}\"", before.name, after.name));
+        }
+        if before.parent != after.parent {
+            let parent =
+                |parent: Option<PlaceId>| parent.map_or("none".to_string(), |id| place(&id));
+            changes.push(format!(
+                "parent {} -> {}",
+                parent(before.parent),
+                parent(after.parent)
+            ));
+        }
+        if before.coordinates != after.coordinates {
+            changes.push("coordinates".to_string());
+        }
+        if before.alternate_names != after.alternate_names {
+            changes.push("alternate names".to_string());
+        }
+        println!("~ place {}: {}", place(&change.place), changes.join(", "));
+    }
+    if tree_diff.is_empty() {
+        println!("No differences");
+    }
+    Ok(())
+}
+
+fn merge_files(files: MergeDriver, printer: Printer) -> Result<(), Box<dyn Error>> {
+    let base = load(&files.base)?;
+    let ours = load(&files.ours)?;
+    let theirs = load(&files.theirs)?;
+    let (tree, conflicts) = FamilyTree::merge_versions(&base, &ours, &theirs);
+    // git passes temporary files, which must not get backups or a change log
+    storage::write_atomic(Path::new(&files.ours), &tree.save_bytes()?)?;
+    if conflicts.is_empty() {
+        return printer.print(&json!({ "conflicts": [] }), String::new);
+    }
+    for conflict in &conflicts {
+        printer.warn(format_args!("Conflict: {}", conflict));
+    }
+    // the exit code makes git keep the file as conflicted
+    Err(LibError::from(MergeConflict::Unresolved(conflicts)).into())
+}
+
+/// List or restore backups of the tree file.
+fn backup(path: &Path, action: Backup, printer: Printer) -> Result<(), Box<dyn Error>> {
+    match action {
+        Backup::List => {
+            let backups = storage::backups(path)?;
+            let listed = backups
+                .iter()
+                .enumerate()
+                .map(|(index, backup)| {
+                    json!({
+                        "number": index + 1,
+                        "timestamp": backup.timestamp,
+                        "date_time": backup.date_time(),
+                        "path": backup.path,
+                    })
+                })
+                .collect::<Vec<_>>();
+            printer.print(&listed, || {
+                if backups.is_empty() {
+                    return "No backups".to_string();
+                }
+                backups
+                    .iter()
+                    .enumerate()
+                    .map(|(index, backup)| {
+                        format!(
+                            "{:>4}  {}  {}",
+                            index + 1,
+                            backup.date_time(),
+                            backup.timestamp
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        Backup::Restore(restore) => {
+            let backup = storage::find_backup(path, &restore.backup)?;
+            let tree = storage::restore(path, &backup)?;
+            let persons = tree.get_persons().len();
+            let result = json!({ "restored": backup.path, "persons": persons });
+            printer.print(&result, || {
+                format!(
+                    "Restored the version replaced at {} with {} persons",
+                    backup.date_time(),
+                    persons
+                )
+            })
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let args = Cli::parse();
+    let format = args.format;
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => output::report(format, err.as_ref()),
+    }
+}
+
+fn run(args: Cli) -> Result<(), Box<dyn Error>> {
+    let printer = Printer(args.format);
+    // comparing and merging files does not need a tree of its own
+    match args.action {
+        Some(Action::Diff(diff)) => return print_diff(diff, printer),
+        Some(Action::MergeDriver(files)) => return merge_files(files, printer),
+        _ => {}
+    }
+    let path = args.file.ok_or("A tree file is required")?;
+    // restoring must not load the tree, which may be damaged
+    if let Some(Action::Backup(action)) = args.action {
+        return backup(Path::new(&path), action, printer);
+    }
     let mut tree = if args.new {
         let tree = FamilyTree::new();
-        save(&args.file, &tree)?;
+        save(&path, &tree)?;
         tree
+    } else if let Some(Action::Import(Import::Persons(file) | Import::Text(file))) = &args.action {
+        // the imported tree replaces the file, which may not exist yet
+        if !file.force && Path::new(&path).exists() {
+            return Err(format!("\"{}\" exists, use --force to replace it", path).into());
+        }
+        FamilyTree::new()
     } else {
-        let json_str = fs::read_to_string(&args.file)?;
-        FamilyTree::try_from(&json_str)?
+        load(&path)?
     };
+    tree.set_author(args.author);
 
     if let Some(action) = args.action {
+        if action.is_mutation() {
+            let applied = mutate(&mut tree, action)?;
+            save(&path, &tree)?;
+            return print_applied(printer, &applied);
+        }
+        if action.is_query() {
+            return query(&tree, action, printer);
+        }
         match action {
-            Action::Add(add) => match add {
-                Add::Child(child) => {
-                    let child_id =
-                        tree.add_child(RelationshipId(u128::from_str_radix(&child.rel_id, 16)?))?;
-                    save(&args.file, &tree)?;
-                    println!("Added child as \"{}\"", child_id);
-                }
-                Add::Parent(parent) => {
-                    let result =
-                        tree.add_parent(RelationshipId(u128::from_str_radix(&parent.rel_id, 16)?))?;
-                    save(&args.file, &tree)?;
-                    println!(
-                        "Added parent as \"{}\" and child of relationship \"{}\"",
-                        result.0, result.1
-                    );
-                }
-                Add::NewRelationship(rel) => {
-                    let rel_id = tree.add_new_relationship(PersonId(u128::from_str_radix(
-                        &rel.person_id,
-                        16,
-                    )?))?;
-                    save(&args.file, &tree)?;
-                    println!("Added new relationship \"{}\"", rel_id);
-                }
-                Add::RelationshipWithPartner(rel) => {
-                    let rel_id = tree.add_relationship_with_partner(
-                        PersonId(u128::from_str_radix(&rel.person_id, 16)?),
-                        PersonId(u128::from_str_radix(&rel.partner_id, 16)?),
-                    )?;
-                    save(&args.file, &tree)?;
-                    println!("Added relationship \"{}\"", rel_id);
-                }
-            },
-            Action::Info(info) => match info {
-                Info::Insert(insert) => {
-                    tree.insert_info(
-                        PersonId(u128::from_str_radix(&insert.person_id, 16)?),
-                        insert.key.clone(),
-                        insert.value.clone(),
-                    )?;
-                    save(&args.file, &tree)?;
-                    println!(
-                        "Inserted \"{}\": \"{}\" to \"{}\"",
-                        insert.key, insert.value, insert.person_id
-                    );
+            Action::Add(_)
+            | Action::Info(_)
+            | Action::Place(_)
+            | Action::Name(_)
+            | Action::Remove(_)
+            | Action::Merge(_)
+            | Action::Search(_)
+            | Action::Show(_) => {
+                unreachable!("Handled as mutation or query")
+            }
+            Action::Shell => shell::run(&path, tree)?,
+            Action::Script(script) => {
+                let script = match script.input.as_deref() {
+                    None | Some("-") => io::read_to_string(io::stdin())?,
+                    Some(input) => fs::read_to_string(input)?,
+                };
+                let applied = script::run(&mut tree, &script)?;
+                save(&path, &tree)?;
+                print_applied(printer, &applied)?;
+            }
+            Action::Apply(batch) => {
+                let commands = command::read_batch(&fs::read_to_string(batch.input)?)?;
+                // either all commands are applied or the file stays unchanged
+                let results = tree.apply_all(commands.clone())?;
+                save(&path, &tree)?;
+                let applied = commands
+                    .into_iter()
+                    .zip(results)
+                    .map(|(command, result)| Applied { command, result })
+                    .collect::<Vec<_>>();
+                print_applied(printer, &applied)?;
+            }
+            Action::Convert(convert) => {
+                tree.set_format(convert.format);
+                save(&path, &tree)?;
+                printer.print(&json!({ "format": convert.format }), || {
+                    format!("Converted to {:?}", convert.format)
+                })?;
+            }
+            Action::Export(Export::Persons(file)) => {
+                export(spreadsheet::export_persons(&tree)?, file.output, printer)?;
+            }
+            Action::Export(Export::Relationships(file)) => {
+                export(
+                    spreadsheet::export_relationships(&tree)?,
+                    file.output,
+                    printer,
+                )?;
+            }
+            Action::Export(Export::Text(file)) => {
+                export(tree.to_text(), file.output, printer)?;
+            }
+            Action::Import(Import::Persons(file)) => {
+                let csv = fs::read_to_string(file.input)?;
+                let SheetImport { tree, issues } = spreadsheet::import_persons(&csv)?;
+                save(&path, &tree)?;
+                let persons = tree.get_persons().len();
+                for issue in &issues {
+                    printer.warn(format_args!(
+                        "Line {} (\"{}\"): {}",
+                        issue.line, issue.id, issue.kind
+                    ));
                 }
-                Info::Remove(remove) => {
-                    let value = tree.remove_info(
-                        PersonId(u128::from_str_radix(&remove.person_id, 16)?),
-                        &remove.key,
-                    )?;
-                    save(&args.file, &tree)?;
-                    println!(
-                        "Removed \"{}\": \"{}\" from \"{}\"",
-                        remove.key, value, remove.person_id
-                    );
+                let result = json!({ "persons": persons, "issues": issues });
+                printer.print(&result, || {
+                    format!("Imported {} persons, {} issues", persons, issues.len())
+                })?;
+            }
+            Action::Import(Import::Text(file)) => {
+                let tree = FamilyTree::from_text(&fs::read_to_string(file.input)?)?;
+                save(&path, &tree)?;
+                let persons = tree.get_persons().len();
+                printer.print(&json!({ "persons": persons }), || {
+                    format!("Imported {} persons", persons)
+                })?;
+            }
+            Action::Diff(_) | Action::MergeDriver(_) | Action::Backup(_) => {
+                unreachable!("Handled before loading the tree")
+            }
+            Action::Log(log) => {
+                let entries = changelog::read_file(&changelog::log_path(Path::new(&path)))?;
+                if let Some(snapshot_path) = log.replay {
+                    let mut snapshot = load(&snapshot_path)?;
+                    let replayed = entries
+                        .get(log.from.saturating_sub(1)..)
+                        .unwrap_or_default();
+                    snapshot.replay(replayed)?;
+                    save(&snapshot_path, &snapshot)?;
+                    let result = json!({ "replayed": replayed.len(), "snapshot": snapshot_path });
+                    printer.print(&result, || {
+                        format!(
+                            "Replayed {} changes onto \"{}\"",
+                            replayed.len(),
+                            snapshot_path
+                        )
+                    })?;
+                } else if printer.0 == OutputFormat::Json {
+                    printer.print(&entries, String::new)?;
+                } else {
+                    for (index, entry) in entries.iter().enumerate() {
+                        let persons = entry
+                            .affected_persons()
+                            .iter()
+                            .map(|pid| pid.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        println!(
+                            "{:>4}  {}  {}  {:?}  {}",
+                            index + 1,
+                            entry.date_time(),
+                            entry.author.as_deref().unwrap_or("-"),
+                            entry.operation,
+                            persons
+                        );
+                    }
                 }
-            },
-            Action::Show(show) => match show {
-                Show::Persons => println!("Persons: {:#?}", tree.get_persons()),
-                Show::Relationships => println!("Relationships: {:#?}", tree.get_relationships()),
-                Show::Layers => {
-                    let graph = Graph::new(tree.get_relationships()).cut();
-                    println!("Layers: {:#?}", graph.layers())
-                }
-                Show::PersonLayers => {
-                    let graph = Graph::new(tree.get_relationships()).cut();
-                    println!(
-                        "Person Layers: {:#?}",
-                        graph.person_layers(tree.get_relationships())
-                    )
-                }
-            },
+            }
         };
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_driver_without_backups() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("baumstamm-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let base = FamilyTree::new();
+        let rid = base.get_relationships()[0].id;
+        let mut ours = base.clone();
+        ours.add_parent(rid)?;
+        let mut theirs = base.clone();
+        theirs.add_child(rid)?;
+        for (name, tree) in [("base", &base), ("ours", &ours), ("theirs", &theirs)] {
+            fs::write(path(name), tree.save_bytes()?)?;
+        }
+
+        let files = MergeDriver {
+            base: path("base"),
+            ours: path("ours"),
+            theirs: path("theirs"),
+        };
+        let merged = merge_files(files, Printer(OutputFormat::Json));
+        let persons = load(path("ours"))?.get_persons().len();
+        let backups = storage::backup_dir(Path::new(&path("ours")));
+        let log = changelog::log_path(Path::new(&path("ours")));
+        fs::remove_dir_all(&dir)?;
+        merged?;
+        assert_eq!(3, persons);
+        assert!(!backups.exists());
+        assert!(!log.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Output of results and errors as text or JSON.
+//!
+//! Errors exit with a code per category, which stays stable for scripts:
+//!
+//! | Code | Category      |
+//! |------|---------------|
+//! | 1    | other         |
+//! | 2    | usage         |
+//! | 3    | io            |
+//! | 4    | format        |
+//! | 5    | input         |
+//! | 6    | consistency   |
+//! | 7    | schema        |
+//! | 8    | merge         |
+//! | 9    | display       |
+
+use baumstamm_lib::error::{ConsistencyError, Error as LibError};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::{error::Error, num::ParseIntError, process::ExitCode};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Messages for humans
+    #[default]
+    Text,
+    /// One JSON document per invocation, errors included
+    Json,
+}
+
+/// Prints results in the requested format.
+#[derive(Clone, Copy)]
+pub(crate) struct Printer(pub(crate) OutputFormat);
+
+impl Printer {
+    /// Print a result, the text is only built, if it is needed.
+    pub(crate) fn print<T: Serialize>(
+        &self,
+        value: &T,
+        text: impl FnOnce() -> String,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.0 {
+            OutputFormat::Text => {
+                let text = text();
+                if !text.is_empty() {
+                    println!("{}", text);
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+
+    /// Print a warning to stderr, as JSON the result carries it instead.
+    pub(crate) fn warn(&self, warning: impl std::fmt::Display) {
+        if self.0 == OutputFormat::Text {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+}
+
+/// The error and all of its sources.
+fn chain<'a>(err: &'a (dyn Error + 'static)) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    std::iter::successors(Some(err), |&err| err.source())
+}
+
+/// Category and exit code of an error.
+///
+/// The first known error in the chain of sources decides.
+fn category(err: &(dyn Error + 'static)) -> (&'static str, u8) {
+    chain(err)
+        .find_map(|err| {
+            if let Some(err) = err.downcast_ref::<LibError>() {
+                Some(match err {
+                    LibError::Io(_) => ("io", 3),
+                    LibError::Serialization(_)
+                    | LibError::Binary(_)
+                    | LibError::Csv(_)
+                    | LibError::Text(_)
+                    | LibError::Version(_) => ("format", 4),
+                    LibError::Input(_) => ("input", 5),
+                    LibError::Consistency(_) => ("consistency", 6),
+                    LibError::Schema(_) => ("schema", 7),
+                    LibError::MergeConflict(_) => ("merge", 8),
+                    LibError::Display(_) => ("display", 9),
+                })
+            } else if err.is::<std::io::Error>() {
+                Some(("io", 3))
+            } else if err.is::<ParseIntError>() {
+                Some(("input", 5))
+            } else if err.is::<clap::Error>() {
+                Some(("usage", 2))
+            } else if err.is::<ConsistencyError>() {
+                Some(("consistency", 6))
+            } else {
+                None
+            }
+        })
+        .unwrap_or(("other", 1))
+}
+
+/// Report an error and return the exit code of its category.
+///
+/// As JSON, the error is printed to stdout like any other result.
+pub(crate) fn report(format: OutputFormat, err: &(dyn Error + 'static)) -> ExitCode {
+    let (category, code) = category(err);
+    match format {
+        OutputFormat::Text => eprintln!("Error: {}", err),
+        OutputFormat::Json => {
+            let details = chain(err)
+                .find_map(|err| err.downcast_ref::<LibError>())
+                .and_then(|err| serde_json::to_value(err).ok());
+            let error = json!({
+                "error": {
+                    "category": category,
+                    "code": code,
+                    "message": err.to_string(),
+                    "details": details,
+                }
+            });
+            println!("{}", error);
+        }
+    }
+    ExitCode::from(code)
+}
@@ -0,0 +1,352 @@
+//! Drawing of ancestors and descendants as a tree in the terminal.
+
+use baumstamm_lib::{FamilyTree, Person, PersonId, Relationship};
+use clap::Args;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Args)]
+pub(crate) struct RenderOptions {
+    /// Number of generations to show
+    #[arg(long)]
+    depth: Option<usize>,
+    /// Show the ids next to the names
+    #[arg(long)]
+    ids: bool,
+    /// Draw with ASCII instead of Unicode characters
+    #[arg(long)]
+    ascii: bool,
+}
+
+/// A person in the drawn tree with the branches below.
+#[derive(Serialize)]
+pub(crate) struct Node {
+    /// `None` for an unknown partner.
+    id: Option<PersonId>,
+    name: Option<String>,
+    /// The node is a partner, whose branches are the children of the relationship.
+    #[serde(skip_serializing_if = "is_false")]
+    partner: bool,
+    /// The person is already shown elsewhere, so the branches are not repeated.
+    #[serde(skip_serializing_if = "is_false")]
+    repeated: bool,
+    /// Children, partners or parents depending on the direction of the tree.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    branches: Vec<Node>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+struct Glyphs {
+    branch: &'static str,
+    last: &'static str,
+    vertical: &'static str,
+    space: &'static str,
+    partner: &'static str,
+}
+
+const UNICODE: Glyphs = Glyphs {
+    branch: "├── ",
+    last: "└── ",
+    vertical: "│   ",
+    space: "    ",
+    partner: "⚭ ",
+};
+
+const ASCII: Glyphs = Glyphs {
+    branch: "|-- ",
+    last: "`-- ",
+    vertical: "|   ",
+    space: "    ",
+    partner: "+ ",
+};
+
+/// Builds trees of nodes, showing every person only once.
+pub(crate) struct Renderer<'a> {
+    tree: &'a FamilyTree,
+    persons: HashMap<PersonId, &'a Person>,
+    depth: Option<usize>,
+    partners: bool,
+    visited: HashSet<PersonId>,
+}
+
+impl<'a> Renderer<'a> {
+    pub(crate) fn new(tree: &'a FamilyTree, options: &RenderOptions, partners: bool) -> Self {
+        Self {
+            tree,
+            persons: tree
+                .get_persons()
+                .iter()
+                .map(|person| (person.id, person))
+                .collect(),
+            depth: options.depth,
+            partners,
+            visited: HashSet::new(),
+        }
+    }
+
+    fn node(&self, id: Option<PersonId>) -> Node {
+        Node {
+            id,
+            name: id
+                .and_then(|id| self.persons.get(&id))
+                .and_then(|person| person.display_name()),
+            partner: false,
+            repeated: false,
+            branches: Vec::new(),
+        }
+    }
+
+    fn own_relationships(&self, pid: PersonId) -> impl Iterator<Item = &'a Relationship> {
+        self.tree
+            .get_relationships()
+            .iter()
+            .filter(move |rel| rel.parents.contains(&Some(pid)))
+    }
+
+    fn parents(&self, pid: PersonId) -> Vec<PersonId> {
+        self.tree
+            .get_relationships()
+            .iter()
+            .filter(|rel| rel.children.contains(&pid))
+            .flat_map(|rel| rel.parents.iter().flatten().copied())
+            .collect()
+    }
+
+    /// Mark a person as shown, returning a cut node, if it already was or the depth is reached.
+    fn visit(&mut self, pid: PersonId, generation: usize) -> Result<Node, Node> {
+        let mut node = self.node(Some(pid));
+        if !self.visited.insert(pid) {
+            node.repeated = true;
+            return Err(node);
+        }
+        if self.depth.is_some_and(|depth| generation >= depth) {
+            return Err(node);
+        }
+        Ok(node)
+    }
+
+    pub(crate) fn descendants(&mut self, pid: PersonId) -> Node {
+        self.descendant(pid, 0)
+    }
+
+    fn descendant(&mut self, pid: PersonId, generation: usize) -> Node {
+        let mut node = match self.visit(pid, generation) {
+            Ok(node) => node,
+            Err(node) => return node,
+        };
+        for rel in self.own_relationships(pid).collect::<Vec<_>>() {
+            let children = rel
+                .children
+                .iter()
+                .map(|child| self.descendant(*child, generation + 1))
+                .collect::<Vec<_>>();
+            if self.partners {
+                let partner = rel.parents.iter().flatten().find(|p| **p != pid).copied();
+                if partner.is_none() && children.is_empty() {
+                    continue;
+                }
+                let mut partner_node = self.node(partner);
+                partner_node.partner = true;
+                partner_node.branches = children;
+                node.branches.push(partner_node);
+                self.visited.extend(partner);
+            } else {
+                node.branches.extend(children);
+            }
+        }
+        node
+    }
+
+    pub(crate) fn ancestors(&mut self, pid: PersonId) -> Node {
+        self.ancestor(pid, 0)
+    }
+
+    fn ancestor(&mut self, pid: PersonId, generation: usize) -> Node {
+        let mut node = match self.visit(pid, generation) {
+            Ok(node) => node,
+            Err(node) => return node,
+        };
+        node.branches = self
+            .parents(pid)
+            .into_iter()
+            .map(|parent| self.ancestor(parent, generation + 1))
+            .collect();
+        node
+    }
+
+    /// Descendants of all persons without parents.
+    ///
+    /// Persons without parents, whose partner has parents, are shown as partners instead.
+    pub(crate) fn forest(&mut self) -> Vec<Node> {
+        let mut roots: Vec<PersonId> = Vec::new();
+        for person in self.tree.sorted_persons() {
+            let partners = self
+                .own_relationships(person.id)
+                .flat_map(|rel| rel.parents.iter().flatten().copied())
+                .filter(|partner| *partner != person.id)
+                .collect::<Vec<_>>();
+            let is_root = self.parents(person.id).is_empty()
+                && partners
+                    .iter()
+                    .all(|partner| self.parents(*partner).is_empty() && !roots.contains(partner));
+            if is_root {
+                roots.push(person.id);
+            }
+        }
+        let mut nodes = Vec::new();
+        for root in roots {
+            if !self.visited.contains(&root) {
+                nodes.push(self.descendants(root));
+            }
+        }
+        nodes
+    }
+}
+
+/// Draw nodes as trees, separated by empty lines.
+pub(crate) fn draw(nodes: &[Node], options: &RenderOptions) -> String {
+    let glyphs = if options.ascii { &ASCII } else { &UNICODE };
+    nodes
+        .iter()
+        .map(|node| {
+            let mut lines = vec![label(node, options, glyphs)];
+            draw_branches(&node.branches, "", &mut lines, options, glyphs);
+            lines.join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn draw_branches(
+    nodes: &[Node],
+    prefix: &str,
+    lines: &mut Vec<String>,
+    options: &RenderOptions,
+    glyphs: &Glyphs,
+) {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index == nodes.len() - 1;
+        let (connector, indent) = if last {
+            (glyphs.last, glyphs.space)
+        } else {
+            (glyphs.branch, glyphs.vertical)
+        };
+        lines.push(format!(
+            "{}{}{}",
+            prefix,
+            connector,
+            label(node, options, glyphs)
+        ));
+        let prefix = format!("{}{}", prefix, indent);
+        draw_branches(&node.branches, &prefix, lines, options, glyphs);
+    }
+}
+
+fn label(node: &Node, options: &RenderOptions, glyphs: &Glyphs) -> String {
+    let mut label = String::new();
+    if node.partner {
+        label.push_str(glyphs.partner);
+    }
+    label.push_str(node.name.as_deref().unwrap_or("?"));
+    if let (true, Some(id)) = (options.ids, node.id) {
+        label.push_str(&format!(" [{}]", id));
+    }
+    if node.repeated {
+        label.push_str(" (see above)");
+    }
+    label
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use baumstamm_lib::name::Name;
+
+    fn options(depth: Option<usize>, ascii: bool) -> RenderOptions {
+        RenderOptions {
+            depth,
+            ids: false,
+            ascii,
+        }
+    }
+
+    fn name(tree: &mut FamilyTree, pid: PersonId, given: &str) {
+        let name = Name {
+            given: Some(given.to_string()),
+            ..Default::default()
+        };
+        tree.add_name(pid, name).expect("Name must be valid");
+    }
+
+    /// Siblings C and D with the child E, so the grandparents A and B are ancestors twice.
+    fn family() -> (FamilyTree, [PersonId; 5]) {
+        let mut tree = FamilyTree::new();
+        let c = tree.get_persons()[0].id;
+        let root = tree.get_relationships()[0].id;
+        let (a, _) = tree.add_parent(root).expect("Parent must be added");
+        let (b, _) = tree.add_parent(root).expect("Parent must be added");
+        let d = tree.add_child(root).expect("Child must be added");
+        let rel = tree
+            .add_relationship_with_partner(c, d)
+            .expect("Relationship must be added");
+        let e = tree.add_child(rel).expect("Child must be added");
+        let pids = [a, b, c, d, e];
+        for (pid, given) in pids.iter().zip(["A", "B", "C", "D", "E"]) {
+            name(&mut tree, *pid, given);
+        }
+        (tree, pids)
+    }
+
+    #[test]
+    fn repeated_ancestors() {
+        let (tree, [.., e]) = family();
+        let options = options(None, true);
+        let node = Renderer::new(&tree, &options, false).ancestors(e);
+        let expected = "\
+E
+|-- C
+|   |-- A
+|   `-- B
+`-- D
+    |-- A (see above)
+    `-- B (see above)";
+        assert_eq!(expected, draw(&[node], &options));
+    }
+
+    #[test]
+    fn repeated_descendants() {
+        let (tree, [a, ..]) = family();
+        let options = options(None, false);
+        let node = Renderer::new(&tree, &options, true).descendants(a);
+        let expected = "\
+A
+└── ⚭ B
+    ├── C
+    │   └── ⚭ D
+    │       └── E
+    └── D (see above)";
+        assert_eq!(expected, draw(&[node], &options));
+    }
+
+    #[test]
+    fn depth() {
+        let (tree, [a, ..]) = family();
+        for (depth, expected) in [(0, "A"), (1, "A\n|-- C\n`-- D")] {
+            let options = options(Some(depth), true);
+            let node = Renderer::new(&tree, &options, false).descendants(a);
+            assert_eq!(expected, draw(&[node], &options));
+        }
+    }
+
+    #[test]
+    fn forest() {
+        let (tree, _) = family();
+        let options = options(Some(1), true);
+        let nodes = Renderer::new(&tree, &options, true).forest();
+        assert_eq!(1, nodes.len());
+        assert_eq!("A\n`-- + B\n    |-- C\n    `-- D", draw(&nodes, &options));
+    }
+}
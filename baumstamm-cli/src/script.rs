@@ -0,0 +1,306 @@
+//! Scripts of actions, which are run as one transaction.
+//!
+//! Every line is an action with the same syntax as on the command line, e.g. `add child 1A2B`.
+//! Results can be bound to variables with `$child = add child $rel` and used in later lines.
+//! Actions returning two ids bind both, e.g. `$parent $rel = add parent $root`.
+//! Words are separated by whitespace and may be quoted with `"` or `'`,
+//! lines starting with `#` are comments.
+
+use crate::{mutate, Action, Applied};
+use baumstamm_lib::{command::CommandResult, FamilyTree};
+use clap::Parser;
+use std::{collections::HashMap, error::Error, fmt};
+
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct Statement {
+    #[command(subcommand)]
+    action: Action,
+}
+
+/// An error in a line of a script.
+#[derive(Debug)]
+struct LineError {
+    line: usize,
+    source: Box<dyn Error>,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.source)
+    }
+}
+
+impl Error for LineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Run all lines of the script, either all of them succeed or the tree stays unchanged.
+///
+/// Returns the applied commands.
+pub(crate) fn run(tree: &mut FamilyTree, script: &str) -> Result<Vec<Applied>, Box<dyn Error>> {
+    let mut variables = HashMap::new();
+    tree.transaction(|tree| {
+        let mut applied = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_applied =
+                run_line(tree, line, &mut variables).map_err(|source| LineError {
+                    line: index + 1,
+                    source,
+                })?;
+            applied.extend(line_applied);
+        }
+        Ok(applied)
+    })
+}
+
+fn run_line(
+    tree: &mut FamilyTree,
+    line: &str,
+    variables: &mut HashMap<String, String>,
+) -> Result<Vec<Applied>, Box<dyn Error>> {
+    let (targets, action) = parse(line, variables)?;
+    if !action.is_mutation() {
+        return Err("Only actions changing the tree are allowed in scripts".into());
+    }
+    let applied = mutate(tree, action)?;
+    bind(targets, &applied, variables)?;
+    Ok(applied)
+}
+
+/// Parse a line into the variables to bind and the action with substituted variables.
+pub(crate) fn parse(
+    line: &str,
+    variables: &HashMap<String, String>,
+) -> Result<(Vec<String>, Action), Box<dyn Error>> {
+    let (targets, statement) = match line.split_once('=') {
+        Some((targets, statement)) if targets.trim_start().starts_with('$') => {
+            let targets = targets
+                .split_whitespace()
+                .map(|target| {
+                    target
+                        .strip_prefix('$')
+                        .filter(|name| is_name(name))
+                        .map(str::to_string)
+                        .ok_or_else(|| format!("Invalid variable \"{}\"", target))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            (targets, statement)
+        }
+        _ => (Vec::new(), line),
+    };
+    let words = split(statement)?
+        .into_iter()
+        .map(|(word, quoted)| match word.strip_prefix('$') {
+            Some(name) if !quoted => variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown variable \"${}\"", name)),
+            _ => Ok(word),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let action = Statement::try_parse_from(words)?.action;
+    Ok((targets, action))
+}
+
+/// Bind the values of the first result of an action to the variables.
+pub(crate) fn bind(
+    targets: Vec<String>,
+    applied: &[Applied],
+    variables: &mut HashMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let values = applied
+        .first()
+        .map(|applied| values(&applied.result))
+        .unwrap_or_default();
+    if targets.len() > values.len() {
+        return Err(format!("The action returns {} values", values.len()).into());
+    }
+    variables.extend(targets.into_iter().zip(values));
+    Ok(())
+}
+
+/// Values of a result, which can be bound to variables.
+fn values(result: &CommandResult) -> Vec<String> {
+    match result {
+        CommandResult::None | CommandResult::PlaceMerges(_) => Vec::new(),
+        CommandResult::Person(pid) => vec![pid.to_string()],
+        CommandResult::Relationship(rid) => vec![rid.to_string()],
+        CommandResult::ParentAndRelationship(pid, rid) => vec![pid.to_string(), rid.to_string()],
+        CommandResult::Info(value) => vec![value.clone()],
+        CommandResult::Index(index) => vec![index.to_string()],
+        CommandResult::Name(name) => vec![name.to_string()],
+        CommandResult::Place(place_id) => vec![place_id.to_string()],
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Split a line into words, which are marked as quoted, if they were.
+fn split(line: &str) -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some(next) if next == c => break,
+                    Some(next) => word.push(next),
+                    None => return Err("Unterminated quote".into()),
+                }
+            }
+            words.push((word, true));
+        } else {
+            let mut word = String::new();
+            while let Some(next) = chars.next_if(|next| !next.is_whitespace()) {
+                word.push(next);
+            }
+            words.push((word, false));
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Info, InsertInfo};
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn split_words() -> Result<(), Box<dyn Error>> {
+        let words = split(r#"info insert  "Anna Müller" 'say "hi"' x"#)?;
+        assert_eq!(
+            vec![
+                ("info".to_string(), false),
+                ("insert".to_string(), false),
+                ("Anna Müller".to_string(), true),
+                ("say \"hi\"".to_string(), true),
+                ("x".to_string(), false),
+            ],
+            words
+        );
+        assert_eq!(
+            "Unterminated quote",
+            split("info insert \"open")
+                .expect_err("Must fail")
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_quoted_values() -> Result<(), Box<dyn Error>> {
+        let variables = variables(&[("person", "1A")]);
+        let (targets, action) = parse(r#"info insert $person note "a = b""#, &variables)?;
+        assert!(targets.is_empty());
+        let Action::Info(Info::Insert(InsertInfo {
+            person_id,
+            key,
+            value,
+        })) = action
+        else {
+            panic!("Must be an info insert");
+        };
+        assert_eq!(("1A", "note", "a = b"), (&*person_id, &*key, &*value));
+
+        // quoted words are not substituted
+        let (targets, action) = parse(r#"$old = info insert "$person" note x=y"#, &variables)?;
+        assert_eq!(vec!["old".to_string()], targets);
+        let Action::Info(Info::Insert(insert)) = action else {
+            panic!("Must be an info insert");
+        };
+        assert_eq!(("$person", "x=y"), (&*insert.person_id, &*insert.value));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let variables = variables(&[]);
+        let error = |line| {
+            parse(line, &variables)
+                .err()
+                .expect("Must fail")
+                .to_string()
+        };
+        assert_eq!("Unknown variable \"$rel\"", error("add child $rel"));
+        assert_eq!("Invalid variable \"$a-b\"", error("$a-b = add child 1"));
+        assert_eq!("Invalid variable \"$\"", error("$ = add child 1"));
+        assert!(parse("add sibling 1", &variables).is_err());
+    }
+
+    #[test]
+    fn bind_values() -> Result<(), Box<dyn Error>> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id.to_string();
+        let applied = run(&mut tree, &format!("add parent {}", root))?;
+        let mut variables = HashMap::new();
+        let targets = vec!["parent".to_string(), "rel".to_string()];
+        bind(targets, &applied, &mut variables)?;
+        assert_eq!(2, variables.len());
+        assert!(variables["rel"] != root);
+
+        let targets = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let err = bind(targets, &applied, &mut variables).expect_err("Must fail");
+        assert_eq!("The action returns 2 values", err.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn run_script() -> Result<(), Box<dyn Error>> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let script = format!(
+            "# a new generation\n\
+            $parent $rel = add parent {}\n\
+            \n\
+            info insert $parent @firstName \"Anna = Annie\"\n\
+            $child = add child $root_child\n",
+            root
+        );
+        let err = run(&mut tree, &script).err().expect("Must fail");
+        assert_eq!("Line 5: Unknown variable \"$root_child\"", err.to_string());
+        // the first lines are rolled back
+        assert_eq!(1, tree.get_persons().len());
+        assert!(tree.log().is_empty());
+
+        let script = script.replace("$root_child", "$parent");
+        let applied = run(&mut tree, &script)?;
+        assert_eq!(3, applied.len());
+        assert_eq!(3, tree.get_persons().len());
+        // the child is added to the only relationship of the parent
+        let anna = tree.resolve_person("Anna = Annie")?;
+        let root = tree.get_relationships().iter().find(|rel| rel.id == root);
+        assert_eq!(Some(anna), root.and_then(|rel| rel.parents[0]));
+        assert_eq!(Some(2), root.map(|rel| rel.children.len()));
+        Ok(())
+    }
+
+    #[test]
+    fn only_mutations() {
+        let mut tree = FamilyTree::new();
+        let err = run(&mut tree, "info keys").err().expect("Must fail");
+        assert_eq!(
+            "Line 1: Only actions changing the tree are allowed in scripts",
+            err.to_string()
+        );
+    }
+}
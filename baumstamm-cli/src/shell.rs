@@ -0,0 +1,351 @@
+//! Interactive shell, which keeps the tree loaded between actions.
+//!
+//! Lines have the same syntax as scripts, so results can be bound to variables.
+//! Changes are kept in memory until they are saved and can be undone until then.
+
+use crate::{
+    describe, label, mutate,
+    output::{OutputFormat, Printer},
+    query, save, script,
+};
+use baumstamm_lib::{FamilyTree, PersonId};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use std::{collections::HashMap, error::Error};
+
+const KEYWORDS: [&str; 12] = [
+    "add", "info", "name", "place", "remove", "merge", "search", "show", "save", "undo", "quit",
+    "help",
+];
+
+const HELP: &str = "\
+Actions have the same syntax as on the command line, e.g. \"add child <relationship id>\".
+Bind results to variables with \"$child = add child $rel\" and use them as \"$child\".
+Use \"<action> --help\" for details and tab to complete ids, names and variables.
+
+  save    Save the tree and its change log
+  undo    Revert the last change since saving
+  quit    Leave the shell, \"quit!\" discards unsaved changes";
+
+/// Completes actions, person ids and names, relationship ids and variables.
+struct ShellHelper {
+    /// Id and display name of all persons.
+    persons: Vec<(String, String)>,
+    relationships: Vec<String>,
+    variables: Vec<String>,
+}
+
+impl ShellHelper {
+    fn new(tree: &FamilyTree, variables: &HashMap<String, String>) -> Self {
+        Self {
+            persons: tree
+                .get_persons()
+                .iter()
+                .map(|person| {
+                    let name = person.display_name().unwrap_or_default();
+                    (person.id.to_string(), name)
+                })
+                .collect(),
+            relationships: tree
+                .get_relationships()
+                .iter()
+                .map(|rel| rel.id.to_string())
+                .collect(),
+            variables: variables.keys().map(|name| format!("${}", name)).collect(),
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + 1);
+        let word = &line[start..pos];
+        let pair = |display: String, replacement: &str| Pair {
+            display,
+            replacement: replacement.to_string(),
+        };
+        let candidates = if line[..start].trim().is_empty() {
+            KEYWORDS
+                .iter()
+                .filter(|keyword| keyword.starts_with(word))
+                .map(|keyword| pair(keyword.to_string(), keyword))
+                .collect()
+        } else if word.starts_with('$') {
+            self.variables
+                .iter()
+                .filter(|variable| variable.starts_with(word))
+                .map(|variable| pair(variable.clone(), variable))
+                .collect()
+        } else if word.is_empty() {
+            Vec::new()
+        } else {
+            let upper = word.to_uppercase();
+            let lower = word.to_lowercase();
+            let persons = self
+                .persons
+                .iter()
+                .filter(|(id, name)| id.starts_with(&upper) || name.to_lowercase().contains(&lower))
+                .map(|(id, name)| pair(format!("{}  {}", id, name), id));
+            let relationships = self
+                .relationships
+                .iter()
+                .filter(|id| id.starts_with(&upper))
+                .map(|id| pair(format!("{}  relationship", id), id));
+            persons.chain(relationships).collect()
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Run the shell until it is quit.
+pub(crate) fn run(path: &str, mut tree: FamilyTree) -> Result<(), Box<dyn Error>> {
+    let mut editor = Editor::<ShellHelper, DefaultHistory>::new()?;
+    let mut variables = HashMap::new();
+    println!("Type \"help\" for help");
+    loop {
+        editor.set_helper(Some(ShellHelper::new(&tree, &variables)));
+        let line = match editor.readline("baumstamm> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => {
+                if !tree.log().is_empty() {
+                    println!("Discarded unsaved changes");
+                }
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        match line {
+            "quit" | "exit" if !tree.log().is_empty() => {
+                println!("There are unsaved changes, use \"save\" or \"quit!\"")
+            }
+            "quit" | "exit" | "quit!" => break,
+            "help" => println!("{}", HELP),
+            "save" => match save(path, &tree) {
+                Ok(()) => {
+                    // the saved changes were appended to the log file
+                    tree.take_log();
+                    println!("Saved \"{}\"", path);
+                }
+                Err(err) => eprintln!("Error: {}", err),
+            },
+            "undo" => match tree.undo() {
+                Ok(Some(operation)) => println!("Reverted {:?}", operation),
+                Ok(None) => println!("Nothing to undo since the last save"),
+                Err(err) => eprintln!("Error: {}", err),
+            },
+            line => {
+                if let Err(err) = execute(&mut tree, line, &mut variables) {
+                    match err.downcast_ref::<clap::Error>() {
+                        // also help and usage
+                        Some(err) => err.print()?,
+                        None => eprintln!("Error: {}", err),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn execute(
+    tree: &mut FamilyTree,
+    line: &str,
+    variables: &mut HashMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let (targets, action) = script::parse(line, variables)?;
+    if action.is_mutation() {
+        let log_len = tree.log().len();
+        let applied = mutate(tree, action)?;
+        for applied in &applied {
+            println!("{}", describe(&applied.command, &applied.result));
+        }
+        script::bind(targets, &applied, variables)?;
+        let mut affected = Vec::new();
+        for pid in tree.log()[log_len..]
+            .iter()
+            .flat_map(|entry| entry.affected_persons())
+        {
+            if !affected.contains(&pid) {
+                affected.push(pid);
+            }
+        }
+        for pid in affected {
+            print_neighbourhood(tree, pid);
+        }
+        Ok(())
+    } else if action.is_query() {
+        if !targets.is_empty() {
+            return Err("Only actions changing the tree return values".into());
+        }
+        query(tree, action, Printer(OutputFormat::Text))
+    } else {
+        Err("The action is not available in the shell".into())
+    }
+}
+
+/// Print a person with their parents, partners and children.
+fn print_neighbourhood(tree: &FamilyTree, pid: PersonId) {
+    if !tree.get_persons().iter().any(|person| person.id == pid) {
+        return;
+    }
+    let rels = tree.get_relationships();
+    let parents = rels
+        .iter()
+        .filter(|rel| rel.children.contains(&pid))
+        .flat_map(|rel| rel.parents.iter().flatten().copied())
+        .collect::<Vec<_>>();
+    let own_rels = rels
+        .iter()
+        .filter(|rel| rel.parents.contains(&Some(pid)))
+        .collect::<Vec<_>>();
+    let partners = own_rels
+        .iter()
+        .flat_map(|rel| rel.parents.iter().flatten().copied())
+        .filter(|partner| *partner != pid)
+        .collect::<Vec<_>>();
+    let children = own_rels
+        .iter()
+        .flat_map(|rel| rel.children.iter().copied())
+        .collect::<Vec<_>>();
+    println!("{}", label(tree, pid));
+    for (title, pids) in [
+        ("parents", parents),
+        ("partners", partners),
+        ("children", children),
+    ] {
+        if !pids.is_empty() {
+            let labels = pids.iter().map(|pid| label(tree, *pid)).collect::<Vec<_>>();
+            println!("  {}: {}", title, labels.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use baumstamm_lib::name::NameType;
+    use rustyline::history::MemHistory;
+
+    fn complete(helper: &ShellHelper, line: &str) -> rustyline::Result<(usize, Vec<String>)> {
+        let history = MemHistory::new();
+        let (start, candidates) = helper.complete(line, line.len(), &Context::new(&history))?;
+        let replacements = candidates
+            .into_iter()
+            .map(|candidate| candidate.replacement)
+            .collect();
+        Ok((start, replacements))
+    }
+
+    #[test]
+    fn execute_lines() -> Result<(), Box<dyn Error>> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id.to_string();
+        let mut variables = HashMap::from([("root".to_string(), root.clone())]);
+        execute(&mut tree, "$parent $rel = add parent $root", &mut variables)?;
+        execute(&mut tree, "$child = add child $rel", &mut variables)?;
+        assert_eq!(3, tree.get_persons().len());
+        assert_eq!(4, variables.len());
+
+        execute(&mut tree, "name add $child --given Anna", &mut variables)?;
+        let child = tree
+            .get_persons()
+            .iter()
+            .find(|person| person.id.to_string() == variables["child"])
+            .expect("Child must exist");
+        assert_eq!(Some("Anna".to_string()), child.display_name());
+        let line = "name update $child 0 --surname Müller --type married";
+        execute(&mut tree, line, &mut variables)?;
+        let child = tree
+            .get_persons()
+            .iter()
+            .find(|person| person.id.to_string() == variables["child"])
+            .expect("Child must exist");
+        assert_eq!(Some("Anna Müller".to_string()), child.display_name());
+        assert_eq!(NameType::Married, child.names[0].name_type);
+
+        // queries neither change the tree nor bind values
+        let log_len = tree.log().len();
+        execute(&mut tree, "search Anna", &mut variables)?;
+        assert_eq!(log_len, tree.log().len());
+        let err =
+            execute(&mut tree, "$found = search Anna", &mut variables).expect_err("Must fail");
+        assert_eq!(
+            "Only actions changing the tree return values",
+            err.to_string()
+        );
+        let err = execute(&mut tree, "convert json", &mut variables).expect_err("Must fail");
+        assert_eq!("The action is not available in the shell", err.to_string());
+        assert_eq!(4, variables.len());
+        Ok(())
+    }
+
+    #[test]
+    fn complete_words() -> Result<(), Box<dyn Error>> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let (pid, rid) = tree.add_parent(root)?;
+        tree.add_name(
+            pid,
+            baumstamm_lib::name::Name {
+                given: Some("Anna".to_string()),
+                ..Default::default()
+            },
+        )?;
+        let variables = HashMap::from([("rel".to_string(), rid.to_string())]);
+        let helper = ShellHelper::new(&tree, &variables);
+
+        assert_eq!(
+            (
+                0,
+                vec!["search".to_string(), "show".to_string(), "save".to_string()]
+            ),
+            complete(&helper, "s")?
+        );
+        assert_eq!(
+            (10, vec!["$rel".to_string()]),
+            complete(&helper, "add child $r")?
+        );
+        assert_eq!((4, Vec::new()), complete(&helper, "add ")?);
+        assert_eq!(
+            (15, vec![pid.to_string()]),
+            complete(&helper, "show ancestors ann")?
+        );
+        let prefix = &rid.to_string()[..4];
+        let (start, candidates) = complete(&helper, &format!("add child {}", prefix))?;
+        assert_eq!(10, start);
+        assert!(candidates.contains(&rid.to_string()));
+        Ok(())
+    }
+}
@@ -101,10 +101,6 @@ pub fn get_person_indices(person_layers: &Grid<Pid>, row_length: usize) -> Grid<
 }
 
 const fn middle(a: usize, b: usize) -> usize {
-    let diff = if a <= b { b - a } else { a - b };
-    if diff % 2 == 0 {
-        diff / 2
-    } else {
-        (diff - 1) / 2
-    }
+    // rounds down for uneven differences
+    a.abs_diff(b) / 2
 }
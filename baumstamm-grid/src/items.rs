@@ -73,8 +73,7 @@ pub fn new_sibling_item(
     item_index: usize,
     mut rel_connections: Option<&mut Connections>,
     y_indices: &HashMap<u32, usize>,
-) -> GridItem
-{
+) -> GridItem {
     fn get_x_index(
         connection: u32,
         is_crossing: bool,
@@ -204,11 +203,7 @@ pub fn new_sibling_item(
                     y_index,
                 });
             } else if start < item_index && item_index < end {
-                if rel_indices
-                    .children
-                    .iter()
-                    .any(|index| *index == item_index)
-                {
+                if rel_indices.children.contains(&item_index) {
                     let x_index = get_x_index(
                         connection,
                         false,
@@ -0,0 +1,585 @@
+//! Change log of all mutations of a `FamilyTree`.
+//!
+//! Every entry stores the old and new state of the persons, relationships and places it changed,
+//! so it can be listed for auditing and replayed onto an older snapshot of the tree.
+//! Logs are stored as JSON lines, one entry per line, so new entries can be appended.
+
+use crate::{
+    error::{Error, InputError},
+    place::{Place, PlaceId},
+    Person, PersonId, Relationship, RelationshipId, TreeData,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    hash::Hash,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The mutation of a `FamilyTree`, which produced a log entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Operation {
+    AddParent,
+    AddChild,
+    AddNewRelationship,
+    AddRelationshipWithPartner,
+    RemovePerson,
+    MergePerson,
+    InsertInfo,
+    RemoveInfo,
+    AddName,
+    UpdateName,
+    RemoveName,
+    SetPrimaryName,
+    AddPlace,
+    SetPlaceCoordinates,
+    AddPlaceName,
+    RemovePlace,
+    SetPlace,
+    NormalizePlaces,
+}
+
+/// The state of an item before and after an operation.
+///
+/// `None` means, that the item did not exist (`old`) or was removed (`new`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Type)]
+pub enum Change {
+    Person {
+        id: PersonId,
+        old: Option<Person>,
+        new: Option<Person>,
+    },
+    Relationship {
+        id: RelationshipId,
+        old: Option<Relationship>,
+        new: Option<Relationship>,
+    },
+    Place {
+        id: PlaceId,
+        old: Option<Place>,
+        new: Option<Place>,
+    },
+}
+
+/// A recorded mutation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Type)]
+pub struct LogEntry {
+    pub operation: Operation,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    pub author: Option<String>,
+    pub changes: Vec<Change>,
+}
+
+impl LogEntry {
+    pub(crate) fn new(
+        operation: Operation,
+        author: Option<String>,
+        old: &TreeData,
+        new: &TreeData,
+    ) -> Self {
+        let persons = changes(&old.persons, &new.persons, |person| person.id)
+            .map(|(id, old, new)| Change::Person { id, old, new });
+        let relationships = changes(&old.relationships, &new.relationships, |rel| rel.id)
+            .map(|(id, old, new)| Change::Relationship { id, old, new });
+        let places = changes(&old.places, &new.places, |place| place.id)
+            .map(|(id, old, new)| Change::Place { id, old, new });
+        Self {
+            operation,
+            timestamp: now(),
+            author,
+            changes: persons.chain(relationships).chain(places).collect(),
+        }
+    }
+
+    /// Record the changes since the snapshot was taken.
+    pub(crate) fn since(
+        operation: Operation,
+        author: Option<String>,
+        snapshot: &Snapshot,
+        new: &TreeData,
+    ) -> Self {
+        let persons = snapshot
+            .persons
+            .changes(&new.persons, |person| person.id)
+            .map(|(id, old, new)| Change::Person { id, old, new });
+        let relationships = snapshot
+            .relationships
+            .changes(&new.relationships, |rel| rel.id)
+            .map(|(id, old, new)| Change::Relationship { id, old, new });
+        let places = snapshot
+            .places
+            .changes(&new.places, |place| place.id)
+            .map(|(id, old, new)| Change::Place { id, old, new });
+        Self {
+            operation,
+            timestamp: now(),
+            author,
+            changes: persons.chain(relationships).chain(places).collect(),
+        }
+    }
+
+    /// Ids of all persons, which were added, changed or removed.
+    pub fn affected_persons(&self) -> Vec<PersonId> {
+        self.changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Person { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The timestamp as date and time in UTC, like `2024-01-31 12:00:00`.
+    pub fn date_time(&self) -> String {
+        date_time(self.timestamp)
+    }
+
+    /// Apply the changes to the tree data.
+    ///
+    /// Fails, if the tree data does not match the old state of the changes.
+    pub(crate) fn apply(&self, tree_data: &mut TreeData) -> Result<(), Error> {
+        for change in &self.changes {
+            match change {
+                Change::Person { id, old, new } => {
+                    apply(&mut tree_data.persons, *id, old, new, |person| person.id)
+                }
+                Change::Relationship { id, old, new } => {
+                    apply(&mut tree_data.relationships, *id, old, new, |rel| rel.id)
+                }
+                Change::Place { id, old, new } => {
+                    apply(&mut tree_data.places, *id, old, new, |place| place.id)
+                }
+            }?;
+        }
+        Ok(())
+    }
+
+    /// Restore the old state of the changes.
+    ///
+    /// Fails, if the tree data does not match the new state of the changes.
+    pub(crate) fn revert(&self, tree_data: &mut TreeData) -> Result<(), Error> {
+        for change in self.changes.iter().rev() {
+            match change {
+                Change::Person { id, old, new } => {
+                    apply(&mut tree_data.persons, *id, new, old, |person| person.id)
+                }
+                Change::Relationship { id, old, new } => {
+                    apply(&mut tree_data.relationships, *id, new, old, |rel| rel.id)
+                }
+                Change::Place { id, old, new } => {
+                    apply(&mut tree_data.places, *id, new, old, |place| place.id)
+                }
+            }?;
+        }
+        Ok(())
+    }
+}
+
+/// Ids of the items, which a mutation may change or remove.
+///
+/// New items need not be listed, as mutations append them.
+#[derive(Default)]
+pub(crate) struct Touched {
+    pub persons: Vec<PersonId>,
+    pub relationships: Vec<RelationshipId>,
+    pub places: Vec<PlaceId>,
+}
+
+/// Old state of the items touched by a mutation,
+/// so its changes can be recorded without copying the whole tree.
+pub(crate) struct Snapshot {
+    persons: Items<Person, PersonId>,
+    relationships: Items<Relationship, RelationshipId>,
+    places: Items<Place, PlaceId>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(tree_data: &TreeData, touched: Touched) -> Self {
+        Self {
+            persons: Items::new(&tree_data.persons, touched.persons, |person| person.id),
+            relationships: Items::new(&tree_data.relationships, touched.relationships, |rel| {
+                rel.id
+            }),
+            places: Items::new(&tree_data.places, touched.places, |place| place.id),
+        }
+    }
+}
+
+/// Touched items of one kind and the number of all items.
+struct Items<T, K> {
+    old: Vec<(K, Option<T>)>,
+    len: usize,
+}
+
+impl<T, K> Items<T, K>
+where
+    T: Clone + PartialEq,
+    K: Copy + Eq + Hash,
+{
+    fn new(items: &[T], ids: Vec<K>, id: impl Fn(&T) -> K) -> Self {
+        let old = ids
+            .into_iter()
+            .unique()
+            .map(|key| (key, items.iter().find(|item| id(item) == key).cloned()))
+            .collect();
+        Self {
+            old,
+            len: items.len(),
+        }
+    }
+
+    /// Touched items, which differ now, followed by the appended items.
+    ///
+    /// Untouched items keep their position, so the items behind them are new.
+    fn changes(
+        &self,
+        new: &[T],
+        id: impl Fn(&T) -> K,
+    ) -> impl Iterator<Item = (K, Option<T>, Option<T>)> {
+        let mut removed = 0;
+        let mut changes = Vec::new();
+        for (key, old_item) in &self.old {
+            let new_item = new.iter().find(|item| id(item) == *key);
+            if old_item.is_some() && new_item.is_none() {
+                removed += 1;
+            }
+            if old_item.as_ref() != new_item {
+                changes.push((*key, old_item.clone(), new_item.cloned()));
+            }
+        }
+        let appended = new
+            .get(self.len.saturating_sub(removed)..)
+            .unwrap_or_default()
+            .iter()
+            .filter(|item| self.old.iter().all(|(key, _)| *key != id(item)))
+            .map(|item| (id(item), None, Some(item.clone())));
+        changes.extend(appended);
+        changes.into_iter()
+    }
+}
+
+/// Items, which differ between the old and the new version, matched by id.
+fn changes<T, K>(
+    old: &[T],
+    new: &[T],
+    id: impl Fn(&T) -> K,
+) -> impl Iterator<Item = (K, Option<T>, Option<T>)>
+where
+    T: Clone + PartialEq,
+    K: Copy + Eq + Hash,
+{
+    let old_items: HashMap<K, &T> = old.iter().map(|item| (id(item), item)).collect();
+    let new_items: HashMap<K, &T> = new.iter().map(|item| (id(item), item)).collect();
+    old.iter()
+        .chain(new)
+        .map(&id)
+        .unique()
+        .filter_map(|key| {
+            let old_item = old_items.get(&key).copied();
+            let new_item = new_items.get(&key).copied();
+            (old_item != new_item).then(|| (key, old_item.cloned(), new_item.cloned()))
+        })
+        .collect_vec()
+        .into_iter()
+}
+
+fn apply<T, K>(
+    items: &mut Vec<T>,
+    key: K,
+    old: &Option<T>,
+    new: &Option<T>,
+    id: impl Fn(&T) -> K,
+) -> Result<(), Error>
+where
+    T: Clone + PartialEq,
+    K: PartialEq,
+{
+    let position = items.iter().position(|item| id(item) == key);
+    if position.map(|position| &items[position]) != old.as_ref() {
+        return Err(InputError::LogMismatch.into());
+    }
+    match (position, new) {
+        (Some(position), Some(new)) => items[position] = new.clone(),
+        (Some(position), None) => {
+            items.remove(position);
+        }
+        (None, Some(new)) => items.push(new.clone()),
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Read a log stored as JSON lines.
+pub fn read(log: &str) -> Result<Vec<LogEntry>, Error> {
+    log.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Write entries as JSON lines, which can be appended to a stored log.
+pub fn write(entries: &[LogEntry]) -> Result<String, Error> {
+    let mut log = String::new();
+    for entry in entries {
+        log.push_str(&serde_json::to_string(entry)?);
+        log.push('\n');
+    }
+    Ok(log)
+}
+
+/// Path of the log stored alongside a tree file, which is the tree path with `.log` appended.
+pub fn log_path(tree_path: &Path) -> PathBuf {
+    let mut path = tree_path.as_os_str().to_owned();
+    path.push(".log");
+    PathBuf::from(path)
+}
+
+/// Append entries to a stored log, which is created if necessary.
+pub fn append_to_file(path: &Path, entries: &[LogEntry]) -> Result<(), Error> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(write(entries)?.as_bytes())?;
+    Ok(())
+}
+
+/// Read the log stored at the path, which may not exist yet.
+pub fn read_file(path: &Path) -> Result<Vec<LogEntry>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(log) => read(&log),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The system time is not available in the browser.
+#[cfg(target_arch = "wasm32")]
+fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Seconds since the UNIX epoch as date and time in UTC.
+pub(crate) fn date_time(timestamp: u64) -> String {
+    let seconds = timestamp % 86400;
+    let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Convert days since the UNIX epoch to a date of the proleptic Gregorian calendar.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        name::Name,
+        place::{AlternateName, Coordinates},
+        FamilyTree,
+    };
+
+    #[test]
+    fn dates() {
+        let entry = |timestamp| LogEntry {
+            operation: Operation::AddChild,
+            timestamp,
+            author: None,
+            changes: Vec::new(),
+        };
+        assert_eq!("1970-01-01 00:00:00", entry(0).date_time());
+        assert_eq!("2000-02-29 23:59:59", entry(951868799).date_time());
+        assert_eq!("2024-12-31 12:30:00", entry(1735648200).date_time());
+    }
+
+    #[test]
+    fn record_and_replay() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let snapshot = FamilyTree::try_from(&tree.save()?)?;
+        tree.set_author(Some("Anna".to_string()));
+        let root = tree.get_relationships()[0].id;
+        let (parent, _) = tree.add_parent(root)?;
+        tree.insert_info(parent, "@firstName".to_string(), "Bert".to_string())?;
+        tree.insert_info(parent, "@firstName".to_string(), "Berta".to_string())?;
+        assert!(tree.add_child(RelationshipId(0)).is_err());
+
+        let log = tree.log();
+        let operations = log.iter().map(|entry| entry.operation).collect_vec();
+        assert_eq!(
+            vec![
+                Operation::AddParent,
+                Operation::InsertInfo,
+                Operation::InsertInfo
+            ],
+            operations
+        );
+        assert_eq!(Some("Anna".to_string()), log[0].author);
+        assert_eq!(vec![parent], log[2].affected_persons());
+        let Change::Person { old, new, .. } = &log[2].changes[0] else {
+            panic!("Must be a person change");
+        };
+        let first_name = |person: &Option<Person>| {
+            person
+                .as_ref()
+                .and_then(|person| person.info.as_ref())
+                .map(|info| info["@firstName"].clone())
+        };
+        assert_eq!(Some("Bert".to_string()), first_name(old));
+        assert_eq!(Some("Berta".to_string()), first_name(new));
+
+        let entries = read(&write(log)?)?;
+        assert_eq!(log, entries);
+        let mut replayed = snapshot;
+        replayed.replay(&entries)?;
+        assert_eq!(tree.save()?, replayed.save()?);
+        // the entries do not match the new state
+        assert!(replayed.replay(&entries).is_err());
+        Ok(())
+    }
+
+    /// Compare the last entry, which only knows the touched items, with the whole tree.
+    fn assert_recorded(tree: &FamilyTree, old: &TreeData) -> Result<TreeData, Error> {
+        let new = crate::io::read(&tree.save()?)?;
+        let entry = tree.log().last().expect("Must be recorded");
+        let expected = LogEntry::new(entry.operation, None, old, &new).changes;
+        assert_eq!(expected.len(), entry.changes.len(), "{:?}", entry.operation);
+        for change in &expected {
+            assert!(entry.changes.contains(change), "{:?}", entry.operation);
+        }
+        Ok(new)
+    }
+
+    #[test]
+    fn touched_items() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let snapshot = FamilyTree::try_from(&tree.save()?)?;
+        let mut state = crate::io::read(&tree.save()?)?;
+        let root = tree.get_relationships()[0].id;
+        let first = tree.get_persons()[0].id;
+
+        let (parent, _) = tree.add_parent(root)?;
+        state = assert_recorded(&tree, &state)?;
+        let sibling = tree.add_child(root)?;
+        state = assert_recorded(&tree, &state)?;
+        let rel1 = tree.add_new_relationship(first)?;
+        state = assert_recorded(&tree, &state)?;
+        let child1 = tree.add_child(rel1)?;
+        state = assert_recorded(&tree, &state)?;
+        let rel2 = tree.add_new_relationship(sibling)?;
+        state = assert_recorded(&tree, &state)?;
+        let child2 = tree.add_child(rel2)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.insert_info(child1, "@firstName".to_string(), "Carl".to_string())?;
+        state = assert_recorded(&tree, &state)?;
+        let name = |given: &str| Name {
+            given: Some(given.to_string()),
+            ..Default::default()
+        };
+        tree.add_name(child2, name("Carla"))?;
+        state = assert_recorded(&tree, &state)?;
+        tree.add_name(child2, name("Karla"))?;
+        state = assert_recorded(&tree, &state)?;
+        tree.set_primary_name(child2, 1)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.remove_name(child2, 0)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.merge_person(child1, child2)?;
+        state = assert_recorded(&tree, &state)?;
+        let merged = tree
+            .log()
+            .last()
+            .expect("Must be recorded")
+            .affected_persons();
+        assert_eq!(3, merged.len());
+
+        let berlin = tree.add_place("Berlin".to_string(), None)?;
+        state = assert_recorded(&tree, &state)?;
+        let coordinates = Coordinates {
+            latitude: 52.52,
+            longitude: 13.405,
+        };
+        tree.set_place_coordinates(berlin, Some(coordinates))?;
+        state = assert_recorded(&tree, &state)?;
+        let alias = AlternateName {
+            name: "Cölln".to_string(),
+            historical: true,
+        };
+        tree.add_place_name(berlin, alias)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.set_place(parent, "@birthPlace", berlin)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.remove_info(parent, "@birthPlace")?;
+        state = assert_recorded(&tree, &state)?;
+        tree.remove_place(berlin)?;
+        state = assert_recorded(&tree, &state)?;
+        tree.add_relationship_with_partner(parent, sibling)?;
+        state = assert_recorded(&tree, &state)?;
+        let new_person = tree
+            .get_persons()
+            .iter()
+            .map(|person| person.id)
+            .find(|pid| ![first, parent, sibling].contains(pid))
+            .expect("Merged person must exist");
+        tree.remove_person(new_person)?;
+        assert_recorded(&tree, &state)?;
+
+        let mut replayed = snapshot;
+        replayed.replay(tree.log())?;
+        assert_eq!(tree.save()?, replayed.save()?);
+        Ok(())
+    }
+
+    #[test]
+    fn undo() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let saved = tree.save()?;
+        let root = tree.get_relationships()[0].id;
+        let (parent, _) = tree.add_parent(root)?;
+        let after_parent = tree.save()?;
+        tree.insert_info(parent, "@firstName".to_string(), "Bert".to_string())?;
+        tree.add_child(root)?;
+
+        assert_eq!(Some(Operation::AddChild), tree.undo()?);
+        assert_eq!(Some(Operation::InsertInfo), tree.undo()?);
+        assert_eq!(after_parent, tree.save()?);
+        assert_eq!(Some(Operation::AddParent), tree.undo()?);
+        assert_eq!(saved, tree.save()?);
+        assert_eq!(None, tree.undo()?);
+        assert!(tree.log().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,395 @@
+//! Serializable mutations of a `FamilyTree`.
+//!
+//! Front-ends build a `Command` and pass it to `FamilyTree::apply`, so all of them share one dispatch.
+//! Batches of commands are stored as JSON lines, one command per line.
+
+use crate::{
+    changelog::Operation,
+    error::Error,
+    name::Name,
+    place::{AlternateName, Coordinates, PlaceId, PlaceMerge},
+    FamilyTree, PersonId, RelationshipId,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// A mutation of a `FamilyTree` with its arguments.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Type)]
+pub enum Command {
+    AddParent {
+        relationship: RelationshipId,
+    },
+    AddChild {
+        relationship: RelationshipId,
+    },
+    AddNewRelationship {
+        person: PersonId,
+    },
+    AddRelationshipWithPartner {
+        person: PersonId,
+        partner: PersonId,
+    },
+    RemovePerson {
+        person: PersonId,
+    },
+    MergePerson {
+        person1: PersonId,
+        person2: PersonId,
+    },
+    InsertInfo {
+        person: PersonId,
+        key: String,
+        value: String,
+    },
+    RemoveInfo {
+        person: PersonId,
+        key: String,
+    },
+    AddName {
+        person: PersonId,
+        name: Name,
+    },
+    UpdateName {
+        person: PersonId,
+        index: usize,
+        name: Name,
+    },
+    RemoveName {
+        person: PersonId,
+        index: usize,
+    },
+    SetPrimaryName {
+        person: PersonId,
+        index: usize,
+    },
+    AddPlace {
+        name: String,
+        parent: Option<PlaceId>,
+    },
+    SetPlaceCoordinates {
+        place: PlaceId,
+        coordinates: Option<Coordinates>,
+    },
+    AddPlaceName {
+        place: PlaceId,
+        name: AlternateName,
+    },
+    RemovePlace {
+        place: PlaceId,
+    },
+    SetPlace {
+        person: PersonId,
+        key: String,
+        place: PlaceId,
+    },
+    NormalizePlaces,
+}
+
+/// Return value of an applied `Command`.
+///
+/// Serialized without a tag, so front-ends receive the plain value of the underlying method.
+#[derive(Clone, Debug, PartialEq, Serialize, Type)]
+#[serde(untagged)]
+pub enum CommandResult {
+    None,
+    Person(PersonId),
+    Relationship(RelationshipId),
+    /// The new parent and the relationship, the parent is a child of.
+    ParentAndRelationship(PersonId, RelationshipId),
+    /// The removed info value.
+    Info(String),
+    /// The index of the added name.
+    Index(usize),
+    /// The removed name.
+    Name(Name),
+    Place(PlaceId),
+    PlaceMerges(Vec<PlaceMerge>),
+}
+
+impl Command {
+    /// The operation recorded in the change log, when the command is applied.
+    pub fn operation(&self) -> Operation {
+        match self {
+            Self::AddParent { .. } => Operation::AddParent,
+            Self::AddChild { .. } => Operation::AddChild,
+            Self::AddNewRelationship { .. } => Operation::AddNewRelationship,
+            Self::AddRelationshipWithPartner { .. } => Operation::AddRelationshipWithPartner,
+            Self::RemovePerson { .. } => Operation::RemovePerson,
+            Self::MergePerson { .. } => Operation::MergePerson,
+            Self::InsertInfo { .. } => Operation::InsertInfo,
+            Self::RemoveInfo { .. } => Operation::RemoveInfo,
+            Self::AddName { .. } => Operation::AddName,
+            Self::UpdateName { .. } => Operation::UpdateName,
+            Self::RemoveName { .. } => Operation::RemoveName,
+            Self::SetPrimaryName { .. } => Operation::SetPrimaryName,
+            Self::AddPlace { .. } => Operation::AddPlace,
+            Self::SetPlaceCoordinates { .. } => Operation::SetPlaceCoordinates,
+            Self::AddPlaceName { .. } => Operation::AddPlaceName,
+            Self::RemovePlace { .. } => Operation::RemovePlace,
+            Self::SetPlace { .. } => Operation::SetPlace,
+            Self::NormalizePlaces => Operation::NormalizePlaces,
+        }
+    }
+}
+
+impl FamilyTree {
+    /// Apply a command by calling the matching method.
+    pub fn apply(&mut self, command: Command) -> Result<CommandResult, Error> {
+        let result = match command {
+            Command::AddParent { relationship } => {
+                let (person, relationship) = self.add_parent(relationship)?;
+                CommandResult::ParentAndRelationship(person, relationship)
+            }
+            Command::AddChild { relationship } => {
+                CommandResult::Person(self.add_child(relationship)?)
+            }
+            Command::AddNewRelationship { person } => {
+                CommandResult::Relationship(self.add_new_relationship(person)?)
+            }
+            Command::AddRelationshipWithPartner { person, partner } => {
+                CommandResult::Relationship(self.add_relationship_with_partner(person, partner)?)
+            }
+            Command::RemovePerson { person } => {
+                self.remove_person(person)?;
+                CommandResult::None
+            }
+            Command::MergePerson { person1, person2 } => {
+                self.merge_person(person1, person2)?;
+                CommandResult::None
+            }
+            Command::InsertInfo { person, key, value } => {
+                self.insert_info(person, key, value)?;
+                CommandResult::None
+            }
+            Command::RemoveInfo { person, key } => {
+                CommandResult::Info(self.remove_info(person, &key)?)
+            }
+            Command::AddName { person, name } => CommandResult::Index(self.add_name(person, name)?),
+            Command::UpdateName {
+                person,
+                index,
+                name,
+            } => {
+                self.update_name(person, index, name)?;
+                CommandResult::None
+            }
+            Command::RemoveName { person, index } => {
+                CommandResult::Name(self.remove_name(person, index)?)
+            }
+            Command::SetPrimaryName { person, index } => {
+                self.set_primary_name(person, index)?;
+                CommandResult::None
+            }
+            Command::AddPlace { name, parent } => {
+                CommandResult::Place(self.add_place(name, parent)?)
+            }
+            Command::SetPlaceCoordinates { place, coordinates } => {
+                self.set_place_coordinates(place, coordinates)?;
+                CommandResult::None
+            }
+            Command::AddPlaceName { place, name } => {
+                self.add_place_name(place, name)?;
+                CommandResult::None
+            }
+            Command::RemovePlace { place } => {
+                self.remove_place(place)?;
+                CommandResult::None
+            }
+            Command::SetPlace { person, key, place } => {
+                self.set_place(person, &key, place)?;
+                CommandResult::None
+            }
+            Command::NormalizePlaces => CommandResult::PlaceMerges(self.normalize_places()),
+        };
+        Ok(result)
+    }
+
+    /// Apply commands as one transaction.
+    ///
+    /// Either all commands are applied or the tree stays unchanged.
+    pub fn apply_all(&mut self, commands: Vec<Command>) -> Result<Vec<CommandResult>, Error> {
+        self.transaction(|tree| {
+            commands
+                .into_iter()
+                .map(|command| tree.apply(command))
+                .collect()
+        })
+    }
+}
+
+/// Read a batch of commands stored as JSON lines.
+///
+/// Empty lines and lines starting with `#` are skipped.
+pub fn read_batch(batch: &str) -> Result<Vec<Command>, Error> {
+    batch
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Write commands as JSON lines.
+pub fn write_batch(commands: &[Command]) -> Result<String, Error> {
+    let mut batch = String::new();
+    for command in commands {
+        batch.push_str(&serde_json::to_string(command)?);
+        batch.push('\n');
+    }
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::ConsistencyError, name::NameType, Person};
+
+    fn first_name(person: &Person) -> Option<&str> {
+        person
+            .info
+            .as_ref()
+            .and_then(|info| info.get("@firstName"))
+            .map(String::as_str)
+    }
+
+    #[test]
+    fn apply_commands() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let CommandResult::ParentAndRelationship(parent, _) =
+            tree.apply(Command::AddParent { relationship: root })?
+        else {
+            panic!("Must return the new parent");
+        };
+        let result = tree.apply(Command::InsertInfo {
+            person: parent,
+            key: "@firstName".to_string(),
+            value: "Anna".to_string(),
+        })?;
+        assert_eq!(CommandResult::None, result);
+        let name = Name {
+            given: Some("Anna".to_string()),
+            surname: None,
+            prefix: None,
+            suffix: None,
+            name_type: NameType::Birth,
+            valid_from: None,
+            valid_until: None,
+            primary: true,
+        };
+        let result = tree.apply(Command::AddName {
+            person: parent,
+            name,
+        })?;
+        assert_eq!(CommandResult::Index(0), result);
+        let result = tree.apply(Command::RemoveInfo {
+            person: parent,
+            key: "@firstName".to_string(),
+        })?;
+        assert_eq!(CommandResult::Info("Anna".to_string()), result);
+        assert!(tree
+            .apply(Command::AddChild {
+                relationship: RelationshipId(0)
+            })
+            .is_err());
+
+        let operations = tree
+            .log()
+            .iter()
+            .map(|entry| entry.operation)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Operation::AddParent,
+                Operation::InsertInfo,
+                Operation::AddName,
+                Operation::RemoveInfo
+            ],
+            operations
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn batch() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let person = tree.get_persons()[0].id;
+        let batch = format!(
+            "# name the root person\n\
+            {{\"InsertInfo\":{{\"person\":\"{person}\",\"key\":\"@firstName\",\"value\":\"Anna\"}}}}\n\
+            \n\
+            {{\"AddNewRelationship\":{{\"person\":\"{person}\"}}}}\n\
+            \"NormalizePlaces\"\n"
+        );
+        let commands = read_batch(&batch)?;
+        assert_eq!(3, commands.len());
+        assert_eq!(Operation::NormalizePlaces, commands[2].operation());
+        assert_eq!(commands, read_batch(&write_batch(&commands)?)?);
+        for command in commands {
+            tree.apply(command)?;
+        }
+        assert_eq!(Some("Anna"), first_name(&tree.get_persons()[0]));
+        assert_eq!(2, tree.get_relationships().len());
+        assert!(read_batch("{\"Unknown\":{}}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rollback() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let person = tree.get_persons()[0].id;
+        let saved = tree.save()?;
+        let commands = vec![
+            Command::AddNewRelationship { person },
+            Command::InsertInfo {
+                person,
+                key: "@firstName".to_string(),
+                value: "Anna".to_string(),
+            },
+            Command::RemovePerson {
+                person: PersonId(0),
+            },
+        ];
+        assert!(tree.apply_all(commands).is_err());
+        assert_eq!(saved, tree.save()?);
+        assert!(tree.log().is_empty());
+
+        let results = tree.apply_all(vec![
+            Command::AddNewRelationship { person },
+            Command::InsertInfo {
+                person,
+                key: "@firstName".to_string(),
+                value: "Anna".to_string(),
+            },
+        ])?;
+        assert!(matches!(
+            results.as_slice(),
+            [CommandResult::Relationship(_), CommandResult::None]
+        ));
+        assert_eq!(2, tree.log().len());
+        Ok(())
+    }
+
+    #[test]
+    fn deferred_consistency() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let (father, _) = tree.add_parent(root)?;
+        let (mother, _) = tree.add_parent(root)?;
+        let saved = tree.save()?;
+        let log_len = tree.log().len();
+        // the parents already share the root relationship
+        assert!(tree.add_relationship_with_partner(father, mother).is_err());
+        let result = tree.transaction(|tree| {
+            let rid = tree.add_relationship_with_partner(father, mother)?;
+            tree.add_child(rid)
+        });
+        assert!(matches!(
+            result,
+            Err(Error::Consistency(ConsistencyError::RelationshipExists))
+        ));
+        assert_eq!(saved, tree.save()?);
+        assert_eq!(log_len, tree.log().len());
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
-use crate::{error::ConsistencyError, extract_persons, Person, PersonId, Relationship, TreeData};
+use crate::{
+    error::ConsistencyError,
+    extract_persons,
+    place::{self, Place, PLACE_KEYS},
+    Person, PersonId, Relationship, TreeData,
+};
 use itertools::Itertools;
 use std::{collections::HashMap, iter::FromIterator};
 
 pub fn check(tree_data: &TreeData) -> Result<(), ConsistencyError> {
     check_relationships(&tree_data.relationships)?;
     check_persons(&tree_data.persons)?;
+    check_places(&tree_data.places)?;
+    check_place_references(&tree_data.persons, &tree_data.places)?;
 
     // turn into hash map for O(n) access
     let persons_hashmap: HashMap<PersonId, ()> = HashMap::from_iter(
@@ -27,7 +34,7 @@ pub fn check(tree_data: &TreeData) -> Result<(), ConsistencyError> {
     Ok(())
 }
 
-fn check_relationships(relationships: &Vec<Relationship>) -> Result<(), ConsistencyError> {
+fn check_relationships(relationships: &[Relationship]) -> Result<(), ConsistencyError> {
     if relationships.is_empty() {
         return Ok(());
     }
@@ -127,6 +134,58 @@ fn check_persons(persons: &[Person]) -> Result<(), ConsistencyError> {
     Ok(())
 }
 
+fn check_places(places: &[Place]) -> Result<(), ConsistencyError> {
+    if places.len() != places.iter().map(|place| place.id).unique().count() {
+        return Err(ConsistencyError::PlaceIdExists);
+    }
+
+    let find = |id| places.iter().find(|place| place.id == id);
+    if places
+        .iter()
+        .filter_map(|place| place.parent)
+        .any(|parent| find(parent).is_none())
+    {
+        return Err(ConsistencyError::UnknownParentPlace);
+    }
+
+    if places
+        .iter()
+        .filter_map(|place| place.coordinates)
+        .any(|coordinates| !coordinates.is_valid())
+    {
+        return Err(ConsistencyError::InvalidCoordinates);
+    }
+
+    // every chain of parents must end within the number of places
+    for place in places {
+        let mut current = place.parent;
+        let mut steps = 0;
+        while let Some(parent) = current {
+            steps += 1;
+            if steps > places.len() {
+                return Err(ConsistencyError::PlaceCycle);
+            }
+            current = find(parent).and_then(|place| place.parent);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_place_references(persons: &[Person], places: &[Place]) -> Result<(), ConsistencyError> {
+    if persons
+        .iter()
+        .filter_map(|person| person.info.as_ref())
+        .flat_map(|info| PLACE_KEYS.iter().filter_map(|key| info.get(*key)))
+        .filter_map(|value| place::reference(value))
+        .any(|id| !places.iter().any(|place| place.id == id))
+    {
+        return Err(ConsistencyError::UnknownPlace);
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -139,6 +198,7 @@ mod test {
     enum FileType {
         Relationships,
         Persons,
+        Places,
     }
 
     fn assert_err_for_file(expected_err_message: &str, file_name: &str, file_type: FileType) {
@@ -150,6 +210,9 @@ mod test {
             FileType::Persons => {
                 check_persons(&tree_data.persons).expect_err("Consistency check failed")
             }
+            FileType::Places => {
+                check_places(&tree_data.places).expect_err("Consistency check failed")
+            }
         };
         assert_eq!(expected_err_message, format!("{err}"))
     }
@@ -226,6 +289,44 @@ mod test {
         );
     }
 
+    #[test]
+    fn unknown_parent_place() {
+        assert_err_for_file(
+            "Parent place does not exist",
+            "test/consistency/unknown_parent_place.json",
+            FileType::Places,
+        );
+    }
+
+    #[test]
+    fn place_cycle() {
+        assert_err_for_file(
+            "Cycle in place hierarchy",
+            "test/consistency/place_cycle.json",
+            FileType::Places,
+        );
+    }
+
+    #[test]
+    fn invalid_coordinates() {
+        assert_err_for_file(
+            "Coordinates out of range",
+            "test/consistency/invalid_coordinates.json",
+            FileType::Places,
+        );
+    }
+
+    #[test]
+    fn unknown_place() {
+        let mut tree_data = read("test/consistency/unknown_place.json");
+        let err = check(&tree_data).expect_err("Consistency check failed");
+        assert_eq!("Referenced place does not exist", format!("{err}"));
+        // free text is no reference
+        let info = tree_data.persons[0].info.as_mut().expect("Info must exist");
+        info.remove("@residence");
+        assert!(check(&tree_data).is_ok());
+    }
+
     #[test]
     fn check_both() -> Result<(), ConsistencyError> {
         let tree_data = read("test/consistency/check_both.json");
@@ -0,0 +1,320 @@
+//! Structural comparison of two versions of a tree.
+
+use crate::{
+    name::Name,
+    place::{Place, PlaceId},
+    PersonId, RelationshipId, TreeData,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use specta::Type;
+use std::collections::{BTreeSet, HashMap};
+
+/// Differences between two versions of a tree, matched by id.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Type)]
+pub struct TreeDiff {
+    pub added_persons: Vec<PersonId>,
+    pub removed_persons: Vec<PersonId>,
+    pub changed_info: Vec<InfoChange>,
+    pub changed_names: Vec<NamesChange>,
+    pub added_relationships: Vec<RelationshipId>,
+    pub removed_relationships: Vec<RelationshipId>,
+    pub changed_parents: Vec<ParentsChange>,
+    pub moved_children: Vec<MovedChild>,
+    pub added_places: Vec<PlaceId>,
+    pub removed_places: Vec<PlaceId>,
+    pub changed_places: Vec<PlaceChange>,
+}
+
+/// An info key of a person, which was added, removed or changed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct InfoChange {
+    pub person: PersonId,
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A person present in both versions, whose names differ.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct NamesChange {
+    pub person: PersonId,
+    pub old: Vec<Name>,
+    pub new: Vec<Name>,
+}
+
+/// A relationship present in both versions, whose parents differ.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct ParentsChange {
+    pub relationship: RelationshipId,
+    pub old: [Option<PersonId>; 2],
+    pub new: [Option<PersonId>; 2],
+}
+
+/// A person present in both versions, who is child of another relationship.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct MovedChild {
+    pub child: PersonId,
+    pub from: RelationshipId,
+    pub to: RelationshipId,
+}
+
+/// A place present in both versions, whose name, parent, coordinates or alternate names differ.
+#[derive(Clone, Debug, PartialEq, Serialize, Type)]
+pub struct PlaceChange {
+    pub place: PlaceId,
+    pub old: Place,
+    pub new: Place,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Compare two versions of a tree.
+///
+/// Persons, relationships and places are matched by id,
+/// so the order within the tree data does not matter.
+/// Added persons are reported without their info and names.
+pub fn diff(old: &TreeData, new: &TreeData) -> TreeDiff {
+    let old_persons: HashMap<PersonId, _> = old.persons.iter().map(|p| (p.id, p)).collect();
+    let new_persons: HashMap<PersonId, _> = new.persons.iter().map(|p| (p.id, p)).collect();
+    let old_rels: HashMap<RelationshipId, _> =
+        old.relationships.iter().map(|rel| (rel.id, rel)).collect();
+    let new_rels: HashMap<RelationshipId, _> =
+        new.relationships.iter().map(|rel| (rel.id, rel)).collect();
+
+    let changed_info = new
+        .persons
+        .iter()
+        .filter_map(|person| Some((old_persons.get(&person.id)?, person)))
+        .flat_map(|(old_person, new_person)| {
+            let old_info = old_person.info.clone().unwrap_or_default();
+            let new_info = new_person.info.clone().unwrap_or_default();
+            let keys: BTreeSet<String> = old_info.keys().chain(new_info.keys()).cloned().collect();
+            keys.into_iter()
+                .filter(|key| old_info.get(key) != new_info.get(key))
+                .map(|key| InfoChange {
+                    person: new_person.id,
+                    old: old_info.get(&key).cloned(),
+                    new: new_info.get(&key).cloned(),
+                    key,
+                })
+                .collect_vec()
+        })
+        .collect();
+
+    let changed_names = new
+        .persons
+        .iter()
+        .filter_map(|person| Some((old_persons.get(&person.id)?, person)))
+        .filter(|(old_person, new_person)| old_person.names != new_person.names)
+        .map(|(old_person, new_person)| NamesChange {
+            person: new_person.id,
+            old: old_person.names.clone(),
+            new: new_person.names.clone(),
+        })
+        .collect();
+
+    let old_places: HashMap<PlaceId, _> = old.places.iter().map(|p| (p.id, p)).collect();
+    let new_places: HashMap<PlaceId, _> = new.places.iter().map(|p| (p.id, p)).collect();
+    let changed_places = new
+        .places
+        .iter()
+        .filter_map(|place| Some((*old_places.get(&place.id)?, place)))
+        .filter(|(old_place, new_place)| old_place != new_place)
+        .map(|(old_place, new_place)| PlaceChange {
+            place: new_place.id,
+            old: old_place.clone(),
+            new: new_place.clone(),
+        })
+        .collect();
+
+    let changed_parents = new
+        .relationships
+        .iter()
+        .filter_map(|rel| Some((old_rels.get(&rel.id)?, rel)))
+        .filter(|(old_rel, new_rel)| {
+            // the order of the parents has no meaning
+            old_rel.parents().into_iter().sorted().collect_vec()
+                != new_rel.parents().into_iter().sorted().collect_vec()
+        })
+        .map(|(old_rel, new_rel)| ParentsChange {
+            relationship: new_rel.id,
+            old: old_rel.parents,
+            new: new_rel.parents,
+        })
+        .collect();
+
+    let parent_rels = |tree_data: &TreeData| -> HashMap<PersonId, RelationshipId> {
+        tree_data
+            .relationships
+            .iter()
+            .flat_map(|rel| rel.children.iter().map(|child| (*child, rel.id)))
+            .collect()
+    };
+    let old_parent_rels = parent_rels(old);
+    let moved_children = new
+        .relationships
+        .iter()
+        .flat_map(|rel| rel.children.iter().map(move |child| (*child, rel.id)))
+        .filter_map(|(child, to)| {
+            let from = *old_parent_rels.get(&child)?;
+            (from != to).then_some(MovedChild { child, from, to })
+        })
+        .collect();
+
+    TreeDiff {
+        added_persons: new
+            .persons
+            .iter()
+            .map(|person| person.id)
+            .filter(|pid| !old_persons.contains_key(pid))
+            .collect(),
+        removed_persons: old
+            .persons
+            .iter()
+            .map(|person| person.id)
+            .filter(|pid| !new_persons.contains_key(pid))
+            .collect(),
+        changed_info,
+        changed_names,
+        added_relationships: new
+            .relationships
+            .iter()
+            .map(|rel| rel.id)
+            .filter(|rid| !old_rels.contains_key(rid))
+            .collect(),
+        removed_relationships: old
+            .relationships
+            .iter()
+            .map(|rel| rel.id)
+            .filter(|rid| !new_rels.contains_key(rid))
+            .collect(),
+        changed_parents,
+        moved_children,
+        added_places: new
+            .places
+            .iter()
+            .map(|place| place.id)
+            .filter(|id| !old_places.contains_key(id))
+            .collect(),
+        removed_places: old
+            .places
+            .iter()
+            .map(|place| place.id)
+            .filter(|id| !new_places.contains_key(id))
+            .collect(),
+        changed_places,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read(file_name: &str) -> TreeData {
+        let json_data = std::fs::read_to_string(file_name).expect("Cannot read test file");
+        crate::io::read(&json_data).expect("Cannot convert test file")
+    }
+
+    #[test]
+    fn no_changes() {
+        let tree_data = read("test/diff/old.json");
+        assert!(diff(&tree_data, &tree_data).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = read("test/diff/old.json");
+        let new = read("test/diff/new.json");
+        let expected = TreeDiff {
+            added_persons: vec![PersonId(4)],
+            removed_persons: vec![PersonId(2)],
+            changed_info: vec![
+                InfoChange {
+                    person: PersonId(1),
+                    key: "@firstName".to_string(),
+                    old: Some("Anna".to_string()),
+                    new: Some("Anne".to_string()),
+                },
+                InfoChange {
+                    person: PersonId(1),
+                    key: "@lastName".to_string(),
+                    old: None,
+                    new: Some("Schmidt".to_string()),
+                },
+                InfoChange {
+                    person: PersonId(3),
+                    key: "@occupation".to_string(),
+                    old: Some("Baker".to_string()),
+                    new: None,
+                },
+            ],
+            added_relationships: vec![RelationshipId(0xD)],
+            removed_relationships: vec![RelationshipId(0xB)],
+            changed_parents: vec![ParentsChange {
+                relationship: RelationshipId(0xC),
+                old: [Some(PersonId(1)), Some(PersonId(2))],
+                new: [Some(PersonId(1)), None],
+            }],
+            moved_children: vec![MovedChild {
+                child: PersonId(3),
+                from: RelationshipId(0xC),
+                to: RelationshipId(0xD),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(expected, diff(&old, &new));
+    }
+
+    #[test]
+    fn names() {
+        let old = read("test/diff/names_old.json");
+        let new = read("test/diff/names_new.json");
+        let expected = vec![
+            NamesChange {
+                person: PersonId(1),
+                old: old.persons[0].names.clone(),
+                new: new.persons[0].names.clone(),
+            },
+            NamesChange {
+                person: PersonId(3),
+                old: old.persons[2].names.clone(),
+                new: Vec::new(),
+            },
+        ];
+        let tree_diff = diff(&old, &new);
+        assert_eq!(expected, tree_diff.changed_names);
+        assert!(tree_diff.changed_info.is_empty());
+    }
+
+    #[test]
+    fn places() {
+        let old = read("test/diff/places_old.json");
+        let new = read("test/diff/places_new.json");
+        let expected = TreeDiff {
+            added_places: vec![PlaceId(4)],
+            removed_places: vec![PlaceId(3)],
+            changed_places: vec![PlaceChange {
+                place: PlaceId(2),
+                old: old.places[1].clone(),
+                new: new.places[1].clone(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(expected, diff(&old, &new));
+    }
+
+    #[test]
+    fn reordered() {
+        let old = read("test/diff/old.json");
+        let mut new = read("test/diff/old.json");
+        new.persons.reverse();
+        new.relationships.reverse();
+        new.relationships[0].parents.reverse();
+        assert!(diff(&old, &new).is_empty());
+    }
+}
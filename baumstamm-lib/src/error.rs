@@ -3,27 +3,64 @@ use thiserror::Error;
 
 #[derive(Debug, Error, Serialize)]
 pub enum Error {
-    #[serde(serialize_with = "serialize_serde_error")]
+    #[serde(serialize_with = "serialize_to_string")]
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Binary format error: {0}")]
+    Binary(String),
+    #[error("CSV error: {0}")]
+    Csv(String),
+    #[serde(serialize_with = "serialize_to_string")]
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
     #[error("Consistency error: {0}")]
     Consistency(#[from] ConsistencyError),
     #[error("Input error: {0}")]
     Input(#[from] InputError),
+    #[error("Schema error: {0}")]
+    Schema(#[from] SchemaError),
+    #[error("Version error: {0}")]
+    Version(#[from] VersionError),
+    #[error("Text format error: {0}")]
+    Text(#[from] TextError),
     #[error("Display error: {0}")]
     Display(#[from] DisplayError),
     #[error("Merge conflict: {0}")]
     MergeConflict(#[from] MergeConflict),
 }
 
-fn serialize_serde_error<S>(error: &serde_json::Error, serializer: S) -> Result<S::Ok, S::Error>
+fn serialize_to_string<E: ToString, S>(error: &E, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
     serializer.serialize_str(&error.to_string())
 }
 
-#[derive(Debug, Error, Serialize)]
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        Self::Csv(value.to_string())
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for Error {
+    fn from(value: ciborium::ser::Error<std::io::Error>) -> Self {
+        Self::Binary(value.to_string())
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for Error {
+    fn from(value: ciborium::de::Error<std::io::Error>) -> Self {
+        Self::Binary(value.to_string())
+    }
+}
+
+impl From<ciborium::value::Error> for Error {
+    fn from(value: ciborium::value::Error) -> Self {
+        Self::Binary(value.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize)]
 pub enum ConsistencyError {
     #[error("The number of persons differs")]
     DifferentNumberOfPersons,
@@ -47,6 +84,16 @@ pub enum ConsistencyError {
     IndirectCycle,
     #[error("Multiple persons with the same id")]
     PersonIdExists,
+    #[error("Multiple places with the same id")]
+    PlaceIdExists,
+    #[error("Parent place does not exist")]
+    UnknownParentPlace,
+    #[error("Cycle in place hierarchy")]
+    PlaceCycle,
+    #[error("Coordinates out of range")]
+    InvalidCoordinates,
+    #[error("Referenced place does not exist")]
+    UnknownPlace,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -65,6 +112,74 @@ pub enum InputError {
     CannotRemovePerson,
     #[error("Cannot merge with oneself")]
     SelfMerge,
+    #[error("Invalid place id")]
+    InvalidPlaceId,
+    #[error("Place is still referenced")]
+    PlaceInUse,
+    #[error("Name must have a given name or surname")]
+    EmptyName,
+    #[error("Invalid name index")]
+    InvalidNameIndex,
+    #[error("Log does not match the tree")]
+    LogMismatch,
+    #[error("Nothing matches \"{0}\"")]
+    UnknownReference(String),
+    #[error("\"{0}\" is ambiguous, candidates: {}", .1.join(", "))]
+    AmbiguousReference(String, Vec<String>),
+}
+
+#[derive(Debug, Error, Serialize)]
+pub enum SchemaError {
+    #[error("Unknown key with reserved prefix")]
+    UnknownKey,
+    #[error("Invalid date, expected YYYY, YYYY-MM or YYYY-MM-DD")]
+    InvalidDate,
+    #[error("Unknown place id")]
+    UnknownPlace,
+    #[error("Invalid value, expected one of: {0}")]
+    InvalidOption(String),
+    #[error("Invalid URL")]
+    InvalidUrl,
+}
+
+#[derive(Debug, Error, Serialize)]
+pub enum VersionError {
+    #[error("Unsupported file format version {0}")]
+    Unsupported(u64),
+    #[error("Version must be a non-negative integer")]
+    InvalidVersion,
+}
+
+#[derive(Debug, Error, Serialize)]
+#[error("line {line}, column {column}: {kind}")]
+pub struct TextError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: TextErrorKind,
+}
+
+#[derive(Debug, Error, Serialize)]
+pub enum TextErrorKind {
+    #[error("Unknown statement \"{0}\"")]
+    UnknownStatement(String),
+    #[error("Indented line outside of a block")]
+    OutsideBlock,
+    #[error("Unterminated string")]
+    UnterminatedString,
+    #[error("Invalid escape sequence")]
+    InvalidEscape,
+    #[error("Missing {0}")]
+    Missing(&'static str),
+    #[error("Unexpected \"{0}\"")]
+    Unexpected(String),
+    #[error("Label \"{0}\" is already defined")]
+    DuplicateLabel(String),
+    #[error("Unknown label \"{0}\"")]
+    UnknownLabel(String),
+    #[error("Invalid number \"{0}\"")]
+    InvalidNumber(String),
+    #[error("{0}")]
+    InvalidNameType(String),
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -75,6 +190,8 @@ pub enum MergeConflict {
     TooManyParents,
     #[error("Inconsistent tree: {0}")]
     InconsistentTree(ConsistencyError),
+    #[error("{} conflicting changes were resolved in favor of our side", .0.len())]
+    Unresolved(Vec<crate::merge::Conflict>),
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -181,7 +181,7 @@ impl Graph {
             .map(|level| level + 1)
     }
 
-    const fn walk_descendants(&self, rid: &Rid) -> DescendantWalker {
+    const fn walk_descendants(&self, rid: &Rid) -> DescendantWalker<'_> {
         DescendantWalker::new(self, *rid)
     }
 
@@ -578,13 +578,8 @@ impl CutGraph {
 
             fn add_to_layer(layer: &mut Vec<Pid>, index: usize, acc: Vec<Pid>) {
                 let len = acc.len();
-                let middle = if len % 2 == 0 {
-                    // even
-                    len / 2
-                } else {
-                    // uneven
-                    (len - 1) / 2
-                };
+                // rounds down for uneven lengths
+                let middle = len / 2;
                 let mut offset = 0;
                 acc.into_iter().enumerate().for_each(|(i, pid)| {
                     let insertion_index = offset + index;
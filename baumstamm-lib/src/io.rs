@@ -1,18 +1,213 @@
-use crate::{error::Error, TreeData};
+use crate::{
+    error::{Error, VersionError},
+    TreeData,
+};
+use ciborium::Value;
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use specta::Type;
+use std::io::{Read, Write};
+
+/// Version of the file format written by this library.
+///
+/// Files without a version field are treated as version 0.
+/// The version is only increased with a change of the structure, which old readers cannot handle,
+/// together with a migration from the previous version.
+pub const CURRENT_VERSION: u64 = 0;
+
+/// Upgrade of the tree data of one version to the next one.
+///
+/// The data is decoded from either format, so ids are hexadecimal text in JSON
+/// and integers in CBOR, and must keep their representation.
+type Migration = fn(&mut Value) -> Result<(), VersionError>;
+
+/// Migrations from every version to the next one, indexed by the old version.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [];
+
+/// Prefix of CBOR files, the "self-described CBOR" tag (RFC 8949, section 3.4.6).
+const CBOR_MAGIC: [u8; 3] = [0xD9, 0xD9, 0xF7];
+
+/// Formats for storing tree data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum Format {
+    /// Pretty-printed JSON with hexadecimal ids.
+    #[default]
+    Json,
+    /// Compact binary CBOR with ids stored as raw integers.
+    Cbor,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "cbor" => Ok(Self::Cbor),
+            _ => Err(format!("Unknown format \"{s}\"")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedTreeData<'a> {
+    version: u64,
+    #[serde(flatten)]
+    tree_data: &'a TreeData,
+}
+
+/// Version field of JSON files, which is missing in version 0.
+#[derive(Deserialize)]
+struct JsonHeader {
+    version: Option<serde_json::Value>,
+}
+
+/// Envelope of CBOR files.
+///
+/// Unlike JSON, the tree data is nested, so the version can be checked before it is decoded.
+#[derive(Serialize, Deserialize)]
+struct BinaryTreeData<T> {
+    version: u64,
+    tree_data: T,
+}
+
+/// Copy of the tree data in a stable order, which does not depend on the editing history.
+///
+/// Persons, relationships and places are sorted by id.
+/// Parents, children and names keep their order, as it may carry meaning.
+/// Info keys are always serialized in sorted order.
+pub fn canonicalize(tree_data: &TreeData) -> TreeData {
+    let mut canonical = tree_data.clone();
+    canonical.persons.sort_by_key(|person| person.id);
+    canonical.relationships.sort_by_key(|rel| rel.id.0);
+    canonical.places.sort_by_key(|place| place.id);
+    canonical
+}
 
 pub fn read(json_str: &str) -> Result<TreeData, Error> {
-    let tree_data = serde_json::from_str(json_str)?;
+    let (tree_data, _) = read_from(json_str.as_bytes())?;
     Ok(tree_data)
 }
 
 pub fn write(tree_data: &TreeData) -> Result<String, Error> {
-    let json_str = serde_json::to_string_pretty(tree_data)?;
-    Ok(json_str)
+    let mut bytes = Vec::new();
+    write_to(tree_data, &mut bytes, Format::Json)?;
+    Ok(String::from_utf8(bytes).expect("JSON must be valid UTF-8"))
+}
+
+/// Detect the format of serialized tree data by its first bytes.
+pub fn detect(bytes: &[u8]) -> Format {
+    if bytes.starts_with(&CBOR_MAGIC) {
+        Format::Cbor
+    } else {
+        Format::Json
+    }
+}
+
+/// Read tree data in any supported format.
+pub fn read_from<R: Read>(mut reader: R) -> Result<(TreeData, Format), Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let format = detect(&bytes);
+    let tree_data = decode(&bytes, format, &MIGRATIONS)?;
+    Ok((tree_data, format))
+}
+
+/// Decode tree data and upgrade it with the migrations, which are pending for its version.
+///
+/// Data of the current version is decoded straight into the tree data.
+fn decode(bytes: &[u8], format: Format, migrations: &[Migration]) -> Result<TreeData, Error> {
+    match format {
+        Format::Json => {
+            let header: JsonHeader = serde_json::from_slice(bytes)?;
+            let version = match header.version {
+                Some(version) => version.as_u64().ok_or(VersionError::InvalidVersion)?,
+                None => 0,
+            };
+            let pending = pending_migrations(version, migrations)?;
+            if pending.is_empty() {
+                return Ok(serde_json::from_slice(bytes)?);
+            }
+            let value: Value = serde_json::from_slice(bytes)?;
+            upgrade(value, pending, format)
+        }
+        Format::Cbor => {
+            let bytes = &bytes[CBOR_MAGIC.len()..];
+            let header: BinaryTreeData<IgnoredAny> = ciborium::from_reader(bytes)?;
+            let pending = pending_migrations(header.version, migrations)?;
+            if pending.is_empty() {
+                let binary: BinaryTreeData<TreeData> = ciborium::from_reader(bytes)?;
+                return Ok(binary.tree_data);
+            }
+            let binary: BinaryTreeData<Value> = ciborium::from_reader(bytes)?;
+            upgrade(binary.tree_data, pending, format)
+        }
+    }
+}
+
+pub fn write_to<W: Write>(
+    tree_data: &TreeData,
+    mut writer: W,
+    format: Format,
+) -> Result<(), Error> {
+    match format {
+        Format::Json => {
+            let versioned = VersionedTreeData {
+                version: CURRENT_VERSION,
+                tree_data,
+            };
+            serde_json::to_writer_pretty(writer, &versioned)?;
+        }
+        Format::Cbor => {
+            let binary = BinaryTreeData {
+                version: CURRENT_VERSION,
+                tree_data,
+            };
+            writer.write_all(&CBOR_MAGIC)?;
+            ciborium::into_writer(&binary, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// The migrations from the version to the newest one.
+fn pending_migrations(
+    version: u64,
+    migrations: &[Migration],
+) -> Result<&[Migration], VersionError> {
+    usize::try_from(version)
+        .ok()
+        .and_then(|version| migrations.get(version..))
+        .ok_or(VersionError::Unsupported(version))
+}
+
+/// Upgrade the data step by step and decode it.
+fn upgrade(mut value: Value, migrations: &[Migration], format: Format) -> Result<TreeData, Error> {
+    for migration in migrations {
+        migration(&mut value)?;
+    }
+    // encode the upgraded data in its format again, which decides how the ids are decoded
+    let mut bytes = Vec::new();
+    let tree_data = match format {
+        Format::Json => {
+            serde_json::to_writer(&mut bytes, &value)?;
+            serde_json::from_slice(&bytes)?
+        }
+        Format::Cbor => {
+            ciborium::into_writer(&value, &mut bytes)?;
+            ciborium::from_reader(bytes.as_slice())?
+        }
+    };
+    Ok(tree_data)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{extract_persons, Person, PersonId, Relationship, RelationshipId, TreeData};
+    use super::{BinaryTreeData, Format, CBOR_MAGIC};
+    use crate::{
+        error::VersionError, extract_persons, Person, PersonId, Relationship, RelationshipId,
+        TreeData,
+    };
+    use ciborium::Value;
     use std::{error::Error, fs};
 
     fn read(file_name: &str) -> Result<TreeData, Box<dyn Error>> {
@@ -84,6 +279,7 @@ mod test {
         let test_persons = vec![Person {
             id: PersonId(0),
             info: None,
+            names: Vec::new(),
         }];
         compare_persons_to_file(test_persons, "test/io/single_person.json")
     }
@@ -106,6 +302,145 @@ Expected: {:?}",
         }
     }
 
+    #[test]
+    fn unversioned() -> Result<(), Box<dyn Error>> {
+        let unversioned = read("test/io/v0.json")?;
+        let versioned = read("test/io/versioned.json")?;
+        assert_eq!(versioned.persons, unversioned.persons);
+        assert_eq!(versioned.relationships, unversioned.relationships);
+        assert_eq!(versioned.places, unversioned.places);
+        Ok(())
+    }
+
+    #[test]
+    fn write_current_version() -> Result<(), Box<dyn Error>> {
+        let tree_data = read("test/io/v0.json")?;
+        let value: serde_json::Value = serde_json::from_str(&super::write(&tree_data)?)?;
+        assert_eq!(Some(super::CURRENT_VERSION), value["version"].as_u64());
+        Ok(())
+    }
+
+    /// Test migration, which renames a key of the tree data.
+    fn rename_people(value: &mut Value) -> Result<(), VersionError> {
+        rename_key(value, "people", "persons");
+        Ok(())
+    }
+
+    fn rename_key(value: &mut Value, old: &str, new: &str) {
+        if let Value::Map(entries) = value {
+            for (key, _) in entries.iter_mut() {
+                if key.as_text() == Some(old) {
+                    *key = Value::Text(new.to_string());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn migrate_json() -> Result<(), Box<dyn Error>> {
+        let expected = read("test/io/versioned.json")?;
+        let old =
+            fs::read_to_string("test/io/versioned.json")?.replace("\"persons\"", "\"people\"");
+        assert!(super::read(&old).is_err());
+        let migrated = super::decode(old.as_bytes(), Format::Json, &[rename_people])?;
+        assert_eq!(expected.persons, migrated.persons);
+        assert_eq!(expected.relationships, migrated.relationships);
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_cbor() -> Result<(), Box<dyn Error>> {
+        let mut expected = read("test/io/versioned.json")?;
+        // ids, which do not fit into 64 bits, must keep their value
+        expected.persons[0].id = PersonId(u128::MAX);
+        let mut bytes = Vec::new();
+        super::write_to(&expected, &mut bytes, Format::Cbor)?;
+        let mut binary: BinaryTreeData<Value> = ciborium::from_reader(&bytes[CBOR_MAGIC.len()..])?;
+        rename_key(&mut binary.tree_data, "persons", "people");
+        let mut old = CBOR_MAGIC.to_vec();
+        ciborium::into_writer(&binary, &mut old)?;
+        assert!(super::read_from(old.as_slice()).is_err());
+        let migrated = super::decode(&old, Format::Cbor, &[rename_people])?;
+        assert_eq!(expected.persons, migrated.persons);
+        assert_eq!(expected.relationships, migrated.relationships);
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let err = read("test/io/unsupported_version.json").expect_err("Must fail");
+        assert_eq!(
+            "Version error: Unsupported file format version 9999",
+            err.to_string()
+        );
+    }
+
+    fn assert_round_trip(file_name: &str, format: super::Format) -> Result<(), Box<dyn Error>> {
+        let tree_data = read(file_name)?;
+        let mut bytes = Vec::new();
+        super::write_to(&tree_data, &mut bytes, format)?;
+        let (read_data, read_format) = super::read_from(bytes.as_slice())?;
+        assert_eq!(format, read_format, "{file_name}");
+        assert_eq!(tree_data.persons, read_data.persons, "{file_name}");
+        assert_eq!(
+            tree_data.relationships, read_data.relationships,
+            "{file_name}"
+        );
+        assert_eq!(tree_data.places, read_data.places, "{file_name}");
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), Box<dyn Error>> {
+        for dir in ["test/io", "test/graph", "test/place"] {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let file_name = path.to_str().ok_or("Invalid path")?;
+                // other tests write to these files concurrently
+                let is_written = file_name.contains("write_");
+                if is_written || file_name.ends_with("unsupported_version.json") {
+                    continue;
+                }
+                assert_round_trip(file_name, super::Format::Json)?;
+                assert_round_trip(file_name, super::Format::Cbor)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compact_ids() -> Result<(), Box<dyn Error>> {
+        let tree_data = read("test/io/versioned.json")?;
+        let mut bytes = Vec::new();
+        super::write_to(&tree_data, &mut bytes, super::Format::Cbor)?;
+        assert!(bytes.len() < super::write(&tree_data)?.len() / 2);
+        // ids are integers instead of hexadecimal strings
+        assert!(!bytes.windows(2).any(|window| window == [0x61, b'A']));
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_order() -> Result<(), Box<dyn Error>> {
+        let expected = fs::read_to_string("test/io/canonical.json")?;
+        let mut tree_data = read("test/io/canonical.json")?;
+        tree_data.persons.reverse();
+        tree_data.relationships.reverse();
+        for _ in 0..10 {
+            // every hash map has its own order of keys
+            for person in tree_data.persons.iter_mut() {
+                person.info = person.info.clone().map(|info| info.into_iter().collect());
+            }
+            let canonical = super::canonicalize(&tree_data);
+            assert_eq!(expected.trim_end(), super::write(&canonical)?);
+        }
+        // children keep their order
+        assert_eq!(
+            vec![PersonId(0xC), PersonId(0xA)],
+            tree_data.relationships[0].children
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_write_persons() -> Result<(), Box<dyn Error>> {
         let mut tree_data = read("test/io/write_persons.json")?;
@@ -1,18 +1,35 @@
 use error::InputError;
+pub use io::Format;
 use itertools::Itertools;
+use name::Name;
+use place::Place;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 pub use tree::FamilyTree;
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 
+pub mod changelog;
+pub mod command;
 mod consistency;
+pub mod diff;
 pub mod error;
 pub mod graph;
 mod io;
+pub mod merge;
+pub mod name;
+pub mod place;
+pub mod reference;
+pub mod schema;
+pub mod spreadsheet;
+pub mod storage;
+pub mod text;
 mod tree;
 
-/// Arbitrary information about a person.
+/// Information about a person.
+///
+/// Keys starting with `@` are declared in the `schema`, all other keys are free text.
 pub type PersonInfo = HashMap<String, String>;
 
 /// UUID for a `Relationship`, stored as u128.
@@ -91,7 +108,10 @@ pub struct PersonId(#[serde(with = "id")] pub u128);
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Type)]
 pub struct Person {
     pub id: PersonId,
+    #[serde(serialize_with = "serialize_info")]
     pub info: Option<PersonInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<Name>,
 }
 
 impl Person {
@@ -99,15 +119,31 @@ impl Person {
         Self {
             id: PersonId(Uuid::new_v4().to_u128_le()),
             info: None,
+            names: Vec::new(),
         }
     }
 }
 
+/// Serialize info with sorted keys, so the output does not depend on the hash map.
+///
+/// This also applies to trees, which are not saved canonically,
+/// because `PersonInfo` does not keep the order of insertion.
+fn serialize_info<S>(info: &Option<PersonInfo>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    info.as_ref()
+        .map(|info| info.iter().collect::<BTreeMap<_, _>>())
+        .serialize(serializer)
+}
+
 /// Raw family tree data.
-#[derive(Debug, Serialize, Deserialize, Type)]
+#[derive(Clone, Debug, Serialize, Deserialize, Type)]
 pub struct TreeData {
     pub relationships: Vec<Relationship>,
     pub persons: Vec<Person>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub places: Vec<Place>,
 }
 
 impl TreeData {
@@ -115,6 +151,7 @@ impl TreeData {
         Self {
             relationships,
             persons,
+            places: Vec::new(),
         }
     }
 }
@@ -126,6 +163,14 @@ fn extract_persons(relationships: &[Relationship]) -> Vec<PersonId> {
     parents.chain(children).unique().collect()
 }
 
+/// Comparison key for names, ignoring case, diacritics, punctuation and whitespace.
+fn normalize(text: &str) -> String {
+    text.nfkd()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
 // Trait impls for `PersonId`
 impl From<u128> for PersonId {
     fn from(value: u128) -> Self {
@@ -178,19 +223,28 @@ impl std::fmt::Display for RelationshipId {
     }
 }
 
+/// Ids are hexadecimal strings in human readable formats and raw integers otherwise.
 mod id {
     pub fn serialize<S>(value: &u128, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&format!("{:X}", value))
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&format!("{:X}", value))
+        } else {
+            serializer.serialize_u128(*value)
+        }
     }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<u128, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let s: &str = serde::Deserialize::deserialize(deserializer)?;
-        u128::from_str_radix(s, 16).map_err(serde::de::Error::custom)
+        if deserializer.is_human_readable() {
+            let s: String = serde::Deserialize::deserialize(deserializer)?;
+            u128::from_str_radix(&s, 16).map_err(serde::de::Error::custom)
+        } else {
+            serde::Deserialize::deserialize(deserializer)
+        }
     }
 }
@@ -0,0 +1,390 @@
+//! Three-way merge of concurrently edited versions of a tree.
+
+use crate::{
+    consistency, error::ConsistencyError, place::PlaceId, Person, PersonId, PersonInfo,
+    Relationship, RelationshipId, TreeData,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use std::{collections::HashMap, hash::Hash};
+use thiserror::Error;
+
+/// A change, which could not be merged automatically.
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize)]
+pub enum Conflict {
+    #[error("Person {0} was changed differently on both sides")]
+    Person(PersonId),
+    #[error("Info \"{1}\" of person {0} was changed differently on both sides")]
+    Info(PersonId, String),
+    #[error("Names of person {0} were changed differently on both sides")]
+    Names(PersonId),
+    #[error("Relationship {0} was changed differently on both sides")]
+    Relationship(RelationshipId),
+    #[error("Person {0} was moved to different relationships on both sides")]
+    Child(PersonId),
+    #[error("Place {0} was changed differently on both sides")]
+    Place(PlaceId),
+    #[error("Merged tree is inconsistent: {0}")]
+    Inconsistent(ConsistencyError),
+}
+
+impl Conflict {
+    /// Id of the conflicting item and the info key, inconsistencies come last.
+    fn sort_key(&self) -> (u128, &str) {
+        match self {
+            Conflict::Person(id) | Conflict::Names(id) | Conflict::Child(id) => (id.0, ""),
+            Conflict::Info(id, key) => (id.0, key),
+            Conflict::Relationship(id) => (id.0, ""),
+            Conflict::Place(id) => (id.0, ""),
+            Conflict::Inconsistent(_) => (u128::MAX, ""),
+        }
+    }
+}
+
+/// Result of a three-way merge.
+#[derive(Debug)]
+pub struct Merge {
+    /// Consistent tree data, conflicts are resolved in favor of our side.
+    pub tree_data: TreeData,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merge the changes of two versions of a tree with a common base.
+///
+/// Persons, relationships and places are matched by id.
+/// Changes made on only one side are applied, as are identical changes on both sides.
+/// Info is merged per key, so both sides may change different keys of the same person.
+///
+/// Conflicting changes are resolved in favor of our side and reported.
+/// If the merged tree is inconsistent, our side is returned unchanged.
+pub fn merge(base: &TreeData, ours: &TreeData, theirs: &TreeData) -> Merge {
+    let mut conflicts = Vec::new();
+
+    let persons = merge_maps(
+        [base, ours, theirs].map(|tree_data| by_id(&tree_data.persons, |person| person.id)),
+        |id, [base, ours, theirs]| match (base, ours, theirs) {
+            (Some(base), Some(ours), Some(theirs)) => {
+                Some(merge_person(base, ours, theirs, &mut conflicts))
+            }
+            _ => merge3(base, ours, theirs)
+                .unwrap_or_else(|ours| {
+                    conflicts.push(Conflict::Person(*id));
+                    ours
+                })
+                .map(|person| (*person).clone()),
+        },
+    );
+
+    let parents = merge_maps(
+        [base, ours, theirs].map(|tree_data| {
+            by_id(&tree_data.relationships, |rel| rel.id)
+                .into_iter()
+                .map(|(id, rel)| (id, sorted_parents(rel)))
+                .collect()
+        }),
+        |id, [base, ours, theirs]| {
+            merge3(base, ours, theirs)
+                .unwrap_or_else(|ours| {
+                    conflicts.push(Conflict::Relationship(*id));
+                    ours
+                })
+                .copied()
+        },
+    );
+
+    let child_of = merge_maps(
+        [base, ours, theirs].map(|tree_data| {
+            tree_data
+                .relationships
+                .iter()
+                .flat_map(|rel| rel.children.iter().map(|child| (*child, rel.id)))
+                .collect()
+        }),
+        |id, [base, ours, theirs]| {
+            merge3(base, ours, theirs)
+                .unwrap_or_else(|ours| {
+                    conflicts.push(Conflict::Child(*id));
+                    ours
+                })
+                .copied()
+        },
+    );
+
+    let places = merge_maps(
+        [base, ours, theirs].map(|tree_data| by_id(&tree_data.places, |place| place.id)),
+        |id, [base, ours, theirs]| {
+            merge3(base, ours, theirs)
+                .unwrap_or_else(|ours| {
+                    conflicts.push(Conflict::Place(*id));
+                    ours
+                })
+                .map(|place| (*place).clone())
+        },
+    );
+
+    // keep the order of our side, followed by additions of their side
+    let sides = [ours, theirs, base];
+    let person_order = sides
+        .iter()
+        .flat_map(|tree_data| tree_data.persons.iter().map(|person| person.id))
+        .unique()
+        .collect_vec();
+    let relationships = sides
+        .iter()
+        .flat_map(|tree_data| tree_data.relationships.iter().map(|rel| rel.id))
+        .unique()
+        .filter_map(|id| {
+            let rel_parents = parents.get(&id)?;
+            let versions = sides
+                .iter()
+                .flat_map(|tree_data| &tree_data.relationships)
+                .filter(|rel| rel.id == id)
+                .collect_vec();
+            let rel_parents = versions
+                .iter()
+                .find(|rel| sorted_parents(rel) == *rel_parents)
+                .map_or(*rel_parents, |rel| rel.parents);
+            let children = versions
+                .iter()
+                .flat_map(|rel| rel.children.iter().copied())
+                .unique()
+                .filter(|child| persons.contains_key(child) && child_of.get(child) == Some(&id))
+                .collect();
+            Some(Relationship {
+                id,
+                parents: rel_parents,
+                children,
+            })
+        })
+        .collect();
+    let tree_data = TreeData {
+        relationships,
+        persons: person_order
+            .iter()
+            .filter_map(|id| persons.get(id).cloned())
+            .collect(),
+        places: sides
+            .iter()
+            .flat_map(|tree_data| tree_data.places.iter().map(|place| place.id))
+            .unique()
+            .filter_map(|id| places.get(&id).cloned())
+            .collect(),
+    };
+
+    // the maps are unordered, so sort for a reproducible report
+    conflicts.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    match consistency::check(&tree_data) {
+        Ok(()) => Merge {
+            tree_data,
+            conflicts,
+        },
+        Err(err) => {
+            conflicts.push(Conflict::Inconsistent(err));
+            Merge {
+                tree_data: ours.clone(),
+                conflicts,
+            }
+        }
+    }
+}
+
+/// Merge a single value.
+///
+/// Fails with our value, if both sides changed it differently.
+fn merge3<T: PartialEq>(base: T, ours: T, theirs: T) -> Result<T, T> {
+    if ours == theirs || theirs == base {
+        Ok(ours)
+    } else if ours == base {
+        Ok(theirs)
+    } else {
+        Err(ours)
+    }
+}
+
+/// Merge maps of all three sides key by key, dropping keys merged to `None`.
+fn merge_maps<K, V, R, F>(maps: [HashMap<K, V>; 3], mut merge: F) -> HashMap<K, R>
+where
+    K: Copy + Eq + Hash,
+    F: FnMut(&K, [Option<&V>; 3]) -> Option<R>,
+{
+    let keys = maps
+        .iter()
+        .flat_map(|map| map.keys().copied())
+        .unique()
+        .collect_vec();
+    keys.into_iter()
+        .filter_map(|key| {
+            let values = [&maps[0], &maps[1], &maps[2]].map(|map| map.get(&key));
+            merge(&key, values).map(|value| (key, value))
+        })
+        .collect()
+}
+
+fn by_id<T, K: Eq + Hash>(items: &[T], id: impl Fn(&T) -> K) -> HashMap<K, &T> {
+    items.iter().map(|item| (id(item), item)).collect()
+}
+
+/// The parents in a stable order, the order of the parents has no meaning.
+fn sorted_parents(rel: &Relationship) -> [Option<PersonId>; 2] {
+    let mut parents = rel.parents;
+    parents.sort_by_key(|parent| (parent.is_none(), *parent));
+    parents
+}
+
+fn merge_person(
+    base: &Person,
+    ours: &Person,
+    theirs: &Person,
+    conflicts: &mut Vec<Conflict>,
+) -> Person {
+    let [base_info, our_info, their_info] =
+        [base, ours, theirs].map(|person| person.info.clone().unwrap_or_default());
+    let keys = [&base_info, &our_info, &their_info]
+        .into_iter()
+        .flat_map(|info| info.keys())
+        .unique()
+        .sorted();
+    let mut info = PersonInfo::new();
+    for key in keys {
+        let value = merge3(base_info.get(key), our_info.get(key), their_info.get(key))
+            .unwrap_or_else(|ours| {
+                conflicts.push(Conflict::Info(base.id, key.clone()));
+                ours
+            });
+        if let Some(value) = value {
+            info.insert(key.clone(), value.clone());
+        }
+    }
+    let names = merge3(&base.names, &ours.names, &theirs.names).unwrap_or_else(|ours| {
+        conflicts.push(Conflict::Names(base.id));
+        ours
+    });
+    Person {
+        id: base.id,
+        info: (!info.is_empty()).then_some(info),
+        names: names.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Person;
+
+    fn read(file_name: &str) -> TreeData {
+        let json_data = std::fs::read_to_string(file_name).expect("Cannot read test file");
+        crate::io::read(&json_data).expect("Cannot convert test file")
+    }
+
+    fn info(person: &Person, key: &str) -> Option<String> {
+        person.info.as_ref().and_then(|info| info.get(key)).cloned()
+    }
+
+    #[test]
+    fn unchanged_side() {
+        let base = read("test/merge/base.json");
+        let ours = read("test/merge/ours.json");
+        let merge = merge(&base, &ours, &base);
+        assert_eq!(Vec::<Conflict>::new(), merge.conflicts);
+        assert_eq!(ours.persons, merge.tree_data.persons);
+        assert_eq!(ours.relationships, merge.tree_data.relationships);
+    }
+
+    #[test]
+    fn concurrent_changes() {
+        let base = read("test/merge/base.json");
+        let ours = read("test/merge/ours.json");
+        let theirs = read("test/merge/theirs.json");
+        let Merge {
+            tree_data,
+            conflicts,
+        } = merge(&base, &ours, &theirs);
+        assert_eq!(
+            vec![Conflict::Info(PersonId(3), "@occupation".to_string())],
+            conflicts
+        );
+        let ids = tree_data
+            .persons
+            .iter()
+            .map(|person| person.id.0)
+            .collect_vec();
+        assert_eq!(vec![1, 2, 3, 4, 5], ids);
+        let anna = &tree_data.persons[0];
+        assert_eq!(Some("Anne".to_string()), info(anna, "@firstName"));
+        assert_eq!(Some("Schmidt".to_string()), info(anna, "@lastName"));
+        let carl = &tree_data.persons[2];
+        assert_eq!(Some("Miller".to_string()), info(carl, "@occupation"));
+        let rels = tree_data
+            .relationships
+            .iter()
+            .map(|rel| {
+                (
+                    rel.id.0,
+                    rel.children.iter().map(|child| child.0).collect_vec(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            vec![
+                (0xA, vec![1]),
+                (0xB, vec![2]),
+                (0xC, vec![3, 4]),
+                (0xE, vec![5])
+            ],
+            rels
+        );
+    }
+
+    #[test]
+    fn sorted_conflicts() {
+        let base = read("test/merge/base.json");
+        let side = |value: &str| {
+            let mut tree_data = base.clone();
+            for person in tree_data.persons.iter_mut().rev() {
+                let info = person.info.get_or_insert_with(PersonInfo::new);
+                for key in ["@b", "@a"] {
+                    info.insert(key.to_string(), value.to_string());
+                }
+            }
+            tree_data
+        };
+        let conflicts = merge(&base, &side("ours"), &side("theirs")).conflicts;
+        let expected = base
+            .persons
+            .iter()
+            .map(|person| person.id)
+            .sorted()
+            .flat_map(|pid| ["@a", "@b"].map(|key| Conflict::Info(pid, key.to_string())))
+            .collect_vec();
+        assert_eq!(expected, conflicts);
+    }
+
+    #[test]
+    fn inconsistent_result() {
+        let base = read("test/merge/base.json");
+        // we remove Bert, they add a child to him
+        let mut ours = base.clone();
+        ours.persons.retain(|person| person.id != PersonId(2));
+        ours.relationships
+            .retain(|rel| rel.id != RelationshipId(0xB));
+        ours.relationships[1].parents = [Some(PersonId(1)), None];
+        let mut theirs = base.clone();
+        theirs.persons.push(Person {
+            id: PersonId(6),
+            info: None,
+            names: Vec::new(),
+        });
+        theirs.relationships.push(Relationship {
+            id: RelationshipId(0xF),
+            parents: [Some(PersonId(2)), None],
+            children: vec![PersonId(6)],
+        });
+        let merge = merge(&base, &ours, &theirs);
+        assert!(matches!(
+            merge.conflicts.as_slice(),
+            [Conflict::Inconsistent(_)]
+        ));
+        assert_eq!(ours.persons, merge.tree_data.persons);
+        consistency::check(&merge.tree_data).expect("Must be consistent");
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{normalize, Person};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// A name of a person, valid in a certain period.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Name {
+    pub given: Option<String>,
+    pub surname: Option<String>,
+    /// Titles or particles in front of the name, like "Dr." or "von".
+    pub prefix: Option<String>,
+    /// Additions after the name, like "Jr." or "III".
+    pub suffix: Option<String>,
+    pub name_type: NameType,
+    /// Start of validity as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub valid_from: Option<String>,
+    /// End of validity as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub valid_until: Option<String>,
+    /// Marks the name to be used for display and sorting.
+    pub primary: bool,
+}
+
+/// The kind of a `Name`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum NameType {
+    #[default]
+    Birth,
+    Married,
+    Nickname,
+    Patronymic,
+    Religious,
+    Adopted,
+    Other,
+}
+
+impl std::str::FromStr for NameType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "birth" => Ok(Self::Birth),
+            "married" => Ok(Self::Married),
+            "nickname" => Ok(Self::Nickname),
+            "patronymic" => Ok(Self::Patronymic),
+            "religious" => Ok(Self::Religious),
+            "adopted" => Ok(Self::Adopted),
+            "other" => Ok(Self::Other),
+            _ => Err(format!("Unknown name type \"{s}\"")),
+        }
+    }
+}
+
+impl Name {
+    /// Construct the name stored in the legacy `@firstName` and `@lastName` info keys.
+    fn from_info(person: &Person) -> Option<Self> {
+        let info = person.info.as_ref()?;
+        let given = info.get("@firstName").filter(|name| !name.is_empty());
+        let surname = info.get("@lastName").filter(|name| !name.is_empty());
+        if given.is_none() && surname.is_none() {
+            return None;
+        }
+        Some(Self {
+            given: given.cloned(),
+            surname: surname.cloned(),
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        [&self.given, &self.surname]
+            .into_iter()
+            .all(|part| part.as_deref().unwrap_or_default().trim().is_empty())
+    }
+
+    fn parts(&self) -> impl Iterator<Item = &str> {
+        [&self.prefix, &self.given, &self.surname, &self.suffix]
+            .into_iter()
+            .filter_map(|part| part.as_deref())
+            .filter(|part| !part.is_empty())
+    }
+
+    /// Normalized words of all parts of the name.
+    fn words(&self) -> impl Iterator<Item = String> + '_ {
+        self.parts()
+            .flat_map(str::split_whitespace)
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.parts().join(" "))
+    }
+}
+
+impl Person {
+    /// All names of the person, including the name from the legacy info keys.
+    pub fn all_names(&self) -> Vec<Name> {
+        let mut names = self.names.clone();
+        if let Some(name) = Name::from_info(self) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The name used for display and sorting.
+    ///
+    /// This is the name marked as primary, otherwise the first birth name,
+    /// otherwise the first name of any type.
+    /// Persons without structured names fall back to `@firstName` and `@lastName`.
+    pub fn primary_name(&self) -> Option<Name> {
+        self.names
+            .iter()
+            .find(|name| name.primary)
+            .or_else(|| {
+                self.names
+                    .iter()
+                    .find(|name| name.name_type == NameType::Birth)
+            })
+            .or_else(|| self.names.first())
+            .cloned()
+            .or_else(|| Name::from_info(self))
+    }
+
+    /// The primary name as text, if the person has a name.
+    pub fn display_name(&self) -> Option<String> {
+        self.primary_name().map(|name| name.to_string())
+    }
+
+    /// Key for sorting persons by surname and then by given name.
+    ///
+    /// Persons without a name are sorted last.
+    pub fn sort_key(&self) -> (bool, String, String) {
+        let name = self.primary_name().unwrap_or_default();
+        let part = |part: Option<String>| part.as_deref().map(normalize).unwrap_or_default();
+        (name.is_empty(), part(name.surname), part(name.given))
+    }
+
+    /// Check, whether every word of the query is the beginning of a word in one of the names.
+    pub fn matches_name(&self, query: &str) -> bool {
+        let query = query
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect_vec();
+        if query.is_empty() {
+            return false;
+        }
+        self.all_names().iter().any(|name| {
+            let words = name.words().collect_vec();
+            query
+                .iter()
+                .all(|part| words.iter().any(|word| word.starts_with(part)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{PersonId, PersonInfo};
+
+    fn person(names: Vec<Name>, info: Option<PersonInfo>) -> Person {
+        Person {
+            id: PersonId(0),
+            info,
+            names,
+        }
+    }
+
+    fn name(given: &str, surname: &str, name_type: NameType, primary: bool) -> Name {
+        Name {
+            given: Some(given.to_string()),
+            surname: Some(surname.to_string()),
+            name_type,
+            primary,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn primary_name_rule() {
+        let married = name("Anna", "Schmidt", NameType::Married, false);
+        let birth = name("Anna", "Müller", NameType::Birth, false);
+        let nickname = name("Anni", "", NameType::Nickname, false);
+
+        let without_birth = person(vec![nickname.clone(), married.clone()], None);
+        assert_eq!(Some(nickname.clone()), without_birth.primary_name());
+
+        let with_birth = person(vec![married.clone(), birth.clone()], None);
+        assert_eq!(Some(birth.clone()), with_birth.primary_name());
+
+        let marked = Name {
+            primary: true,
+            ..married.clone()
+        };
+        let with_marked = person(vec![birth, marked.clone()], None);
+        assert_eq!(Some(marked), with_marked.primary_name());
+    }
+
+    #[test]
+    fn legacy_info() {
+        let info = PersonInfo::from([
+            ("@firstName".to_string(), "Karl".to_string()),
+            ("@lastName".to_string(), String::new()),
+        ]);
+        let legacy = person(Vec::new(), Some(info));
+        assert_eq!(Some("Karl".to_string()), legacy.display_name());
+        assert_eq!(None, person(Vec::new(), None).display_name());
+    }
+
+    #[test]
+    fn display() {
+        let name = Name {
+            prefix: Some("Dr.".to_string()),
+            suffix: Some("Jr.".to_string()),
+            ..name("Hans", "von Berg", NameType::Birth, false)
+        };
+        assert_eq!("Dr. Hans von Berg Jr.", name.to_string());
+    }
+
+    #[test]
+    fn search() {
+        let anna = person(
+            vec![
+                name("Anna Maria", "Müller", NameType::Birth, false),
+                name("Anna", "Schmidt", NameType::Married, true),
+            ],
+            None,
+        );
+        assert!(anna.matches_name("anna muller"));
+        assert!(anna.matches_name("Mar Mül"));
+        assert!(anna.matches_name("SCHMIDT"));
+        assert!(!anna.matches_name("Maria Meier"));
+        // all words must match the same name
+        assert!(!anna.matches_name("Maria Schmidt"));
+        assert!(!anna.matches_name(" "));
+    }
+
+    #[test]
+    fn sorting() {
+        let mut persons = [
+            person(Vec::new(), None),
+            person(vec![name("Bert", "Zander", NameType::Birth, false)], None),
+            person(vec![name("Berta", "Ahrens", NameType::Birth, false)], None),
+            person(vec![name("Anton", "Ährens", NameType::Birth, false)], None),
+        ];
+        persons.sort_by_key(Person::sort_key);
+        let names = persons
+            .iter()
+            .map(|person| person.display_name())
+            .collect_vec();
+        assert_eq!(
+            vec![
+                Some("Anton Ährens".to_string()),
+                Some("Berta Ahrens".to_string()),
+                Some("Bert Zander".to_string()),
+                None
+            ],
+            names
+        );
+    }
+}
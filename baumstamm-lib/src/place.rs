@@ -0,0 +1,262 @@
+use crate::{normalize, Person, PersonInfo};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+/// Info keys, whose values reference a `Place` by its id.
+pub const PLACE_KEYS: [&str; 4] = ["@birthPlace", "@deathPlace", "@burialPlace", "@residence"];
+
+/// UUID for a `Place`, stored as u128.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash, Type)]
+pub struct PlaceId(#[serde(with = "crate::id")] pub u128);
+
+/// A named place, which may be part of a larger place (village → county → country).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Type)]
+pub struct Place {
+    pub id: PlaceId,
+    pub name: String,
+    pub parent: Option<PlaceId>,
+    pub coordinates: Option<Coordinates>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternate_names: Vec<AlternateName>,
+}
+
+impl Place {
+    pub(crate) fn new(name: String, parent: Option<PlaceId>) -> Self {
+        Self {
+            id: PlaceId(Uuid::new_v4().to_u128_le()),
+            name,
+            parent,
+            coordinates: None,
+            alternate_names: Vec::new(),
+        }
+    }
+
+    /// All names of the place, starting with the current one.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str())
+            .chain(self.alternate_names.iter().map(|alt| alt.name.as_str()))
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        let keys = self.names().map(normalize).collect_vec();
+        other.names().map(normalize).any(|key| keys.contains(&key))
+    }
+
+    fn absorb(&mut self, other: Self) {
+        let names = std::iter::once(AlternateName {
+            name: other.name,
+            historical: false,
+        })
+        .chain(other.alternate_names);
+        for alt in names {
+            if !self.names().any(|name| name == alt.name) {
+                self.alternate_names.push(alt);
+            }
+        }
+        if self.coordinates.is_none() {
+            self.coordinates = other.coordinates;
+        }
+    }
+}
+
+/// Geographical coordinates in decimal degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Type)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Latitude within ±90 and longitude within ±180 degrees.
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.latitude) && (-180.0..=180.0).contains(&self.longitude)
+    }
+}
+
+/// Another name, under which a place is or was known.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct AlternateName {
+    pub name: String,
+    pub historical: bool,
+}
+
+/// Result of merging a duplicate place into another one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct PlaceMerge {
+    pub merged: PlaceId,
+    pub into: PlaceId,
+}
+
+/// Merge places with matching names and the same parent place.
+///
+/// The first occurrence of a place is kept and the other spellings are added as alternate names.
+/// Children of merged places and references in the persons' info are moved to the remaining place.
+pub(crate) fn merge_duplicates(places: &mut Vec<Place>, persons: &mut [Person]) -> Vec<PlaceMerge> {
+    let mut merges = Vec::new();
+    // merging places can turn their children into duplicates, so repeat until nothing changes
+    while let Some((keep, merge)) = places
+        .iter()
+        .enumerate()
+        .tuple_combinations()
+        .find(|((_, a), (_, b))| a.parent == b.parent && a.matches(b))
+        .map(|((keep, _), (merge, _))| (keep, merge))
+    {
+        let merged = places.remove(merge);
+        let into = places[keep].id;
+        places
+            .iter_mut()
+            .filter(|place| place.parent == Some(merged.id))
+            .for_each(|place| place.parent = Some(into));
+        persons
+            .iter_mut()
+            .filter_map(|person| person.info.as_mut())
+            .for_each(|info| replace_references(info, merged.id, into));
+        merges.push(PlaceMerge {
+            merged: merged.id,
+            into,
+        });
+        places[keep].absorb(merged);
+    }
+    merges
+}
+
+/// Replace free text in place keys with a reference to a place of the same name.
+///
+/// If no such place exists, a new one without parent is created.
+pub(crate) fn link_references(places: &mut Vec<Place>, persons: &mut [Person]) {
+    for info in persons.iter_mut().filter_map(|person| person.info.as_mut()) {
+        for key in PLACE_KEYS {
+            let Some(value) = info.get_mut(key) else {
+                continue;
+            };
+            if places.iter().any(|place| place.id.to_string() == *value) {
+                continue;
+            }
+            let key = normalize(value);
+            let candidates = places
+                .iter()
+                .filter(|place| place.names().any(|name| normalize(name) == key))
+                .collect_vec();
+            let id = match candidates.as_slice() {
+                [place] => place.id,
+                [] => {
+                    let place = Place::new(value.trim().to_string(), None);
+                    let id = place.id;
+                    places.push(place);
+                    id
+                }
+                // ambiguous, leave the value untouched
+                _ => continue,
+            };
+            *value = id.to_string();
+        }
+    }
+}
+
+/// The place referenced by the value of a place key.
+///
+/// Only values written exactly like a `PlaceId` are references,
+/// other values are free text like "Ede", which has not been linked yet.
+pub(crate) fn reference(value: &str) -> Option<PlaceId> {
+    PlaceId::try_from(value)
+        .ok()
+        .filter(|id| id.to_string() == value)
+}
+
+fn replace_references(info: &mut PersonInfo, old: PlaceId, new: PlaceId) {
+    let old = old.to_string();
+    info.iter_mut()
+        .filter(|(key, value)| PLACE_KEYS.contains(&key.as_str()) && **value == old)
+        .for_each(|(_, value)| *value = new.to_string());
+}
+
+// Trait impls for `PlaceId`
+impl From<u128> for PlaceId {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl TryFrom<&str> for PlaceId {
+    type Error = std::num::ParseIntError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(Self(u128::from_str_radix(value, 16)?))
+    }
+}
+
+impl std::fmt::Debug for PlaceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:X}", self.0)
+    }
+}
+
+impl std::fmt::Display for PlaceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TreeData;
+
+    fn read(file_name: &str) -> TreeData {
+        let json_data = std::fs::read_to_string(file_name).expect("Cannot read test file");
+        crate::io::read(&json_data).expect("Cannot convert test file")
+    }
+
+    #[test]
+    fn normalized_names() {
+        assert_eq!(normalize(" München "), normalize("munchen"));
+        assert_eq!(normalize("Sankt-Gallen"), normalize("sankt gallen"));
+        assert_ne!(normalize("Bern"), normalize("Berlin"));
+    }
+
+    #[test]
+    fn merge_spellings() {
+        let mut tree_data = read("test/place/duplicates.json");
+        let merges = merge_duplicates(&mut tree_data.places, &mut tree_data.persons);
+        assert_eq!(
+            vec![
+                PlaceMerge {
+                    merged: PlaceId(2),
+                    into: PlaceId(1)
+                },
+                PlaceMerge {
+                    merged: PlaceId(4),
+                    into: PlaceId(3)
+                }
+            ],
+            merges
+        );
+        let ids = tree_data.places.iter().map(|place| place.id).collect_vec();
+        assert_eq!(vec![PlaceId(0), PlaceId(1), PlaceId(3), PlaceId(5)], ids);
+        let munich = &tree_data.places[1];
+        assert_eq!(
+            vec!["München", "Munich", "Munchen"],
+            munich.names().collect_vec()
+        );
+        assert!(munich.coordinates.is_some());
+        // the child of the merged place is a duplicate of the other child
+        assert_eq!(Some(PlaceId(1)), tree_data.places[2].parent);
+        let info = tree_data.persons[0].info.as_ref().expect("Info must exist");
+        assert_eq!("3", info["@birthPlace"]);
+        assert_eq!("1", info["@residence"]);
+    }
+
+    #[test]
+    fn link_free_text() {
+        let mut tree_data = read("test/place/free_text.json");
+        link_references(&mut tree_data.places, &mut tree_data.persons);
+        assert_eq!(2, tree_data.places.len());
+        let info = tree_data.persons[0].info.as_ref().expect("Info must exist");
+        assert_eq!("0", info["@birthPlace"]);
+        let new_place = &tree_data.places[1];
+        assert_eq!("Hamburg", new_place.name);
+        assert_eq!(new_place.id.to_string(), info["@deathPlace"]);
+        assert_eq!("Hamburg", info["note"]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Resolving of user supplied references to persons and relationships.
+//!
+//! A reference is either an id, a unique beginning of an id like a git short hash, or a name.
+//! Relationships can also be referenced by the names of both partners, e.g. `Anna & Karl`.
+
+use crate::{
+    error::{Error, InputError},
+    normalize, FamilyTree, Person, PersonId, Relationship, RelationshipId,
+};
+
+/// Minimal length of an id prefix, shorter references are always names.
+const MIN_PREFIX_LEN: usize = 4;
+
+/// The relationship of a person, which is meant, when a relationship is referenced by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelationshipRole {
+    /// The relationship, the person is a child of.
+    Child,
+    /// The relationship, the person is a parent in, if there is only one.
+    Parent,
+}
+
+/// Ids, which begin with the reference, if it can be the beginning of an id.
+fn by_prefix<T: ToString>(reference: &str, ids: impl Iterator<Item = T>) -> Vec<T> {
+    let is_prefix =
+        reference.len() >= MIN_PREFIX_LEN && reference.chars().all(|c| c.is_ascii_hexdigit());
+    if !is_prefix {
+        return Vec::new();
+    }
+    let prefix = reference.to_uppercase();
+    ids.filter(|id| id.to_string().starts_with(&prefix))
+        .collect()
+}
+
+fn ambiguous(reference: &str, candidates: Vec<String>) -> Error {
+    InputError::AmbiguousReference(reference.to_string(), candidates).into()
+}
+
+impl FamilyTree {
+    /// Find a person by id, unique id prefix or name.
+    ///
+    /// Ids take precedence over names.
+    /// If several persons match the name, the one with exactly this name is chosen.
+    pub fn resolve_person(&self, reference: &str) -> Result<PersonId, Error> {
+        let reference = reference.trim();
+        let persons = self.get_persons();
+        match by_prefix(reference, persons.iter().map(|person| person.id)).as_slice() {
+            [pid] => return Ok(*pid),
+            [] => {}
+            pids => {
+                let candidates = pids.iter().map(|pid| self.person_label(*pid)).collect();
+                return Err(ambiguous(reference, candidates));
+            }
+        }
+        let found = self.search(reference);
+        let exact = found
+            .iter()
+            .copied()
+            .filter(|person| {
+                person
+                    .display_name()
+                    .is_some_and(|name| normalize(&name) == normalize(reference))
+            })
+            .collect::<Vec<_>>();
+        match (found.as_slice(), exact.as_slice()) {
+            ([], _) => Err(InputError::UnknownReference(reference.to_string()).into()),
+            ([person], _) | (_, [person]) => Ok(person.id),
+            (found, _) => {
+                let candidates = found
+                    .iter()
+                    .map(|person| self.person_label(person.id))
+                    .collect();
+                Err(ambiguous(reference, candidates))
+            }
+        }
+    }
+
+    /// Find a relationship by id, unique id prefix, both partners or one person.
+    ///
+    /// Partners are separated by `&`, a single person is resolved according to the role.
+    pub fn resolve_relationship(
+        &self,
+        reference: &str,
+        role: RelationshipRole,
+    ) -> Result<RelationshipId, Error> {
+        let reference = reference.trim();
+        let rels = self.get_relationships();
+        match by_prefix(reference, rels.iter().map(|rel| rel.id)).as_slice() {
+            [rid] => return Ok(*rid),
+            [] => {}
+            rids => {
+                let candidates = rids
+                    .iter()
+                    .filter_map(|rid| rels.iter().find(|rel| rel.id == *rid))
+                    .map(|rel| self.relationship_label(rel))
+                    .collect();
+                return Err(ambiguous(reference, candidates));
+            }
+        }
+        if let Some((person, partner)) = reference.split_once('&') {
+            let person = self.resolve_person(person)?;
+            let partner = self.resolve_person(partner)?;
+            return rels
+                .iter()
+                .find(|rel| {
+                    rel.parents.contains(&Some(person)) && rel.parents.contains(&Some(partner))
+                })
+                .map(|rel| rel.id)
+                .ok_or_else(|| InputError::UnknownReference(reference.to_string()).into());
+        }
+        let pid = self.resolve_person(reference)?;
+        let found = rels
+            .iter()
+            .filter(|rel| match role {
+                RelationshipRole::Child => rel.children.contains(&pid),
+                RelationshipRole::Parent => rel.parents.contains(&Some(pid)),
+            })
+            .collect::<Vec<_>>();
+        match found.as_slice() {
+            [] => Err(InputError::UnknownReference(reference.to_string()).into()),
+            [rel] => Ok(rel.id),
+            found => {
+                let candidates = found
+                    .iter()
+                    .map(|rel| self.relationship_label(rel))
+                    .collect();
+                Err(ambiguous(reference, candidates))
+            }
+        }
+    }
+
+    fn person_label(&self, pid: PersonId) -> String {
+        let name = self
+            .get_persons()
+            .iter()
+            .find(|person| person.id == pid)
+            .and_then(Person::display_name);
+        match name {
+            Some(name) => format!("{} ({})", pid, name),
+            None => pid.to_string(),
+        }
+    }
+
+    fn relationship_label(&self, rel: &Relationship) -> String {
+        let parents = rel
+            .parents
+            .iter()
+            .flatten()
+            .map(|pid| {
+                self.get_persons()
+                    .iter()
+                    .find(|person| person.id == *pid)
+                    .and_then(Person::display_name)
+                    .unwrap_or_else(|| pid.to_string())
+            })
+            .collect::<Vec<_>>();
+        if parents.is_empty() {
+            rel.id.to_string()
+        } else {
+            format!("{} ({})", rel.id, parents.join(" & "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn named(tree: &mut FamilyTree, pid: PersonId, first_name: &str, last_name: &str) {
+        tree.insert_info(pid, "@firstName".to_string(), first_name.to_string())
+            .expect("Person must exist");
+        tree.insert_info(pid, "@lastName".to_string(), last_name.to_string())
+            .expect("Person must exist");
+    }
+
+    #[test]
+    fn persons() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let anna = tree.get_persons()[0].id;
+        let (karl, _) = tree.add_parent(root)?;
+        let paul = tree.add_child(root)?;
+        named(&mut tree, anna, "Anna", "Müller");
+        named(&mut tree, karl, "Karl", "Müller");
+        named(&mut tree, paul, "Anna", "Müller-Schmidt");
+
+        assert_eq!(karl, tree.resolve_person(&karl.to_string())?);
+        assert_eq!(karl, tree.resolve_person(&karl.to_string()[..12])?);
+        assert_eq!(karl, tree.resolve_person("karl")?);
+        // the exact name wins over other matches
+        assert_eq!(anna, tree.resolve_person("anna muller")?);
+        assert!(matches!(
+            tree.resolve_person("Müller"),
+            Err(Error::Input(InputError::AmbiguousReference(_, candidates))) if candidates.len() == 3
+        ));
+        assert!(matches!(
+            tree.resolve_person("Maria"),
+            Err(Error::Input(InputError::UnknownReference(_)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn relationships() -> Result<(), Error> {
+        let mut tree = FamilyTree::new();
+        let root = tree.get_relationships()[0].id;
+        let anna = tree.get_persons()[0].id;
+        let (karl, karls_parents) = tree.add_parent(root)?;
+        let (eva, _) = tree.add_parent(root)?;
+        named(&mut tree, anna, "Anna", "Müller");
+        named(&mut tree, karl, "Karl", "Müller");
+        named(&mut tree, eva, "Eva", "Schmidt");
+
+        let prefix = &root.to_string()[..8];
+        assert_eq!(
+            root,
+            tree.resolve_relationship(prefix, RelationshipRole::Child)?
+        );
+        assert_eq!(
+            root,
+            tree.resolve_relationship("Karl & Eva", RelationshipRole::Child)?
+        );
+        assert_eq!(
+            root,
+            tree.resolve_relationship("Anna", RelationshipRole::Child)?
+        );
+        assert_eq!(
+            root,
+            tree.resolve_relationship("Karl", RelationshipRole::Parent)?
+        );
+        assert_eq!(
+            karls_parents,
+            tree.resolve_relationship("Karl", RelationshipRole::Child)?
+        );
+        assert!(tree
+            .resolve_relationship("Anna", RelationshipRole::Parent)
+            .is_err());
+
+        let other = tree.add_new_relationship(karl)?;
+        assert!(matches!(
+            tree.resolve_relationship("Karl", RelationshipRole::Parent),
+            Err(Error::Input(InputError::AmbiguousReference(_, candidates))) if candidates.len() == 2
+        ));
+        assert_ne!(
+            other,
+            tree.resolve_relationship("Karl & Eva", RelationshipRole::Parent)?
+        );
+        Ok(())
+    }
+}
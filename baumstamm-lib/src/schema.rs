@@ -0,0 +1,212 @@
+use crate::{error::SchemaError, place::Place};
+use serde::Serialize;
+use specta::Type;
+
+/// Prefix of info keys reserved for the schema.
+pub const RESERVED_PREFIX: &str = "@";
+
+/// Info keys known to Baumstamm.
+///
+/// Keys without the `RESERVED_PREFIX` can be chosen freely and always hold text.
+pub const KEYS: &[KeyDefinition] = &[
+    KeyDefinition::new("@firstName", "First name", ValueType::Text),
+    KeyDefinition::new("@lastName", "Last name", ValueType::Text),
+    KeyDefinition::new("@image", "Image", ValueType::Url),
+    KeyDefinition::new("@sex", "Sex", ValueType::Enum(&["female", "male", "other"])),
+    KeyDefinition::new("@birthDate", "Date of birth", ValueType::Date),
+    KeyDefinition::new("@birthPlace", "Place of birth", ValueType::Place),
+    KeyDefinition::new("@deathDate", "Date of death", ValueType::Date),
+    KeyDefinition::new("@deathPlace", "Place of death", ValueType::Place),
+    KeyDefinition::new("@burialDate", "Date of burial", ValueType::Date),
+    KeyDefinition::new("@burialPlace", "Place of burial", ValueType::Place),
+    KeyDefinition::new("@residence", "Residence", ValueType::Place),
+    KeyDefinition::new("@occupation", "Occupation", ValueType::Text),
+];
+
+/// Type of the value stored under an info key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Type)]
+pub enum ValueType {
+    /// Arbitrary text.
+    Text,
+    /// A date with optional month and day: `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    Date,
+    /// The id of a `Place` in the tree.
+    Place,
+    /// One of the listed options.
+    Enum(&'static [&'static str]),
+    /// An absolute URL, like `https://…` or `data:…`.
+    Url,
+}
+
+/// Declaration of a known info key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct KeyDefinition {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub value_type: ValueType,
+}
+
+impl KeyDefinition {
+    const fn new(key: &'static str, label: &'static str, value_type: ValueType) -> Self {
+        Self {
+            key,
+            label,
+            value_type,
+        }
+    }
+}
+
+/// Look up the definition of a known key.
+pub fn definition(key: &str) -> Option<&'static KeyDefinition> {
+    KEYS.iter().find(|definition| definition.key == key)
+}
+
+/// The type of values stored under a key. Custom keys hold text.
+pub fn value_type(key: &str) -> ValueType {
+    definition(key).map_or(ValueType::Text, |definition| definition.value_type)
+}
+
+/// Check, whether the value is allowed for the given key.
+pub fn validate(key: &str, value: &str, places: &[Place]) -> Result<(), SchemaError> {
+    let definition = match definition(key) {
+        Some(definition) => definition,
+        None if key.starts_with(RESERVED_PREFIX) => return Err(SchemaError::UnknownKey),
+        None => return Ok(()),
+    };
+    match definition.value_type {
+        ValueType::Text => Ok(()),
+        ValueType::Date if is_date(value) => Ok(()),
+        ValueType::Date => Err(SchemaError::InvalidDate),
+        ValueType::Place if places.iter().any(|place| place.id.to_string() == value) => Ok(()),
+        ValueType::Place => Err(SchemaError::UnknownPlace),
+        ValueType::Enum(options) if options.contains(&value) => Ok(()),
+        ValueType::Enum(options) => Err(SchemaError::InvalidOption(options.join(", "))),
+        ValueType::Url if is_url(value) => Ok(()),
+        ValueType::Url => Err(SchemaError::InvalidUrl),
+    }
+}
+
+/// `YYYY`, `YYYY-MM` or `YYYY-MM-DD` with a valid month and day.
+pub(crate) fn is_date(value: &str) -> bool {
+    fn number(part: &str, digits: usize) -> Option<u32> {
+        if part.len() == digits && part.chars().all(|c| c.is_ascii_digit()) {
+            part.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    let mut parts = value.split('-');
+    let Some(year) = parts.next().and_then(|part| number(part, 4)) else {
+        return false;
+    };
+    let month = match parts.next() {
+        Some(part) => match number(part, 2) {
+            Some(month @ 1..=12) => month,
+            _ => return false,
+        },
+        None => return true,
+    };
+    let day = match parts.next() {
+        Some(part) => match number(part, 2) {
+            Some(day) => day,
+            None => return false,
+        },
+        None => return true,
+    };
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    parts.next().is_none() && (1..=days_in_month).contains(&day)
+}
+
+/// An URI scheme followed by a non-empty remainder without whitespace.
+fn is_url(value: &str) -> bool {
+    let Some((scheme, rest)) = value.split_once(':') else {
+        return false;
+    };
+    let mut scheme_chars = scheme.chars();
+    scheme_chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme_chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        && !rest.is_empty()
+        && !value.chars().any(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::place::{PlaceId, PLACE_KEYS};
+
+    #[test]
+    fn place_keys_are_places() {
+        for key in PLACE_KEYS {
+            assert_eq!(ValueType::Place, value_type(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn dates() {
+        for date in ["1900", "1900-02", "1904-02-29", "2000-02-29", "1999-12-31"] {
+            assert!(is_date(date), "{date}");
+        }
+        for date in [
+            "",
+            "19",
+            "1900-2",
+            "1900-13",
+            "1900-02-29",
+            "1999-04-31",
+            "1999-01-01-",
+        ] {
+            assert!(!is_date(date), "{date}");
+        }
+    }
+
+    #[test]
+    fn urls() {
+        for url in [
+            "https://example.com/a.png",
+            "data:image/png;base64,AAAA",
+            "file:///a",
+        ] {
+            assert!(is_url(url), "{url}");
+        }
+        for url in ["", "example.com", "https:", "1http://a", "https://a b"] {
+            assert!(!is_url(url), "{url}");
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let places = [Place {
+            id: PlaceId(0xA),
+            name: "Berlin".to_string(),
+            parent: None,
+            coordinates: None,
+            alternate_names: Vec::new(),
+        }];
+        assert!(validate("@firstName", "Anna", &places).is_ok());
+        assert!(validate("nickname", "Annie", &places).is_ok());
+        assert!(validate("@sex", "female", &places).is_ok());
+        assert!(validate("@birthPlace", "A", &places).is_ok());
+        assert_eq!(
+            "Unknown key with reserved prefix",
+            validate("@firstname", "Anna", &places)
+                .expect_err("Must fail")
+                .to_string()
+        );
+        assert_eq!(
+            "Invalid value, expected one of: female, male, other",
+            validate("@sex", "f", &places)
+                .expect_err("Must fail")
+                .to_string()
+        );
+        assert!(validate("@birthPlace", "Berlin", &places).is_err());
+        assert!(validate("@birthDate", "1.1.1900", &places).is_err());
+        assert!(validate("@image", "me.png", &places).is_err());
+    }
+}
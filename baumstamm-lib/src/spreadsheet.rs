@@ -0,0 +1,478 @@
+//! CSV import and export of persons and relationships.
+
+use crate::{
+    error::Error,
+    place::{self, PLACE_KEYS},
+    schema, FamilyTree, Person, PersonId, PersonInfo, Relationship, TreeData,
+};
+use itertools::Itertools;
+use serde::Serialize;
+use specta::Type;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use thiserror::Error;
+
+/// Column with the local id of a person.
+pub const ID_COLUMN: &str = "id";
+/// Columns referencing the parents of a person by their local id.
+pub const PARENT_COLUMNS: [&str; 2] = ["father", "mother"];
+
+/// A problem with a row of an imported sheet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct Issue {
+    /// Line in the CSV file, the header being line 1.
+    pub line: usize,
+    pub id: String,
+    pub kind: IssueKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize, Type)]
+pub enum IssueKind {
+    #[error("Empty id, row skipped")]
+    EmptyId,
+    #[error("Id is used more than once, row skipped")]
+    DuplicateId,
+    #[error("Unknown parent \"{0}\" ignored")]
+    UnknownParent(String),
+    #[error("Person cannot be its own parent, parent ignored")]
+    SelfReference,
+    #[error("Father and mother are the same person, mother ignored")]
+    SameParents,
+    #[error("Invalid value for \"{0}\" ignored: {1}")]
+    InvalidValue(String, String),
+    #[error("Person is its own ancestor, row skipped")]
+    Cycle,
+    #[error("Person is not connected to the rest of the tree, row skipped")]
+    Unconnected,
+}
+
+/// A tree imported from a sheet and the problems found on the way.
+#[derive(Debug)]
+pub struct Import {
+    pub tree: FamilyTree,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug)]
+struct Row {
+    line: usize,
+    id: String,
+    parents: [Option<String>; 2],
+    info: PersonInfo,
+}
+
+/// Export persons with their id and one column per info key.
+pub fn export_persons(tree: &FamilyTree) -> Result<String, Error> {
+    let persons = tree.get_persons();
+    let keys: BTreeSet<&String> = persons
+        .iter()
+        .filter_map(|person| person.info.as_ref())
+        .flat_map(|info| info.keys())
+        .collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(std::iter::once(ID_COLUMN).chain(keys.iter().map(|key| key.as_str())))?;
+    for person in persons {
+        let id = person.id.to_string();
+        let values = keys.iter().map(|key| {
+            person
+                .info
+                .as_ref()
+                .and_then(|info| info.get(*key))
+                .map_or("", String::as_str)
+        });
+        writer.write_record(std::iter::once(id.as_str()).chain(values))?;
+    }
+    into_string(writer)
+}
+
+/// Export relationships with their id, both parents and the children separated by spaces.
+pub fn export_relationships(tree: &FamilyTree) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["id", "parent1", "parent2", "children"])?;
+    for rel in tree.get_relationships() {
+        let parent =
+            |parent: Option<PersonId>| parent.map(|pid| pid.to_string()).unwrap_or_default();
+        writer.write_record([
+            rel.id.to_string(),
+            parent(rel.parents[0]),
+            parent(rel.parents[1]),
+            rel.children.iter().join(" "),
+        ])?;
+    }
+    into_string(writer)
+}
+
+fn into_string(writer: csv::Writer<Vec<u8>>) -> Result<String, Error> {
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| Error::Csv(err.to_string()))?;
+    String::from_utf8(bytes).map_err(|err| Error::Csv(err.to_string()))
+}
+
+/// Import a sheet of persons.
+///
+/// Every row needs a unique value in the `id` column, which may be any text.
+/// The optional `father` and `mother` columns reference other rows by that id.
+/// All other columns are stored as info, empty cells are skipped.
+///
+/// Rows, which would make the tree inconsistent, are skipped and reported as issues.
+pub fn import_persons(csv_str: &str) -> Result<Import, Error> {
+    let mut issues = Vec::new();
+    let mut rows = read_rows(csv_str, &mut issues)?;
+    resolve_parents(&mut rows, &mut issues);
+    remove_cycles(&mut rows, &mut issues);
+    remove_unconnected(&mut rows, &mut issues);
+
+    let ids: HashMap<String, PersonId> = rows
+        .iter()
+        .map(|row| (row.id.clone(), Person::new().id))
+        .collect();
+    let mut persons = rows
+        .iter()
+        .map(|row| Person {
+            id: ids[&row.id],
+            info: (!row.info.is_empty()).then(|| row.info.clone()),
+            names: Vec::new(),
+        })
+        .collect_vec();
+    let relationships = rows
+        .iter()
+        .map(|row| row.parents.clone().map(|parent| parent.map(|id| ids[&id])))
+        .map(|[father, mother]| {
+            // partners share one relationship, regardless of their order
+            let mut parents = [father, mother];
+            parents.sort();
+            parents
+        })
+        .zip(rows.iter().map(|row| ids[&row.id]))
+        .into_group_map()
+        .into_iter()
+        .flat_map(|(parents, children)| match parents {
+            // persons without parents get a relationship on their own
+            [None, None] => children
+                .into_iter()
+                .map(|child| Relationship::new(None, None, vec![child]))
+                .collect_vec(),
+            [first, second] => vec![Relationship::new(
+                first.or(second),
+                first.and(second),
+                children,
+            )],
+        })
+        .collect_vec();
+
+    let mut places = Vec::new();
+    place::link_references(&mut places, &mut persons);
+    let mut tree_data = TreeData::new(relationships, persons);
+    tree_data.places = places;
+    Ok(Import {
+        tree: FamilyTree::try_from(tree_data)?,
+        issues,
+    })
+}
+
+fn read_rows(csv_str: &str, issues: &mut Vec<Issue>) -> Result<Vec<Row>, Error> {
+    let mut reader = csv::Reader::from_reader(csv_str.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name);
+    let id_column = column(ID_COLUMN).ok_or_else(|| Error::Csv("Missing id column".to_string()))?;
+    let parent_columns = PARENT_COLUMNS.map(column);
+
+    let mut rows: Vec<Row> = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |pos| pos.line() as usize);
+        let cell = |index: usize| record.get(index).map(str::trim).unwrap_or_default();
+        let id = cell(id_column).to_string();
+        let mut issue = |kind| {
+            issues.push(Issue {
+                line,
+                id: id.clone(),
+                kind,
+            })
+        };
+        if id.is_empty() {
+            issue(IssueKind::EmptyId);
+            continue;
+        }
+        if rows.iter().any(|row| row.id == id) {
+            issue(IssueKind::DuplicateId);
+            continue;
+        }
+        let parents = parent_columns.map(|index| {
+            index
+                .map(cell)
+                .filter(|parent| !parent.is_empty())
+                .map(str::to_string)
+        });
+        let mut info = PersonInfo::new();
+        for (index, key) in headers.iter().map(str::trim).enumerate() {
+            let value = cell(index);
+            if index == id_column || parent_columns.contains(&Some(index)) || value.is_empty() {
+                continue;
+            }
+            // free text in place keys is linked to the place registry after the import
+            let validation = if PLACE_KEYS.contains(&key) {
+                Ok(())
+            } else {
+                schema::validate(key, value, &[])
+            };
+            match validation {
+                Ok(()) => {
+                    info.insert(key.to_string(), value.to_string());
+                }
+                Err(err) => issue(IssueKind::InvalidValue(key.to_string(), err.to_string())),
+            }
+        }
+        rows.push(Row {
+            line,
+            id,
+            parents,
+            info,
+        });
+    }
+    Ok(rows)
+}
+
+fn resolve_parents(rows: &mut [Row], issues: &mut Vec<Issue>) {
+    let ids: BTreeSet<String> = rows.iter().map(|row| row.id.clone()).collect();
+    for row in rows.iter_mut() {
+        let mut issue = |kind| {
+            issues.push(Issue {
+                line: row.line,
+                id: row.id.clone(),
+                kind,
+            })
+        };
+        for parent in row.parents.iter_mut() {
+            match parent {
+                Some(id) if !ids.contains(id) => {
+                    issue(IssueKind::UnknownParent(id.clone()));
+                    *parent = None;
+                }
+                Some(id) if *id == row.id => {
+                    issue(IssueKind::SelfReference);
+                    *parent = None;
+                }
+                _ => {}
+            }
+        }
+        if row.parents[0].is_some() && row.parents[0] == row.parents[1] {
+            issue(IssueKind::SameParents);
+            row.parents[1] = None;
+        }
+    }
+}
+
+/// Skip rows, which are their own ancestors, and remove references to them.
+///
+/// A depth-first search through the parents skips the row at the end of each back edge.
+fn remove_cycles(rows: &mut Vec<Row>, issues: &mut Vec<Issue>) {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        Visiting,
+        Visited,
+    }
+
+    let parents: HashMap<&str, Vec<&str>> = rows
+        .iter()
+        .map(|row| {
+            let parents = row.parents.iter().flatten().map(String::as_str).collect();
+            (row.id.as_str(), parents)
+        })
+        .collect();
+    let mut marks: HashMap<&str, Mark> = HashMap::new();
+    let mut cyclic: HashSet<&str> = HashSet::new();
+    for row in rows.iter() {
+        if marks.contains_key(row.id.as_str()) {
+            continue;
+        }
+        marks.insert(&row.id, Mark::Visiting);
+        // persons with the index of their next parent to visit
+        let mut stack = vec![(row.id.as_str(), 0)];
+        while let Some((current, index)) = stack.pop() {
+            let Some(&parent) = parents[current].get(index) else {
+                marks.insert(current, Mark::Visited);
+                continue;
+            };
+            stack.push((current, index + 1));
+            if cyclic.contains(parent) {
+                continue;
+            }
+            match marks.get(parent) {
+                None => {
+                    marks.insert(parent, Mark::Visiting);
+                    stack.push((parent, 0));
+                }
+                Some(Mark::Visiting) => {
+                    cyclic.insert(parent);
+                }
+                Some(Mark::Visited) => {}
+            }
+        }
+    }
+
+    let cyclic: HashSet<String> = cyclic.into_iter().map(str::to_string).collect();
+    while let Some(position) = rows.iter().position(|row| cyclic.contains(&row.id)) {
+        skip_row(rows, position, IssueKind::Cycle, issues);
+    }
+}
+
+/// Keep the largest group of connected persons and skip all other rows.
+fn remove_unconnected(rows: &mut Vec<Row>, issues: &mut Vec<Issue>) {
+    let mut groups: Vec<BTreeSet<&str>> = Vec::new();
+    for row in rows.iter() {
+        // a person is connected to its parents and, through a shared relationship, its siblings
+        let members: BTreeSet<&str> = std::iter::once(row.id.as_str())
+            .chain(row.parents.iter().flatten().map(String::as_str))
+            .chain(
+                rows.iter()
+                    .filter(|other| other.parents.iter().any(Option::is_some))
+                    .filter(|other| other.parents == row.parents)
+                    .map(|other| other.id.as_str()),
+            )
+            .collect();
+        let (connected, mut unconnected): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|group| !group.is_disjoint(&members));
+        let merged = connected.into_iter().flatten().chain(members).collect();
+        unconnected.push(merged);
+        groups = unconnected;
+    }
+    let Some(largest) = groups.into_iter().max_by_key(BTreeSet::len) else {
+        return;
+    };
+    let largest: BTreeSet<String> = largest.into_iter().map(str::to_string).collect();
+    while let Some(position) = rows.iter().position(|row| !largest.contains(&row.id)) {
+        skip_row(rows, position, IssueKind::Unconnected, issues);
+    }
+}
+
+fn skip_row(rows: &mut Vec<Row>, position: usize, kind: IssueKind, issues: &mut Vec<Issue>) {
+    let row = rows.remove(position);
+    rows.iter_mut()
+        .flat_map(|other| other.parents.iter_mut())
+        .filter(|parent| parent.as_ref() == Some(&row.id))
+        .for_each(|parent| *parent = None);
+    issues.push(Issue {
+        line: row.line,
+        id: row.id,
+        kind,
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn import(file_name: &str) -> Import {
+        let csv_str = std::fs::read_to_string(file_name).expect("Cannot read test file");
+        import_persons(&csv_str).expect("Cannot import test file")
+    }
+
+    fn find<'a>(tree: &'a FamilyTree, first_name: &str) -> &'a Person {
+        tree.get_persons()
+            .iter()
+            .find(|person| {
+                person.info.as_ref().and_then(|info| info.get("@firstName"))
+                    == Some(&first_name.to_string())
+            })
+            .expect("Person must exist")
+    }
+
+    #[test]
+    fn family() {
+        let Import { tree, issues } = import("test/spreadsheet/family.csv");
+        assert_eq!(Vec::<Issue>::new(), issues);
+        assert_eq!(5, tree.get_persons().len());
+        let [anna, bert, carl, dora] =
+            ["Anna", "Bert", "Carl", "Dora"].map(|name| find(&tree, name).id);
+        let rel = tree
+            .get_relationships()
+            .iter()
+            .find(|rel| rel.children.contains(&carl))
+            .expect("Relationship must exist");
+        assert!(rel.parents.contains(&Some(anna)) && rel.parents.contains(&Some(bert)));
+        assert!(rel.children.contains(&dora));
+        let residence = &find(&tree, "Anna").info.as_ref().expect("Info must exist")["@residence"];
+        assert_eq!("Berlin", tree.get_places()[0].name);
+        assert_eq!(tree.get_places()[0].id.to_string(), *residence);
+    }
+
+    #[test]
+    fn report_issues() {
+        let Import { tree, issues } = import("test/spreadsheet/issues.csv");
+        let kinds = issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind.clone()))
+            .collect_vec();
+        assert_eq!(
+            vec![
+                (3, IssueKind::DuplicateId),
+                (
+                    4,
+                    IssueKind::InvalidValue(
+                        "@birthDate".to_string(),
+                        "Invalid date, expected YYYY, YYYY-MM or YYYY-MM-DD".to_string()
+                    )
+                ),
+                (5, IssueKind::UnknownParent("x".to_string())),
+                (6, IssueKind::SelfReference),
+                (7, IssueKind::Cycle),
+                // skipping the first row of the cycle breaks it
+                (8, IssueKind::Unconnected),
+                (9, IssueKind::Unconnected),
+            ],
+            kinds
+        );
+        assert_eq!(4, tree.get_persons().len());
+    }
+
+    #[test]
+    fn cycles() {
+        // a -> b -> c -> a and d -> e -> d, f descends from the first cycle
+        let mut rows = [
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "a"),
+            ("d", "e"),
+            ("e", "d"),
+            ("f", "a"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(line, (id, parent))| Row {
+            line,
+            id: id.to_string(),
+            parents: [Some(parent.to_string()), None],
+            info: PersonInfo::new(),
+        })
+        .collect_vec();
+        let mut issues = Vec::new();
+        remove_cycles(&mut rows, &mut issues);
+        let skipped = issues.iter().map(|issue| issue.id.as_str()).collect_vec();
+        assert_eq!(vec!["a", "d"], skipped);
+        let parents = rows
+            .iter()
+            .map(|row| (row.id.as_str(), row.parents[0].as_deref()))
+            .collect_vec();
+        assert_eq!(
+            vec![("b", Some("c")), ("c", None), ("e", None), ("f", None)],
+            parents
+        );
+    }
+
+    #[test]
+    fn export_and_import() -> Result<(), Error> {
+        let Import { tree, .. } = import("test/spreadsheet/family.csv");
+        let persons = export_persons(&tree)?;
+        let header = persons.lines().next().expect("Header must exist");
+        assert_eq!("id,@firstName,@lastName,@residence", header);
+        assert_eq!(6, persons.lines().count());
+        let relationships = export_relationships(&tree)?;
+        assert_eq!(
+            tree.get_relationships().len() + 1,
+            relationships.lines().count()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+//! Safe storage of tree files.
+//!
+//! Files are replaced atomically: the new content is written to a temporary file next to the tree,
+//! synced to disk and renamed over the old file, so a crash leaves either the old or the new version.
+//! Before a file is replaced, its previous content is kept as a timestamped backup
+//! in a directory next to it, of which only the newest ones are kept.
+
+use crate::{
+    changelog,
+    error::{Error, InputError},
+    FamilyTree,
+};
+use serde::Serialize;
+use specta::Type;
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of backups kept for each tree file.
+pub const KEEP_BACKUPS: usize = 10;
+
+/// A previous version of a tree file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Type)]
+pub struct Backup {
+    pub path: PathBuf,
+    /// Milliseconds since the UNIX epoch, when the version was replaced.
+    pub timestamp: u64,
+}
+
+impl Backup {
+    /// The timestamp as date and time in UTC, like `2024-01-31 12:00:00`.
+    pub fn date_time(&self) -> String {
+        changelog::date_time(self.timestamp / 1000)
+    }
+}
+
+/// Directory of the backups of a tree file, which is the tree path with `.backups` appended.
+pub fn backup_dir(tree_path: &Path) -> PathBuf {
+    let mut path = tree_path.as_os_str().to_owned();
+    path.push(".backups");
+    PathBuf::from(path)
+}
+
+/// Back up the current content of the file, replace it atomically and remove old backups.
+pub fn save(path: &Path, data: &[u8]) -> Result<(), Error> {
+    backup(path, data)?;
+    write_atomic(path, data)?;
+    rotate(path, KEEP_BACKUPS)
+}
+
+/// Replace the file with a temporary file, which has been completely written and synced.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file path"))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    let written = write_synced(&temp_path, data, path).and_then(|_| fs::rename(&temp_path, path));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    sync_dir(path);
+    Ok(())
+}
+
+fn write_synced(temp_path: &Path, data: &[u8], path: &Path) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(data)?;
+    // the replaced file keeps its permissions
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()
+}
+
+/// Sync the directory, so the rename survives a crash as well.
+#[cfg(unix)]
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+/// Directories cannot be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) {}
+
+/// Keep the current content of the file as backup,
+/// unless it does not change or is the newest backup already.
+fn backup(path: &Path, new_data: &[u8]) -> Result<(), Error> {
+    let data = match fs::read(path) {
+        Ok(data) if data != new_data => data,
+        Ok(_) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(newest) = backups(path)?.first() {
+        if fs::read(&newest.path).is_ok_and(|backup| backup == data) {
+            return Ok(());
+        }
+    }
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut timestamp = now();
+    // several saves within a millisecond must not replace each other's backups
+    while dir.join(format!("{}{}", timestamp, extension)).exists() {
+        timestamp += 1;
+    }
+    write_atomic(&dir.join(format!("{}{}", timestamp, extension)), &data)
+}
+
+/// Backups of the file, the newest first.
+pub fn backups(path: &Path) -> Result<Vec<Backup>, Error> {
+    let entries = match fs::read_dir(backup_dir(path)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let timestamp = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok());
+        if let (Some(timestamp), true) = (timestamp, path.is_file()) {
+            backups.push(Backup { path, timestamp });
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.timestamp));
+    Ok(backups)
+}
+
+/// Find a backup by its number in the list of backups, starting with 1 for the newest,
+/// or by its timestamp.
+pub fn find_backup(path: &Path, reference: &str) -> Result<Backup, Error> {
+    let reference = reference.trim();
+    let backups = backups(path)?;
+    let by_timestamp = backups
+        .iter()
+        .find(|backup| backup.timestamp.to_string() == reference);
+    let by_number = reference
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| backups.get(number.checked_sub(1)?));
+    by_timestamp
+        .or(by_number)
+        .cloned()
+        .ok_or_else(|| InputError::UnknownReference(reference.to_string()).into())
+}
+
+/// Replace the file with a backup and return the restored tree.
+///
+/// The replaced version is backed up as well, so restoring can be reverted.
+pub fn restore(path: &Path, backup: &Backup) -> Result<FamilyTree, Error> {
+    let data = fs::read(&backup.path)?;
+    // a damaged backup must not replace the file
+    let tree = FamilyTree::load(&data)?;
+    save(path, &data)?;
+    Ok(tree)
+}
+
+/// Remove all but the newest backups.
+fn rotate(path: &Path, keep: usize) -> Result<(), Error> {
+    for backup in backups(path)?.iter().skip(keep) {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::Uuid;
+
+    /// An empty directory, which is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("baumstamm-{}", Uuid::new_v4()));
+            fs::create_dir(&dir).expect("Temporary directory must be creatable");
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn save_and_restore() -> Result<(), Error> {
+        let dir = TempDir::new();
+        let path = dir.0.join("tree.json");
+        let first = FamilyTree::new().save_bytes()?;
+        let second = FamilyTree::new().save_bytes()?;
+
+        save(&path, &first)?;
+        assert_eq!(first, fs::read(&path)?);
+        assert!(backups(&path)?.is_empty());
+        save(&path, &second)?;
+        save(&path, &second)?;
+        assert_eq!(second, fs::read(&path)?);
+        // only the tree and its backups remain, no temporary files
+        assert_eq!(2, fs::read_dir(&dir.0)?.count());
+        let found = backups(&path)?;
+        assert_eq!(1, found.len());
+        assert_eq!(first, fs::read(&found[0].path)?);
+        assert_eq!(
+            Some("json"),
+            found[0].path.extension().and_then(|e| e.to_str())
+        );
+
+        let backup = find_backup(&path, "1")?;
+        assert_eq!(backup, find_backup(&path, &backup.timestamp.to_string())?);
+        assert!(find_backup(&path, "2").is_err());
+        let tree = restore(&path, &backup)?;
+        assert_eq!(first, tree.save_bytes()?);
+        assert_eq!(first, fs::read(&path)?);
+        // the replaced version is the newest backup now
+        assert_eq!(second, fs::read(&find_backup(&path, "1")?.path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn rotation() -> Result<(), Error> {
+        let dir = TempDir::new();
+        let path = dir.0.join("tree.json");
+        for _ in 0..KEEP_BACKUPS + 3 {
+            save(&path, &FamilyTree::new().save_bytes()?)?;
+        }
+        let found = backups(&path)?;
+        assert_eq!(KEEP_BACKUPS, found.len());
+        assert!(found
+            .windows(2)
+            .all(|pair| pair[0].timestamp > pair[1].timestamp));
+
+        fs::write(dir.0.join("tree.json.backups").join("notes.txt"), "")?;
+        assert_eq!(KEEP_BACKUPS, backups(&path)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_backup() -> Result<(), Error> {
+        let dir = TempDir::new();
+        let path = dir.0.join("tree.json");
+        let data = FamilyTree::new().save_bytes()?;
+        save(&path, &data)?;
+        let backup = Backup {
+            path: dir.0.join("damaged.json"),
+            timestamp: 0,
+        };
+        fs::write(&backup.path, "{")?;
+        assert!(restore(&path, &backup).is_err());
+        assert_eq!(data, fs::read(&path)?);
+        Ok(())
+    }
+}
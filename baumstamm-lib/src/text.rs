@@ -0,0 +1,644 @@
+//! Line-oriented text format, which uses short labels instead of ids.
+//!
+//! ```text
+//! # Lines starting with a hash are comments.
+//! place germany Germany
+//! place berlin Berlin
+//!   in germany
+//!   at 52.52 13.405
+//!   alias Cölln historical
+//!
+//! person anna
+//!   name birth given Anna surname "von Berg" primary
+//!   info @birthPlace berlin
+//!   info nickname Annie
+//! person bert
+//! person carl
+//!
+//! relationship
+//!   parents anna bert
+//!   children carl
+//! ```
+//!
+//! Blocks start at the beginning of a line, their attributes are indented.
+//! Words containing whitespace are quoted, within quotes `\"`, `\\`, `\n` and `\r` are escaped,
+//! so multi-line values stay on one line.
+//! Values of place keys reference a place by its label.
+//! Ids are not part of the format, so parsing creates new ones.
+
+use crate::{
+    error::{TextError, TextErrorKind},
+    name::Name,
+    normalize,
+    place::{AlternateName, Coordinates, Place, PlaceId, PLACE_KEYS},
+    Person, PersonId, PersonInfo, Relationship, TreeData,
+};
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, kind: TextErrorKind) -> TextError {
+        TextError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+/// The tokens of a line after the leading keyword.
+struct Arguments {
+    tokens: std::vec::IntoIter<Token>,
+    line: usize,
+    end: usize,
+}
+
+impl Arguments {
+    fn next(&mut self, what: &'static str) -> Result<Token, TextError> {
+        self.tokens.next().ok_or(TextError {
+            line: self.line,
+            column: self.end,
+            kind: TextErrorKind::Missing(what),
+        })
+    }
+
+    fn finish(mut self) -> Result<(), TextError> {
+        match self.tokens.next() {
+            Some(token) => Err(token.error(TextErrorKind::Unexpected(token.text.clone()))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Block {
+    Place {
+        label: Token,
+        name: String,
+        parent: Option<Token>,
+        coordinates: Option<Coordinates>,
+        alternate_names: Vec<AlternateName>,
+    },
+    Person {
+        label: Token,
+        info: Vec<(String, String)>,
+        names: Vec<Name>,
+    },
+    Relationship {
+        parents: Vec<Token>,
+        children: Vec<Token>,
+    },
+}
+
+/// Parse the text format into tree data.
+///
+/// The tree data is not checked for consistency.
+pub fn parse(text: &str) -> Result<TreeData, TextError> {
+    let mut blocks = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_nr = index + 1;
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut tokens = tokenize(line, line_nr)?.into_iter();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let mut args = Arguments {
+            tokens,
+            line: line_nr,
+            end: line.chars().count() + 1,
+        };
+        if !line.starts_with(char::is_whitespace) {
+            blocks.push(parse_block(&keyword, &mut args)?);
+        } else {
+            let block = blocks
+                .last_mut()
+                .ok_or_else(|| keyword.error(TextErrorKind::OutsideBlock))?;
+            parse_attribute(block, &keyword, &mut args)?;
+        }
+        args.finish()?;
+    }
+    resolve(blocks)
+}
+
+fn tokenize(line: &str, line_nr: usize) -> Result<Vec<Token>, TextError> {
+    let error = |column, kind| TextError {
+        line: line_nr,
+        column,
+        kind,
+    };
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().enumerate().peekable();
+    while let Some((index, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let column = index + 1;
+        let mut text = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((index, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => text.push(c),
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 'r')) => text.push('\r'),
+                        _ => return Err(error(index + 1, TextErrorKind::InvalidEscape)),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(error(column, TextErrorKind::UnterminatedString)),
+                }
+            }
+        } else {
+            text.push(c);
+            while let Some((_, c)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+                text.push(c);
+            }
+        }
+        tokens.push(Token {
+            text,
+            line: line_nr,
+            column,
+        });
+    }
+    Ok(tokens)
+}
+
+fn parse_block(keyword: &Token, args: &mut Arguments) -> Result<Block, TextError> {
+    match keyword.text.as_str() {
+        "place" => Ok(Block::Place {
+            label: args.next("label")?,
+            name: args.next("name")?.text,
+            parent: None,
+            coordinates: None,
+            alternate_names: Vec::new(),
+        }),
+        "person" => Ok(Block::Person {
+            label: args.next("label")?,
+            info: Vec::new(),
+            names: Vec::new(),
+        }),
+        "relationship" => Ok(Block::Relationship {
+            parents: Vec::new(),
+            children: Vec::new(),
+        }),
+        _ => Err(keyword.error(TextErrorKind::UnknownStatement(keyword.text.clone()))),
+    }
+}
+
+fn parse_attribute(
+    block: &mut Block,
+    keyword: &Token,
+    args: &mut Arguments,
+) -> Result<(), TextError> {
+    match (block, keyword.text.as_str()) {
+        (Block::Place { parent, .. }, "in") => *parent = Some(args.next("parent label")?),
+        (Block::Place { coordinates, .. }, "at") => {
+            let mut number = |what| {
+                let token = args.next(what)?;
+                token
+                    .text
+                    .parse()
+                    .map_err(|_| token.error(TextErrorKind::InvalidNumber(token.text.clone())))
+            };
+            *coordinates = Some(Coordinates {
+                latitude: number("latitude")?,
+                longitude: number("longitude")?,
+            });
+        }
+        (
+            Block::Place {
+                alternate_names, ..
+            },
+            "alias",
+        ) => {
+            let name = args.next("name")?.text;
+            let historical = match args.tokens.as_slice().first() {
+                Some(token) if token.text == "historical" => args.tokens.next().is_some(),
+                _ => false,
+            };
+            alternate_names.push(AlternateName { name, historical });
+        }
+        (Block::Person { info, .. }, "info") => {
+            let key = args.next("key")?.text;
+            let value = args.next("value")?.text;
+            info.push((key, value));
+        }
+        (Block::Person { names, .. }, "name") => names.push(parse_name(args)?),
+        (Block::Relationship { parents, .. }, "parents") => {
+            parents.push(args.next("parent label")?);
+            parents.extend(args.tokens.next());
+        }
+        (Block::Relationship { children, .. }, "children") => {
+            children.push(args.next("child label")?);
+            children.extend(args.tokens.by_ref());
+        }
+        _ => {
+            return Err(keyword.error(TextErrorKind::UnknownStatement(keyword.text.clone())));
+        }
+    }
+    Ok(())
+}
+
+fn parse_name(args: &mut Arguments) -> Result<Name, TextError> {
+    let name_type = args.next("name type")?;
+    let mut name = Name {
+        name_type: name_type
+            .text
+            .parse()
+            .map_err(|err| name_type.error(TextErrorKind::InvalidNameType(err)))?,
+        ..Default::default()
+    };
+    while let Some(token) = args.tokens.next() {
+        let part = match token.text.as_str() {
+            "primary" => {
+                name.primary = true;
+                continue;
+            }
+            "given" => &mut name.given,
+            "surname" => &mut name.surname,
+            "prefix" => &mut name.prefix,
+            "suffix" => &mut name.suffix,
+            "from" => &mut name.valid_from,
+            "until" => &mut name.valid_until,
+            _ => return Err(token.error(TextErrorKind::Unexpected(token.text.clone()))),
+        };
+        *part = Some(args.next("value")?.text);
+    }
+    Ok(name)
+}
+
+/// Map labels to new ids, rejecting duplicates.
+fn assign_ids<'a, T>(
+    labels: impl Iterator<Item = &'a Token>,
+    new_id: impl Fn() -> T,
+) -> Result<HashMap<&'a str, T>, TextError> {
+    let mut ids = HashMap::new();
+    for label in labels {
+        if ids.insert(label.text.as_str(), new_id()).is_some() {
+            return Err(label.error(TextErrorKind::DuplicateLabel(label.text.clone())));
+        }
+    }
+    Ok(ids)
+}
+
+fn lookup<T: Copy>(ids: &HashMap<&str, T>, label: &Token) -> Result<T, TextError> {
+    ids.get(label.text.as_str())
+        .copied()
+        .ok_or_else(|| label.error(TextErrorKind::UnknownLabel(label.text.clone())))
+}
+
+fn resolve(blocks: Vec<Block>) -> Result<TreeData, TextError> {
+    let place_ids = assign_ids(
+        blocks.iter().filter_map(|block| match block {
+            Block::Place { label, .. } => Some(label),
+            _ => None,
+        }),
+        || Place::new(String::new(), None).id,
+    )?;
+    let person_ids = assign_ids(
+        blocks.iter().filter_map(|block| match block {
+            Block::Person { label, .. } => Some(label),
+            _ => None,
+        }),
+        || Person::new().id,
+    )?;
+
+    let mut tree_data = TreeData::new(Vec::new(), Vec::new());
+    for block in &blocks {
+        match block {
+            Block::Place {
+                label,
+                name,
+                parent,
+                coordinates,
+                alternate_names,
+            } => tree_data.places.push(Place {
+                id: place_ids[label.text.as_str()],
+                name: name.clone(),
+                parent: parent
+                    .as_ref()
+                    .map(|parent| lookup(&place_ids, parent))
+                    .transpose()?,
+                coordinates: *coordinates,
+                alternate_names: alternate_names.clone(),
+            }),
+            Block::Person { label, info, names } => {
+                let info: PersonInfo = info
+                    .iter()
+                    .map(|(key, value)| {
+                        // free text in place keys is kept as it is
+                        let value = match place_ids.get(value.as_str()) {
+                            Some(id) if PLACE_KEYS.contains(&key.as_str()) => id.to_string(),
+                            _ => value.clone(),
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect();
+                tree_data.persons.push(Person {
+                    id: person_ids[label.text.as_str()],
+                    info: (!info.is_empty()).then_some(info),
+                    names: names.clone(),
+                });
+            }
+            Block::Relationship { parents, children } => {
+                let parents: Vec<PersonId> = parents
+                    .iter()
+                    .map(|parent| lookup(&person_ids, parent))
+                    .try_collect()?;
+                let children = children
+                    .iter()
+                    .map(|child| lookup(&person_ids, child))
+                    .try_collect()?;
+                tree_data.relationships.push(Relationship::new(
+                    parents.first().copied(),
+                    parents.get(1).copied(),
+                    children,
+                ));
+            }
+        }
+    }
+    Ok(tree_data)
+}
+
+/// Print tree data in the text format.
+///
+/// Labels are derived from the names of persons and places.
+pub fn print(tree_data: &TreeData) -> String {
+    let mut used = HashSet::new();
+    let place_labels: HashMap<PlaceId, String> = tree_data
+        .places
+        .iter()
+        .map(|place| (place.id, label(&place.name, "place", &mut used)))
+        .collect();
+    let mut used = HashSet::new();
+    let person_labels: HashMap<PersonId, String> = tree_data
+        .persons
+        .iter()
+        .map(|person| {
+            let name = person.display_name().unwrap_or_default();
+            (person.id, label(&name, "person", &mut used))
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    for place in &tree_data.places {
+        lines.push(format!(
+            "place {} {}",
+            place_labels[&place.id],
+            quote(&place.name)
+        ));
+        if let Some(parent) = place.parent.and_then(|parent| place_labels.get(&parent)) {
+            lines.push(format!("  in {}", parent));
+        }
+        if let Some(coordinates) = place.coordinates {
+            lines.push(format!(
+                "  at {} {}",
+                coordinates.latitude, coordinates.longitude
+            ));
+        }
+        for alt in &place.alternate_names {
+            let historical = if alt.historical { " historical" } else { "" };
+            lines.push(format!("  alias {}{}", quote(&alt.name), historical));
+        }
+    }
+    if !tree_data.places.is_empty() {
+        lines.push(String::new());
+    }
+
+    for person in &tree_data.persons {
+        lines.push(format!("person {}", person_labels[&person.id]));
+        for name in &person.names {
+            lines.push(format!("  name {}", print_name(name)));
+        }
+        let info = person.info.iter().flatten().sorted();
+        for (key, value) in info {
+            let value = match PlaceId::try_from(value.as_str()) {
+                Ok(id) if PLACE_KEYS.contains(&key.as_str()) => place_labels.get(&id),
+                _ => None,
+            }
+            .unwrap_or(value);
+            lines.push(format!("  info {} {}", quote(key), quote(value)));
+        }
+    }
+
+    for rel in &tree_data.relationships {
+        let labels = |ids: &mut dyn Iterator<Item = &PersonId>| {
+            ids.map(|id| person_labels[id].as_str()).join(" ")
+        };
+        lines.push(String::new());
+        lines.push("relationship".to_string());
+        if !rel.parents().is_empty() {
+            lines.push(format!(
+                "  parents {}",
+                labels(&mut rel.parents.iter().flatten())
+            ));
+        }
+        if !rel.children.is_empty() {
+            lines.push(format!("  children {}", labels(&mut rel.children.iter())));
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn print_name(name: &Name) -> String {
+    let name_type = format!("{:?}", name.name_type).to_lowercase();
+    let parts = [
+        ("given", &name.given),
+        ("surname", &name.surname),
+        ("prefix", &name.prefix),
+        ("suffix", &name.suffix),
+        ("from", &name.valid_from),
+        ("until", &name.valid_until),
+    ]
+    .into_iter()
+    .filter_map(|(keyword, part)| {
+        part.as_ref()
+            .map(|part| format!("{} {}", keyword, quote(part)))
+    });
+    let primary = name.primary.then(|| "primary".to_string());
+    std::iter::once(name_type)
+        .chain(parts)
+        .chain(primary)
+        .join(" ")
+}
+
+/// A label from the normalized words of a name, made unique with a number.
+fn label(name: &str, fallback: &str, used: &mut HashSet<String>) -> String {
+    let words = name
+        .split_whitespace()
+        .map(normalize)
+        .filter(|word| !word.is_empty())
+        .join("_");
+    let base = if words.is_empty() {
+        fallback.to_string()
+    } else {
+        words
+    };
+    let mut label = base.clone();
+    let mut number = 2;
+    while !used.insert(label.clone()) {
+        label = format!("{}_{}", base, number);
+        number += 1;
+    }
+    label
+}
+
+/// Quote a word, if it would not be read back as a single token.
+fn quote(word: &str) -> String {
+    if word.is_empty() || word.starts_with(['"', '#']) || word.contains(char::is_whitespace) {
+        let escaped = word
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        format!("\"{}\"", escaped)
+    } else {
+        word.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consistency;
+
+    fn read(file_name: &str) -> String {
+        std::fs::read_to_string(file_name).expect("Cannot read test file")
+    }
+
+    fn parse_error(text: &str) -> (usize, usize, String) {
+        let err = parse(text).expect_err("Must fail");
+        (err.line, err.column, err.kind.to_string())
+    }
+
+    #[test]
+    fn family() {
+        let tree_data = parse(&read("test/text/family.txt")).expect("Cannot parse test file");
+        consistency::check(&tree_data).expect("Must be consistent");
+        assert_eq!(2, tree_data.places.len());
+        assert_eq!(3, tree_data.persons.len());
+        assert_eq!(3, tree_data.relationships.len());
+        let berlin = &tree_data.places[1];
+        assert_eq!(Some(tree_data.places[0].id), berlin.parent);
+        assert!(berlin.alternate_names[0].historical);
+        let anna = &tree_data.persons[0];
+        assert_eq!(Some("Anna von Berg".to_string()), anna.display_name());
+        let info = anna.info.as_ref().expect("Info must exist");
+        assert_eq!(berlin.id.to_string(), info["@birthPlace"]);
+        assert_eq!("Say \"Hi\"", info["motto"]);
+        let rel = &tree_data.relationships[2];
+        assert_eq!([Some(anna.id), Some(tree_data.persons[1].id)], rel.parents);
+        assert_eq!(vec![tree_data.persons[2].id], rel.children);
+    }
+
+    #[test]
+    fn print_parsed() {
+        let text = read("test/text/family.txt");
+        let tree_data = parse(&text).expect("Cannot parse test file");
+        let printed = print(&tree_data);
+        let without_comments = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        assert_eq!(without_comments, printed);
+    }
+
+    #[test]
+    fn print_json() {
+        let json = read("test/graph/double_inheritance.json");
+        let tree_data = crate::io::read(&json).expect("Cannot read test file");
+        let parsed = parse(&print(&tree_data)).expect("Cannot parse printed tree");
+        consistency::check(&parsed).expect("Must be consistent");
+        assert_eq!(tree_data.persons.len(), parsed.persons.len());
+        assert_eq!(tree_data.relationships.len(), parsed.relationships.len());
+        // printing again yields the same text, because labels do not depend on ids
+        assert_eq!(print(&tree_data), print(&parsed));
+    }
+
+    #[test]
+    fn multi_line_values() {
+        let mut tree_data = parse(&read("test/text/family.txt")).expect("Cannot parse test file");
+        let note = "First line\nsecond \"line\"\r\nthird line\\".to_string();
+        let info = tree_data.persons[0].info.as_mut().expect("Info must exist");
+        info.insert("note".to_string(), note.clone());
+        let printed = print(&tree_data);
+        // the value stays on a single line
+        assert!(printed.contains(r#"  info note "First line\nsecond \"line\"\r\nthird line\\""#));
+        let parsed = parse(&printed).expect("Cannot parse printed tree");
+        let info = parsed.persons[0].info.as_ref().expect("Info must exist");
+        assert_eq!(note, info["note"]);
+        assert_eq!(printed, print(&parsed));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (
+                "person anna\n  info note \"open",
+                2,
+                13,
+                "Unterminated string",
+            ),
+            (
+                "person anna\n  info note \"a\\b\"",
+                2,
+                15,
+                "Invalid escape sequence",
+            ),
+            ("  info note a", 1, 3, "Indented line outside of a block"),
+            ("family", 1, 1, "Unknown statement \"family\""),
+            ("person anna\n  in berlin", 2, 3, "Unknown statement \"in\""),
+            ("person", 1, 7, "Missing label"),
+            ("person anna bert", 1, 13, "Unexpected \"bert\""),
+            (
+                "person anna\nperson anna",
+                2,
+                8,
+                "Label \"anna\" is already defined",
+            ),
+            (
+                "person anna\nrelationship\n  parents bert",
+                3,
+                11,
+                "Unknown label \"bert\"",
+            ),
+            (
+                "place berlin Berlin\n  in germany",
+                2,
+                6,
+                "Unknown label \"germany\"",
+            ),
+            (
+                "place berlin Berlin\n  at 52.5 east",
+                2,
+                11,
+                "Invalid number \"east\"",
+            ),
+            (
+                "person anna\n  name maiden",
+                2,
+                8,
+                "Unknown name type \"maiden\"",
+            ),
+            ("person anna\n  name birth given", 2, 19, "Missing value"),
+        ];
+        for (text, line, column, message) in cases {
+            assert_eq!(
+                (line, column, message.to_string()),
+                parse_error(text),
+                "{text}"
+            );
+        }
+    }
+}
@@ -1,16 +1,31 @@
 use crate::{
+    changelog::{LogEntry, Operation, Snapshot, Touched},
     consistency,
-    error::{Error, InputError, MergeConflict},
-    io, Person, PersonId, Relationship, RelationshipId, TreeData,
+    diff::{self, TreeDiff},
+    error::{ConsistencyError, Error, InputError, MergeConflict, SchemaError},
+    io::{self, Format},
+    merge::{self, Conflict, Merge},
+    name::Name,
+    place::{self, AlternateName, Coordinates, Place, PlaceId, PlaceMerge, PLACE_KEYS},
+    schema, text, Person, PersonId, Relationship, RelationshipId, TreeData,
 };
 use itertools::Itertools;
 use specta::Type;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
 
 /// The central datatype, containing **consistent** tree data.
-#[derive(Debug, Type)]
+#[derive(Clone, Debug, Type)]
 pub struct FamilyTree {
     tree_data: TreeData,
+    format: Format,
+    canonical: bool,
+    log: Vec<LogEntry>,
+    author: Option<String>,
+    /// Consistency is checked at the end of a transaction instead of after each mutation.
+    deferred_check: bool,
 }
 
 impl FamilyTree {
@@ -18,9 +33,105 @@ impl FamilyTree {
         Self::default()
     }
 
+    /// Load a tree in any supported format, which is detected automatically.
+    pub fn load(bytes: &[u8]) -> Result<Self, Error> {
+        Self::read_from(bytes)
+    }
+
+    /// Read a tree in any supported format, which is detected automatically.
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let (tree_data, format) = io::read_from(reader)?;
+        consistency::check(&tree_data)?;
+        Ok(Self {
+            tree_data,
+            format,
+            canonical: true,
+            log: Vec::new(),
+            author: None,
+            deferred_check: false,
+        })
+    }
+
     /// Serialize the tree data to JSON.
     pub fn save(&self) -> Result<String, Error> {
-        io::write(&self.tree_data)
+        if self.canonical {
+            io::write(&io::canonicalize(&self.tree_data))
+        } else {
+            io::write(&self.tree_data)
+        }
+    }
+
+    /// Serialize the tree data in the format of the tree.
+    pub fn save_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Write the tree data in the format of the tree.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        if self.canonical {
+            io::write_to(&io::canonicalize(&self.tree_data), writer, self.format)
+        } else {
+            io::write_to(&self.tree_data, writer, self.format)
+        }
+    }
+
+    /// Compare the tree with a newer version of it.
+    pub fn diff(&self, new: &FamilyTree) -> TreeDiff {
+        diff::diff(&self.tree_data, &new.tree_data)
+    }
+
+    /// Merge two concurrently edited versions of a tree with their common base.
+    ///
+    /// The merged tree keeps the format of our side.
+    /// Conflicts are resolved in favor of our side.
+    pub fn merge_versions(base: &Self, ours: &Self, theirs: &Self) -> (Self, Vec<Conflict>) {
+        let Merge {
+            tree_data,
+            conflicts,
+        } = merge::merge(&base.tree_data, &ours.tree_data, &theirs.tree_data);
+        let tree = Self {
+            tree_data,
+            format: ours.format,
+            canonical: ours.canonical,
+            log: Vec::new(),
+            author: ours.author.clone(),
+            deferred_check: false,
+        };
+        (tree, conflicts)
+    }
+
+    /// Parse a tree in the human-editable text format.
+    pub fn from_text(text: &str) -> Result<Self, Error> {
+        Self::try_from(text::parse(text)?)
+    }
+
+    /// Print the tree in the human-editable text format.
+    pub fn to_text(&self) -> String {
+        text::print(&self.tree_data)
+    }
+
+    /// The format the tree was loaded in, which is used for saving.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Whether persons, relationships and places are saved in a stable order.
+    pub fn canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Save in a stable order (the default), or keep the order of insertion.
+    ///
+    /// In a stable order, saving an unchanged tree always produces the same output.
+    /// Info keys are sorted either way, because a hash map has no order of insertion.
+    pub fn set_canonical(&mut self, canonical: bool) {
+        self.canonical = canonical;
     }
 
     pub fn get_persons(&self) -> &[Person] {
@@ -31,9 +142,338 @@ impl FamilyTree {
         self.tree_data.relationships.as_slice()
     }
 
+    pub fn get_places(&self) -> &[Place] {
+        self.tree_data.places.as_slice()
+    }
+
+    /// Mutations recorded since the tree was loaded.
+    pub fn log(&self) -> &[LogEntry] {
+        &self.log
+    }
+
+    /// Remove and return the recorded mutations, e.g. after they were stored.
+    pub fn take_log(&mut self) -> Vec<LogEntry> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Author of the following mutations.
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.author = author;
+    }
+
+    /// Apply the changes of log entries, e.g. onto an older snapshot of the tree.
+    ///
+    /// Either all entries are applied or the tree stays unchanged.
+    /// The entries are added to the log of the tree.
+    pub fn replay(&mut self, entries: &[LogEntry]) -> Result<(), Error> {
+        let mut tree_data = self.tree_data.clone();
+        for entry in entries {
+            entry.apply(&mut tree_data)?;
+        }
+        consistency::check(&tree_data)?;
+        self.tree_data = tree_data;
+        self.log.extend_from_slice(entries);
+        Ok(())
+    }
+
+    /// Revert the last recorded mutation, which is removed from the log.
+    ///
+    /// Returns the reverted operation or `None`, if nothing was recorded.
+    pub fn undo(&mut self) -> Result<Option<Operation>, Error> {
+        let Some(entry) = self.log.last() else {
+            return Ok(None);
+        };
+        let mut tree_data = self.tree_data.clone();
+        entry.revert(&mut tree_data)?;
+        consistency::check(&tree_data)?;
+        self.tree_data = tree_data;
+        Ok(self.log.pop().map(|entry| entry.operation))
+    }
+
+    /// Run several mutations as a transaction.
+    ///
+    /// Consistency is checked once after all mutations, so intermediate states may be inconsistent.
+    /// If a mutation fails or the result is inconsistent,
+    /// the tree data and the log are restored and the error is returned.
+    pub fn transaction<T, E: From<ConsistencyError>>(
+        &mut self,
+        mutations: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let tree_data = self.tree_data.clone();
+        let log_len = self.log.len();
+        let deferred_check = std::mem::replace(&mut self.deferred_check, true);
+        let result = mutations(self);
+        self.deferred_check = deferred_check;
+        let result = result.and_then(|value| {
+            self.check()?;
+            Ok(value)
+        });
+        if result.is_err() {
+            self.tree_data = tree_data;
+            self.log.truncate(log_len);
+        }
+        result
+    }
+
+    /// Check the consistency of the tree data, unless it is deferred to the end of a transaction.
+    fn check(&self) -> Result<(), ConsistencyError> {
+        if self.deferred_check {
+            Ok(())
+        } else {
+            consistency::check(&self.tree_data)
+        }
+    }
+
+    /// Run a mutation and record its changes, if it succeeds.
+    ///
+    /// Only the touched items are compared, so the mutation must not change other ones
+    /// and has to append new items.
+    fn record<T>(
+        &mut self,
+        operation: Operation,
+        touched: Touched,
+        mutation: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let snapshot = Snapshot::new(&self.tree_data, touched);
+        let result = mutation(self)?;
+        let entry = LogEntry::since(operation, self.author.clone(), &snapshot, &self.tree_data);
+        self.push_log_entry(entry);
+        Ok(result)
+    }
+
+    fn push_log_entry(&mut self, entry: LogEntry) {
+        if !entry.changes.is_empty() {
+            self.log.push(entry);
+        }
+    }
+
+    /// Touched items of a mutation of the person.
+    fn touched_person(person_id: PersonId) -> Touched {
+        Touched {
+            persons: vec![person_id],
+            ..Default::default()
+        }
+    }
+
+    /// Touched items of a mutation of the relationship.
+    fn touched_relationship(relationship_id: RelationshipId) -> Touched {
+        Touched {
+            relationships: vec![relationship_id],
+            ..Default::default()
+        }
+    }
+
+    /// Touched items of a mutation of the place.
+    fn touched_place(place_id: PlaceId) -> Touched {
+        Touched {
+            places: vec![place_id],
+            ..Default::default()
+        }
+    }
+
+    /// Touched items of removing or merging the persons, including their relationships.
+    fn touched_persons_and_relationships(&self, person_ids: &[PersonId]) -> Touched {
+        let relationships = self
+            .tree_data
+            .relationships
+            .iter()
+            .filter(|rel| rel.persons().iter().any(|pid| person_ids.contains(pid)))
+            .map(|rel| rel.id)
+            .collect();
+        Touched {
+            persons: person_ids.to_vec(),
+            relationships,
+            places: Vec::new(),
+        }
+    }
+
     pub fn add_parent(
         &mut self,
         relationship_id: RelationshipId,
+    ) -> Result<(PersonId, RelationshipId), Error> {
+        self.record(
+            Operation::AddParent,
+            Self::touched_relationship(relationship_id),
+            |tree| tree.add_parent_unrecorded(relationship_id),
+        )
+    }
+
+    pub fn add_child(&mut self, relationship_id: RelationshipId) -> Result<PersonId, Error> {
+        self.record(
+            Operation::AddChild,
+            Self::touched_relationship(relationship_id),
+            |tree| tree.add_child_unrecorded(relationship_id),
+        )
+    }
+
+    pub fn add_new_relationship(&mut self, person_id: PersonId) -> Result<RelationshipId, Error> {
+        self.record(Operation::AddNewRelationship, Touched::default(), |tree| {
+            tree.add_new_relationship_unrecorded(person_id)
+        })
+    }
+
+    pub fn add_relationship_with_partner(
+        &mut self,
+        person_id: PersonId,
+        partner_id: PersonId,
+    ) -> Result<RelationshipId, Error> {
+        self.record(
+            Operation::AddRelationshipWithPartner,
+            Touched::default(),
+            |tree| tree.add_relationship_with_partner_unrecorded(person_id, partner_id),
+        )
+    }
+
+    pub fn remove_person(&mut self, person_id: PersonId) -> Result<(), Error> {
+        self.record(
+            Operation::RemovePerson,
+            self.touched_persons_and_relationships(&[person_id]),
+            |tree| tree.remove_person_unrecorded(person_id),
+        )
+    }
+
+    pub fn merge_person(
+        &mut self,
+        person_id1: PersonId,
+        person_id2: PersonId,
+    ) -> Result<(), Error> {
+        self.record(
+            Operation::MergePerson,
+            self.touched_persons_and_relationships(&[person_id1, person_id2]),
+            |tree| tree.merge_person_unrecorded(person_id1, person_id2),
+        )
+    }
+
+    pub fn insert_info(
+        &mut self,
+        person_id: PersonId,
+        key: String,
+        value: String,
+    ) -> Result<(), Error> {
+        self.record(
+            Operation::InsertInfo,
+            Self::touched_person(person_id),
+            |tree| tree.insert_info_unrecorded(person_id, key, value),
+        )
+    }
+
+    pub fn remove_info(&mut self, person_id: PersonId, key: &str) -> Result<String, Error> {
+        self.record(
+            Operation::RemoveInfo,
+            Self::touched_person(person_id),
+            |tree| tree.remove_info_unrecorded(person_id, key),
+        )
+    }
+
+    /// Add a name to a person, returning its index.
+    ///
+    /// If the new name is primary, other names lose their primary mark.
+    pub fn add_name(&mut self, person_id: PersonId, name: Name) -> Result<usize, Error> {
+        self.record(
+            Operation::AddName,
+            Self::touched_person(person_id),
+            |tree| tree.add_name_unrecorded(person_id, name),
+        )
+    }
+
+    pub fn update_name(
+        &mut self,
+        person_id: PersonId,
+        index: usize,
+        name: Name,
+    ) -> Result<(), Error> {
+        self.record(
+            Operation::UpdateName,
+            Self::touched_person(person_id),
+            |tree| tree.update_name_unrecorded(person_id, index, name),
+        )
+    }
+
+    pub fn remove_name(&mut self, person_id: PersonId, index: usize) -> Result<Name, Error> {
+        self.record(
+            Operation::RemoveName,
+            Self::touched_person(person_id),
+            |tree| tree.remove_name_unrecorded(person_id, index),
+        )
+    }
+
+    pub fn set_primary_name(&mut self, person_id: PersonId, index: usize) -> Result<(), Error> {
+        self.record(
+            Operation::SetPrimaryName,
+            Self::touched_person(person_id),
+            |tree| tree.set_primary_name_unrecorded(person_id, index),
+        )
+    }
+
+    pub fn add_place(&mut self, name: String, parent: Option<PlaceId>) -> Result<PlaceId, Error> {
+        self.record(Operation::AddPlace, Touched::default(), |tree| {
+            tree.add_place_unrecorded(name, parent)
+        })
+    }
+
+    pub fn set_place_coordinates(
+        &mut self,
+        place_id: PlaceId,
+        coordinates: Option<Coordinates>,
+    ) -> Result<(), Error> {
+        self.record(
+            Operation::SetPlaceCoordinates,
+            Self::touched_place(place_id),
+            |tree| tree.set_place_coordinates_unrecorded(place_id, coordinates),
+        )
+    }
+
+    pub fn add_place_name(&mut self, place_id: PlaceId, name: AlternateName) -> Result<(), Error> {
+        self.record(
+            Operation::AddPlaceName,
+            Self::touched_place(place_id),
+            |tree| tree.add_place_name_unrecorded(place_id, name),
+        )
+    }
+
+    pub fn remove_place(&mut self, place_id: PlaceId) -> Result<(), Error> {
+        self.record(
+            Operation::RemovePlace,
+            Self::touched_place(place_id),
+            |tree| tree.remove_place_unrecorded(place_id),
+        )
+    }
+
+    /// Reference a place in the info of a person.
+    ///
+    /// The key must be one of the `PLACE_KEYS`.
+    pub fn set_place(
+        &mut self,
+        person_id: PersonId,
+        key: &str,
+        place_id: PlaceId,
+    ) -> Result<(), Error> {
+        self.record(
+            Operation::SetPlace,
+            Self::touched_person(person_id),
+            |tree| tree.set_place_unrecorded(person_id, key, place_id),
+        )
+    }
+
+    /// Merge places with different spellings of the same name
+    /// and replace free text in place keys by references to the registry.
+    pub fn normalize_places(&mut self) -> Vec<PlaceMerge> {
+        // all persons and places may change, so the whole tree is compared
+        let old = self.tree_data.clone();
+        let merges = self.normalize_places_unrecorded();
+        let entry = LogEntry::new(
+            Operation::NormalizePlaces,
+            self.author.clone(),
+            &old,
+            &self.tree_data,
+        );
+        self.push_log_entry(entry);
+        merges
+    }
+
+    fn add_parent_unrecorded(
+        &mut self,
+        relationship_id: RelationshipId,
     ) -> Result<(PersonId, RelationshipId), Error> {
         let rel = self
             .tree_data
@@ -50,7 +490,7 @@ impl FamilyTree {
 
         self.tree_data.persons.push(parent);
         self.tree_data.relationships.push(new_rel);
-        if let Err(err) = consistency::check(&self.tree_data) {
+        if let Err(err) = self.check() {
             self.tree_data.persons.pop();
             self.tree_data.relationships.pop();
             return Err(err.into());
@@ -59,7 +499,7 @@ impl FamilyTree {
         Ok((new_pid, new_rid))
     }
 
-    pub fn add_child(&mut self, relationship_id: RelationshipId) -> Result<PersonId, Error> {
+    fn add_child_unrecorded(&mut self, relationship_id: RelationshipId) -> Result<PersonId, Error> {
         let rel_opt = self
             .tree_data
             .relationships
@@ -71,7 +511,7 @@ impl FamilyTree {
         let new_id = new_person.id;
         self.tree_data.persons.push(new_person);
         rel.children.push(new_id);
-        if let Err(err) = consistency::check(&self.tree_data) {
+        if let Err(err) = self.check() {
             self.tree_data.persons.pop();
             return Err(err.into());
         }
@@ -79,12 +519,15 @@ impl FamilyTree {
         Ok(new_id)
     }
 
-    pub fn add_new_relationship(&mut self, person_id: PersonId) -> Result<RelationshipId, Error> {
+    fn add_new_relationship_unrecorded(
+        &mut self,
+        person_id: PersonId,
+    ) -> Result<RelationshipId, Error> {
         self.validate_person(person_id)?;
         let new_rel = Relationship::new(Some(person_id), None, vec![]);
         let new_rid = new_rel.id;
         self.tree_data.relationships.push(new_rel);
-        if let Err(err) = consistency::check(&self.tree_data) {
+        if let Err(err) = self.check() {
             self.tree_data.relationships.pop();
             return Err(err.into());
         }
@@ -92,7 +535,7 @@ impl FamilyTree {
         Ok(new_rid)
     }
 
-    pub fn add_relationship_with_partner(
+    fn add_relationship_with_partner_unrecorded(
         &mut self,
         person_id: PersonId,
         partner_id: PersonId,
@@ -102,7 +545,7 @@ impl FamilyTree {
         let new_rel = Relationship::new(Some(person_id), Some(partner_id), Vec::new());
         let new_rid = new_rel.id;
         self.tree_data.relationships.push(new_rel);
-        if let Err(err) = consistency::check(&self.tree_data) {
+        if let Err(err) = self.check() {
             self.tree_data.relationships.pop();
             return Err(err.into());
         }
@@ -110,7 +553,7 @@ impl FamilyTree {
         Ok(new_rid)
     }
 
-    pub fn remove_person(&mut self, person_id: PersonId) -> Result<(), Error> {
+    fn remove_person_unrecorded(&mut self, person_id: PersonId) -> Result<(), Error> {
         let persons_index = self
             .tree_data
             .persons
@@ -141,7 +584,7 @@ impl FamilyTree {
             // delete rel if it is now empty (even if the partner is still there)
             !empty
         });
-        if consistency::check(&self.tree_data).is_err() {
+        if self.check().is_err() {
             // restore old state
             self.tree_data.persons.push(backup_person);
             for backup_rel in backup_rels {
@@ -161,7 +604,7 @@ impl FamilyTree {
         Ok(())
     }
 
-    pub fn merge_person(
+    fn merge_person_unrecorded(
         &mut self,
         person_id1: PersonId,
         person_id2: PersonId,
@@ -194,6 +637,27 @@ impl FamilyTree {
             }
         }
 
+        fn merge_names(person1: &Person, person2: &Person) -> Vec<Name> {
+            let mut names = person1.names.clone();
+            let has_primary = names.iter().any(|name| name.primary);
+            for name in &person2.names {
+                let name = Name {
+                    primary: name.primary && !has_primary,
+                    ..name.clone()
+                };
+                let is_duplicate = names.iter().any(|existing| {
+                    Name {
+                        primary: name.primary,
+                        ..existing.clone()
+                    } == name
+                });
+                if !is_duplicate {
+                    names.push(name);
+                }
+            }
+            names
+        }
+
         const fn merge_parents(
             rel1: &Relationship,
             rel2: &Relationship,
@@ -260,6 +724,7 @@ impl FamilyTree {
         let (pos1, person1) = self.find_pos_and_person(person_id1)?;
         let (pos2, person2) = self.find_pos_and_person(person_id2)?;
         let merged_info = merge_info(person1, person2)?;
+        let merged_names = merge_names(person1, person2);
 
         let persons = &mut self.tree_data.persons;
         let rels = &mut self.tree_data.relationships;
@@ -278,6 +743,7 @@ impl FamilyTree {
         // create new person and parent rel
         let mut new_person = Person::new();
         new_person.info = merged_info;
+        new_person.names = merged_names;
         merged_siblings.push(new_person.id);
         let new_rel = Relationship::new(merged_parents[0], merged_parents[1], merged_siblings);
 
@@ -320,7 +786,7 @@ impl FamilyTree {
         persons.push(new_person);
         rels.push(new_rel);
 
-        if let Err(err) = consistency::check(&self.tree_data) {
+        if let Err(err) = self.check() {
             // restore old state
             let persons = &mut self.tree_data.persons;
             let rels = &mut self.tree_data.relationships;
@@ -335,12 +801,13 @@ impl FamilyTree {
         Ok(())
     }
 
-    pub fn insert_info(
+    fn insert_info_unrecorded(
         &mut self,
         person_id: PersonId,
         key: String,
         value: String,
     ) -> Result<(), Error> {
+        schema::validate(&key, &value, &self.tree_data.places)?;
         let person = self.find_person_mut(person_id)?;
         if let Some(info) = &mut person.info {
             info.insert(key, value);
@@ -351,7 +818,7 @@ impl FamilyTree {
         Ok(())
     }
 
-    pub fn remove_info(&mut self, person_id: PersonId, key: &str) -> Result<String, Error> {
+    fn remove_info_unrecorded(&mut self, person_id: PersonId, key: &str) -> Result<String, Error> {
         let person = self.find_person_mut(person_id)?;
         let info = person.info.as_mut().ok_or(InputError::NoInfo)?;
         let value = info.remove(key).ok_or(InputError::InvalidKey)?;
@@ -359,6 +826,199 @@ impl FamilyTree {
         Ok(value)
     }
 
+    /// All persons sorted by their primary name.
+    pub fn sorted_persons(&self) -> Vec<&Person> {
+        self.tree_data
+            .persons
+            .iter()
+            .sorted_by_cached_key(|person| person.sort_key())
+            .collect()
+    }
+
+    /// Persons with a name matching the query, sorted by their primary name.
+    pub fn search(&self, query: &str) -> Vec<&Person> {
+        self.sorted_persons()
+            .into_iter()
+            .filter(|person| person.matches_name(query))
+            .collect()
+    }
+
+    fn add_name_unrecorded(&mut self, person_id: PersonId, name: Name) -> Result<usize, Error> {
+        validate_name(&name)?;
+        let person = self.find_person_mut(person_id)?;
+        if name.primary {
+            person
+                .names
+                .iter_mut()
+                .for_each(|name| name.primary = false);
+        }
+        person.names.push(name);
+
+        Ok(person.names.len() - 1)
+    }
+
+    fn update_name_unrecorded(
+        &mut self,
+        person_id: PersonId,
+        index: usize,
+        name: Name,
+    ) -> Result<(), Error> {
+        validate_name(&name)?;
+        let person = self.find_person_mut(person_id)?;
+        if index >= person.names.len() {
+            return Err(InputError::InvalidNameIndex.into());
+        }
+        if name.primary {
+            person
+                .names
+                .iter_mut()
+                .for_each(|name| name.primary = false);
+        }
+        person.names[index] = name;
+
+        Ok(())
+    }
+
+    fn remove_name_unrecorded(&mut self, person_id: PersonId, index: usize) -> Result<Name, Error> {
+        let person = self.find_person_mut(person_id)?;
+        if index >= person.names.len() {
+            return Err(InputError::InvalidNameIndex.into());
+        }
+
+        Ok(person.names.remove(index))
+    }
+
+    fn set_primary_name_unrecorded(
+        &mut self,
+        person_id: PersonId,
+        index: usize,
+    ) -> Result<(), Error> {
+        let person = self.find_person_mut(person_id)?;
+        if index >= person.names.len() {
+            return Err(InputError::InvalidNameIndex.into());
+        }
+        person
+            .names
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, name)| name.primary = i == index);
+
+        Ok(())
+    }
+
+    fn add_place_unrecorded(
+        &mut self,
+        name: String,
+        parent: Option<PlaceId>,
+    ) -> Result<PlaceId, Error> {
+        if let Some(parent) = parent {
+            self.find_place(parent)?;
+        }
+        let place = Place::new(name, parent);
+        let new_id = place.id;
+        self.tree_data.places.push(place);
+
+        Ok(new_id)
+    }
+
+    fn set_place_coordinates_unrecorded(
+        &mut self,
+        place_id: PlaceId,
+        coordinates: Option<Coordinates>,
+    ) -> Result<(), Error> {
+        if coordinates.is_some_and(|coordinates| !coordinates.is_valid()) {
+            return Err(ConsistencyError::InvalidCoordinates.into());
+        }
+        self.find_place_mut(place_id)?.coordinates = coordinates;
+
+        Ok(())
+    }
+
+    fn add_place_name_unrecorded(
+        &mut self,
+        place_id: PlaceId,
+        name: AlternateName,
+    ) -> Result<(), Error> {
+        self.find_place_mut(place_id)?.alternate_names.push(name);
+
+        Ok(())
+    }
+
+    fn remove_place_unrecorded(&mut self, place_id: PlaceId) -> Result<(), Error> {
+        let position = self
+            .tree_data
+            .places
+            .iter()
+            .position(|place| place.id == place_id)
+            .ok_or(InputError::InvalidPlaceId)?;
+        let has_children = self
+            .tree_data
+            .places
+            .iter()
+            .any(|place| place.parent == Some(place_id));
+        let id = place_id.to_string();
+        let is_referenced = self
+            .tree_data
+            .persons
+            .iter()
+            .filter_map(|person| person.info.as_ref())
+            .any(|info| PLACE_KEYS.iter().any(|key| info.get(*key) == Some(&id)));
+        if has_children || is_referenced {
+            return Err(InputError::PlaceInUse.into());
+        }
+        self.tree_data.places.remove(position);
+
+        Ok(())
+    }
+
+    fn set_place_unrecorded(
+        &mut self,
+        person_id: PersonId,
+        key: &str,
+        place_id: PlaceId,
+    ) -> Result<(), Error> {
+        if !PLACE_KEYS.contains(&key) {
+            return Err(InputError::InvalidKey.into());
+        }
+        self.find_place(place_id)?;
+        self.insert_info_unrecorded(person_id, key.to_string(), place_id.to_string())
+    }
+
+    /// The place followed by all places it is part of.
+    pub fn place_hierarchy(&self, place_id: PlaceId) -> Result<Vec<&Place>, Error> {
+        let mut hierarchy = vec![self.find_place(place_id)?];
+        while let Some(parent) = hierarchy.last().and_then(|place| place.parent) {
+            hierarchy.push(self.find_place(parent)?);
+        }
+
+        Ok(hierarchy)
+    }
+
+    fn normalize_places_unrecorded(&mut self) -> Vec<PlaceMerge> {
+        let TreeData {
+            persons, places, ..
+        } = &mut self.tree_data;
+        let merges = place::merge_duplicates(places, persons);
+        place::link_references(places, persons);
+        merges
+    }
+
+    fn find_place(&self, place_id: PlaceId) -> Result<&Place, InputError> {
+        self.tree_data
+            .places
+            .iter()
+            .find(|place| place.id == place_id)
+            .ok_or(InputError::InvalidPlaceId)
+    }
+
+    fn find_place_mut(&mut self, place_id: PlaceId) -> Result<&mut Place, InputError> {
+        self.tree_data
+            .places
+            .iter_mut()
+            .find(|place| place.id == place_id)
+            .ok_or(InputError::InvalidPlaceId)
+    }
+
     fn validate_person(&self, person_id: PersonId) -> Result<(), InputError> {
         if self
             .tree_data
@@ -395,12 +1055,33 @@ impl FamilyTree {
     }
 }
 
+fn validate_name(name: &Name) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(InputError::EmptyName.into());
+    }
+    let dates = [&name.valid_from, &name.valid_until];
+    if dates
+        .into_iter()
+        .flatten()
+        .any(|date| !schema::is_date(date))
+    {
+        return Err(SchemaError::InvalidDate.into());
+    }
+
+    Ok(())
+}
+
 impl Default for FamilyTree {
     fn default() -> Self {
         let initial_person = Person::new();
         let initial_rels = vec![Relationship::new(None, None, vec![initial_person.id])];
         Self {
             tree_data: TreeData::new(initial_rels, vec![initial_person]),
+            format: Format::default(),
+            canonical: true,
+            log: Vec::new(),
+            author: None,
+            deferred_check: false,
         }
     }
 }
@@ -410,7 +1091,14 @@ impl TryFrom<TreeData> for FamilyTree {
 
     fn try_from(tree_data: TreeData) -> Result<Self, Self::Error> {
         consistency::check(&tree_data)?;
-        Ok(Self { tree_data })
+        Ok(Self {
+            tree_data,
+            format: Format::default(),
+            canonical: true,
+            log: Vec::new(),
+            author: None,
+            deferred_check: false,
+        })
     }
 }
 
@@ -420,7 +1108,14 @@ impl TryFrom<&str> for FamilyTree {
     fn try_from(json_str: &str) -> Result<Self, Self::Error> {
         let tree_data = io::read(json_str)?;
         consistency::check(&tree_data)?;
-        Ok(Self { tree_data })
+        Ok(Self {
+            tree_data,
+            format: Format::Json,
+            canonical: true,
+            log: Vec::new(),
+            author: None,
+            deferred_check: false,
+        })
     }
 }
 
@@ -0,0 +1,631 @@
+//! HTTP/JSON API for family trees stored in files.
+//!
+//! Every tree is served under the stem of its file name:
+//!
+//! | Method | Path                          | Result                                  |
+//! |--------|-------------------------------|-----------------------------------------|
+//! | GET    | `/trees`                      | names and ETags of all trees            |
+//! | GET    | `/trees/{name}`               | persons, relationships and places       |
+//! | GET    | `/trees/{name}/persons`       | all persons                             |
+//! | GET    | `/trees/{name}/persons/{ref}` | a person by id, id prefix or name       |
+//! | GET    | `/trees/{name}/relationships` | all relationships                       |
+//! | GET    | `/trees/{name}/places`        | all places                              |
+//! | GET    | `/trees/{name}/grid`          | the grid of `baumstamm_grid::generate`  |
+//! | GET    | `/trees/{name}/log`           | the change log next to the file         |
+//! | GET    | `/trees/{name}/search?q=…`    | persons matching a name                 |
+//! | GET    | `/info-keys`                  | keys declared in the schema             |
+//! | POST   | `/trees/{name}/commands`      | apply a `Command` or an array of them   |
+//!
+//! Responses about a tree carry an ETag of its file, the SHA-256 of its content.
+//! Commands must send it as `If-Match`, so changes based on an outdated tree are rejected
+//! with `412 Precondition Failed` instead of overwriting the changes of another client.
+//! An array of commands is applied as one transaction, the file is saved after every request.
+//!
+//! Before a tree is used, it is reloaded, if another program like the CLI or `git pull`
+//! changed its file, so these changes get a new ETag as well.
+
+use baumstamm_lib::{
+    changelog,
+    command::Command,
+    error::{Error as LibError, InputError},
+    storage, FamilyTree,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, error::Error, fs, io, net::SocketAddr, path::PathBuf};
+use tiny_http::{Header, Method, Request, Response};
+
+/// A tree and the file it is saved to.
+struct Entry {
+    path: PathBuf,
+    tree: FamilyTree,
+    /// Content of the file, when it was last read or written.
+    disk: Vec<u8>,
+    etag: String,
+}
+
+impl Entry {
+    fn load(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let disk = fs::read(&path)?;
+        let tree = FamilyTree::load(&disk)?;
+        Ok(Self {
+            path,
+            tree,
+            etag: etag(&disk),
+            disk,
+        })
+    }
+
+    /// Reload the tree, if its file was changed by another program.
+    fn reload_if_modified(&mut self) -> Result<(), LibError> {
+        // a missing file is written again by the next save
+        let Ok(disk) = fs::read(&self.path) else {
+            return Ok(());
+        };
+        if disk != self.disk {
+            self.tree = FamilyTree::load(&disk)?;
+            self.etag = etag(&disk);
+            self.disk = disk;
+        }
+        Ok(())
+    }
+
+    /// Save the tree and append its recorded changes to the log next to it.
+    fn save(&mut self) -> Result<(), LibError> {
+        let disk = self.tree.save_bytes()?;
+        storage::save(&self.path, &disk)?;
+        self.etag = etag(&disk);
+        self.disk = disk;
+        changelog::append_to_file(&changelog::log_path(&self.path), &self.tree.take_log())?;
+        Ok(())
+    }
+}
+
+/// ETag of the file content, which stays the same across builds of the server.
+fn etag(disk: &[u8]) -> String {
+    let hash = Sha256::digest(disk)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("\"{}\"", hash)
+}
+
+/// A response before it is sent.
+#[derive(Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Value,
+    pub etag: Option<String>,
+}
+
+impl Reply {
+    fn ok(body: Value, etag: &str) -> Self {
+        Self {
+            status: 200,
+            body,
+            etag: Some(etag.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            body: json!({ "error": { "message": message.to_string() } }),
+            etag: None,
+        }
+    }
+
+    fn lib_error(err: LibError) -> Self {
+        let status = match err {
+            LibError::Input(_) | LibError::Schema(_) | LibError::Serialization(_) => 400,
+            LibError::Consistency(_) | LibError::MergeConflict(_) => 409,
+            _ => 500,
+        };
+        Self {
+            status,
+            body: json!({ "error": { "message": err.to_string(), "details": err } }),
+            etag: None,
+        }
+    }
+}
+
+/// A request reduced to the parts the API uses.
+pub struct ApiRequest<'a> {
+    pub method: &'a Method,
+    /// Path with query.
+    pub url: &'a str,
+    pub if_match: Option<&'a str>,
+    pub if_none_match: Option<&'a str>,
+    pub body: &'a str,
+}
+
+/// Trees served by name.
+pub struct Trees(BTreeMap<String, Entry>);
+
+impl Trees {
+    /// Load the tree files, which are named by their file stem.
+    pub fn load(paths: impl IntoIterator<Item = PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let mut trees = BTreeMap::new();
+        for path in paths {
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| format!("Invalid file name \"{}\"", path.display()))?
+                .to_string();
+            if trees.contains_key(&name) {
+                return Err(format!("More than one tree named \"{}\"", name).into());
+            }
+            trees.insert(name, Entry::load(path)?);
+        }
+        Ok(Self(trees))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Answer a request.
+    pub fn handle(&mut self, request: &ApiRequest) -> Reply {
+        let (path, query) = request.url.split_once('?').unwrap_or((request.url, ""));
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            // a plus is only a space in the query
+            .map(|segment| decode(&segment.replace('+', "%2B")))
+            .collect::<Vec<_>>();
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        match (request.method, segments.as_slice()) {
+            (Method::Get, ["info-keys"]) => Reply {
+                status: 200,
+                body: json!(baumstamm_lib::schema::KEYS),
+                etag: None,
+            },
+            (Method::Get, ["trees"]) => {
+                // files, which cannot be loaded, are reported by the requests about them
+                for entry in self.0.values_mut() {
+                    let _ = entry.reload_if_modified();
+                }
+                let trees = self
+                    .0
+                    .iter()
+                    .map(|(name, entry)| json!({ "name": name, "etag": entry.etag }))
+                    .collect::<Vec<_>>();
+                Reply {
+                    status: 200,
+                    body: json!(trees),
+                    etag: None,
+                }
+            }
+            (method, ["trees", name, rest @ ..]) => {
+                let Some(entry) = self.0.get_mut(*name) else {
+                    return Reply::error(404, format!("Unknown tree \"{}\"", name));
+                };
+                if let Err(err) = entry.reload_if_modified() {
+                    let message = format!("The file was changed and cannot be loaded: {}", err);
+                    return Reply::error(409, message);
+                }
+                match method {
+                    Method::Get => {
+                        if request.if_none_match == Some(entry.etag.as_str()) {
+                            return Reply {
+                                status: 304,
+                                body: Value::Null,
+                                etag: Some(entry.etag.clone()),
+                            };
+                        }
+                        get(entry, rest, query)
+                    }
+                    Method::Post if rest == ["commands"] => post(entry, request),
+                    _ => Reply::error(405, "Method not allowed"),
+                }
+            }
+            _ => Reply::error(404, "Not found"),
+        }
+    }
+}
+
+fn get(entry: &Entry, rest: &[&str], query: &str) -> Reply {
+    let tree = &entry.tree;
+    let body = match rest {
+        [] => json!({
+            "persons": tree.get_persons(),
+            "relationships": tree.get_relationships(),
+            "places": tree.get_places(),
+        }),
+        ["persons"] => json!(tree.get_persons()),
+        ["persons", reference] => match tree.resolve_person(reference) {
+            Ok(pid) => json!(tree.get_persons().iter().find(|person| person.id == pid)),
+            Err(err @ LibError::Input(InputError::UnknownReference(_))) => {
+                return Reply::error(404, err)
+            }
+            Err(err) => return Reply::lib_error(err),
+        },
+        ["relationships"] => json!(tree.get_relationships()),
+        ["places"] => json!(tree.get_places()),
+        ["grid"] => json!(baumstamm_grid::generate(tree)),
+        ["log"] => match changelog::read_file(&changelog::log_path(&entry.path)) {
+            Ok(log) => json!(log),
+            Err(err) => return Reply::lib_error(err),
+        },
+        ["search"] => {
+            let query = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("q="))
+                .map(decode)
+                .unwrap_or_default();
+            json!(tree.search(&query))
+        }
+        _ => return Reply::error(404, "Not found"),
+    };
+    Reply::ok(body, &entry.etag)
+}
+
+fn post(entry: &mut Entry, request: &ApiRequest) -> Reply {
+    match request.if_match {
+        None => return Reply::error(428, "Commands require the ETag of the tree as If-Match"),
+        Some(etag) if etag != entry.etag && etag != "*" => {
+            let mut reply = Reply::error(412, "The tree changed, fetch it again");
+            reply.etag = Some(entry.etag.clone());
+            return reply;
+        }
+        Some(_) => {}
+    }
+    let body = match serde_json::from_str::<Value>(request.body) {
+        Ok(body) => body,
+        Err(err) => return Reply::lib_error(err.into()),
+    };
+    // restored, if the changes cannot be saved, so the tree matches the file and its ETag
+    let snapshot = entry.tree.clone();
+    let result = if body.is_array() {
+        serde_json::from_value::<Vec<Command>>(body)
+            .map_err(LibError::from)
+            .and_then(|commands| entry.tree.apply_all(commands))
+            .map(|results| json!(results))
+    } else {
+        serde_json::from_value::<Command>(body)
+            .map_err(LibError::from)
+            .and_then(|command| entry.tree.apply(command))
+            .map(|result| json!(result))
+    };
+    match result.and_then(|result| entry.save().map(|()| result)) {
+        Ok(result) => Reply::ok(json!({ "result": result }), &entry.etag),
+        Err(err) => {
+            entry.tree = snapshot;
+            Reply::lib_error(err)
+        }
+    }
+}
+
+/// Decode percent encoded characters and pluses, which encode spaces in queries.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Serves trees over HTTP, one request after the other.
+pub struct Server {
+    http: tiny_http::Server,
+    trees: Trees,
+}
+
+impl Server {
+    pub fn new(addr: &str, trees: Trees) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let http = tiny_http::Server::http(addr)?;
+        Ok(Self { http, trees })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Answer requests until the server fails.
+    pub fn run(mut self) -> io::Result<()> {
+        loop {
+            let request = self.http.recv()?;
+            self.respond(request)?;
+        }
+    }
+
+    fn respond(&mut self, mut request: Request) -> io::Result<()> {
+        let mut body = String::new();
+        if let Err(err) = request.as_reader().read_to_string(&mut body) {
+            let response = Response::from_string(err.to_string()).with_status_code(400);
+            return request.respond(response);
+        }
+        let header = |name: &str| {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.as_str().as_str().eq_ignore_ascii_case(name))
+                .map(|header| header.value.as_str())
+        };
+        let reply = self.trees.handle(&ApiRequest {
+            method: request.method(),
+            url: request.url(),
+            if_match: header("If-Match"),
+            if_none_match: header("If-None-Match"),
+            body: &body,
+        });
+        let mut response = Response::from_string(if reply.status == 304 {
+            String::new()
+        } else {
+            reply.body.to_string()
+        })
+        .with_status_code(reply.status)
+        .with_header(header_from("Content-Type", "application/json"));
+        if let Some(etag) = reply.etag {
+            response.add_header(header_from("ETag", &etag));
+        }
+        request.respond(response)
+    }
+}
+
+fn header_from(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("Header must be valid")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        fs::File,
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        thread,
+    };
+
+    /// Send a request and return status, ETag and body.
+    fn send(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        if_match: Option<&str>,
+        body: &str,
+    ) -> (u16, Option<String>, Value) {
+        let mut stream = TcpStream::connect(addr).expect("Server must run");
+        let if_match = if_match
+            .map(|etag| format!("If-Match: {}\r\n", etag))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            if_match,
+            body.len(),
+            body
+        )
+        .expect("Request must be sent");
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("Status line");
+        let status = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let mut etag = None;
+        loop {
+            line.clear();
+            reader.read_line(&mut line).expect("Header line");
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((field, value)) = header.split_once(": ") {
+                if field.eq_ignore_ascii_case("ETag") {
+                    etag = Some(value.to_string());
+                }
+            }
+        }
+        let mut body = String::new();
+        reader.read_to_string(&mut body).expect("Body");
+        (
+            status,
+            etag,
+            serde_json::from_str(&body).unwrap_or(Value::Null),
+        )
+    }
+
+    fn serve(name: &str) -> (SocketAddr, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("baumstamm-server-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.json", name));
+        fs::write(&path, FamilyTree::new().save().unwrap()).unwrap();
+        let trees = Trees::load([path.clone()]).unwrap();
+        let server = Server::new("127.0.0.1:0", trees).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        (addr, path)
+    }
+
+    #[test]
+    fn optimistic_concurrency() {
+        let (addr, path) = serve("concurrency");
+        let (status, etag, persons) = send(addr, "GET", "/trees/concurrency/persons", None, "");
+        assert_eq!(200, status);
+        let etag = etag.expect("Responses about a tree carry an ETag");
+        let person = persons[0]["id"].as_str().unwrap().to_string();
+
+        let command = json!({ "InsertInfo": {
+            "person": person,
+            "key": "@firstName",
+            "value": "Anna",
+        }})
+        .to_string();
+        let (status, _, _) = send(addr, "POST", "/trees/concurrency/commands", None, &command);
+        assert_eq!(428, status);
+        let (status, new_etag, body) = send(
+            addr,
+            "POST",
+            "/trees/concurrency/commands",
+            Some(&etag),
+            &command,
+        );
+        assert_eq!(200, status, "{}", body);
+        let new_etag = new_etag.unwrap();
+        assert_ne!(etag, new_etag);
+        // a second client with the old ETag must not overwrite the change
+        let (status, current, _) = send(
+            addr,
+            "POST",
+            "/trees/concurrency/commands",
+            Some(&etag),
+            &command,
+        );
+        assert_eq!(412, status);
+        assert_eq!(Some(new_etag), current);
+
+        let (status, _, anna) = send(addr, "GET", "/trees/concurrency/persons/anna", None, "");
+        assert_eq!(200, status);
+        assert_eq!(person, anna["id"]);
+        let saved = FamilyTree::read_from(BufReader::new(File::open(path).unwrap())).unwrap();
+        assert_eq!(
+            Some("Anna".to_string()),
+            saved.get_persons()[0].display_name()
+        );
+    }
+
+    #[test]
+    fn transaction() {
+        let (addr, _) = serve("transaction");
+        let (_, etag, rels) = send(addr, "GET", "/trees/transaction/relationships", None, "");
+        let etag = etag.unwrap();
+        let rel = rels[0]["id"].clone();
+        let commands = json!([
+            { "AddChild": { "relationship": rel } },
+            { "RemovePerson": { "person": "0" } },
+        ])
+        .to_string();
+        let (status, unchanged, body) = send(
+            addr,
+            "POST",
+            "/trees/transaction/commands",
+            Some(&etag),
+            &commands,
+        );
+        assert_eq!(400, status);
+        assert!(body["error"]["message"].is_string());
+        assert_eq!(None, unchanged);
+
+        let commands = json!([{ "AddChild": { "relationship": rel } }]).to_string();
+        let (status, _, body) = send(
+            addr,
+            "POST",
+            "/trees/transaction/commands",
+            Some(&etag),
+            &commands,
+        );
+        assert_eq!(200, status);
+        assert!(body["result"][0].is_string());
+        let (_, _, grid) = send(addr, "GET", "/trees/transaction/grid", None, "");
+        assert!(grid.is_array());
+        let (status, _, _) = send(addr, "GET", "/trees/unknown", None, "");
+        assert_eq!(404, status);
+    }
+
+    #[test]
+    fn failed_save() {
+        let dir = std::env::temp_dir().join(format!("baumstamm-server-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("failed_save.json");
+        fs::write(&path, FamilyTree::new().save().unwrap()).unwrap();
+        let mut trees = Trees::load([path.clone()]).unwrap();
+        let get = |trees: &mut Trees| {
+            trees.handle(&ApiRequest {
+                method: &Method::Get,
+                url: "/trees/failed_save/persons",
+                if_match: None,
+                if_none_match: None,
+                body: "",
+            })
+        };
+        let before = get(&mut trees);
+        let person = before.body[0]["id"].as_str().unwrap().to_string();
+        let command = json!({ "InsertInfo": {
+            "person": person,
+            "key": "@firstName",
+            "value": "Anna",
+        }})
+        .to_string();
+        // a directory in place of the file cannot be replaced
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        let reply = trees.handle(&ApiRequest {
+            method: &Method::Post,
+            url: "/trees/failed_save/commands",
+            if_match: before.etag.as_deref(),
+            if_none_match: None,
+            body: &command,
+        });
+        fs::remove_dir(&path).unwrap();
+        assert_eq!(500, reply.status);
+        let after = get(&mut trees);
+        assert_eq!(before.etag, after.etag);
+        assert_eq!(before.body, after.body);
+    }
+
+    #[test]
+    fn external_change() {
+        let (addr, path) = serve("external");
+        let (_, old, _) = send(addr, "GET", "/trees/external/persons", None, "");
+        let old = old.expect("Responses about a tree carry an ETag");
+
+        // e.g. the CLI adds a person
+        let mut tree = FamilyTree::load(&fs::read(&path).expect("File must exist"))
+            .expect("File must be a tree");
+        let root = tree.get_relationships()[0].id;
+        tree.add_child(root).expect("Child must be added");
+        let disk = tree.save_bytes().expect("Tree must be serializable");
+        fs::write(&path, &disk).expect("File must be written");
+
+        let command = json!({ "AddChild": { "relationship": root } }).to_string();
+        let (status, current, _) = send(
+            addr,
+            "POST",
+            "/trees/external/commands",
+            Some(&old),
+            &command,
+        );
+        assert_eq!(412, status);
+        assert_eq!(Some(etag(&disk)), current);
+        let (_, _, persons) = send(addr, "GET", "/trees/external/persons", None, "");
+        assert_eq!(Some(2), persons.as_array().map(Vec::len));
+
+        fs::write(&path, "{").expect("File must be written");
+        let (status, _, _) = send(addr, "GET", "/trees/external/persons", None, "");
+        assert_eq!(409, status);
+    }
+
+    #[test]
+    fn stable_etag() {
+        assert_eq!(
+            "\"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\"",
+            etag(b"")
+        );
+    }
+
+    #[test]
+    fn decode_query() {
+        assert_eq!("Anna Maria+", decode("Anna+Maria%2B"));
+        assert_eq!("Müller", decode("M%C3%BCller"));
+    }
+}
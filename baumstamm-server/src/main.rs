@@ -0,0 +1,29 @@
+use baumstamm_server::{Server, Trees};
+use clap::Parser;
+use std::{error::Error, path::PathBuf};
+
+#[derive(Parser)]
+struct Cli {
+    /// Tree files, which are served under their file stem
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Address to listen on, only the local machine by default
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let trees = Trees::load(args.files)?;
+    let names = trees.names().collect::<Vec<_>>().join(", ");
+    let server = Server::new(&format!("{}:{}", args.host, args.port), trees)
+        .map_err(|err| err.to_string())?;
+    if let Some(addr) = server.local_addr() {
+        println!("Serving {} on http://{}", names, addr);
+    }
+    Ok(server.run()?)
+}
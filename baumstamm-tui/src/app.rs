@@ -0,0 +1,660 @@
+//! State of the terminal UI and handling of key presses.
+
+use baumstamm_lib::{
+    changelog,
+    command::{Command, CommandResult},
+    error::Error as LibError,
+    graph::Graph,
+    storage, FamilyTree, Person, PersonId, Relationship, RelationshipId,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{error::Error, path::PathBuf};
+
+/// Part of the screen, which receives the arrow keys.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Focus {
+    Tree,
+    Relatives,
+}
+
+/// A question in the status line, which needs an answer before continuing.
+pub(crate) enum Mode {
+    Normal,
+    Input {
+        prompt: String,
+        input: String,
+        pending: Pending,
+    },
+    Confirm {
+        question: String,
+        pending: Pending,
+    },
+}
+
+/// The action waiting for input or confirmation.
+pub(crate) enum Pending {
+    FirstName,
+    LastName { first_name: String },
+    InfoKey,
+    InfoValue { key: String },
+    RemoveInfo,
+    ChildWith,
+    LinkPartner,
+    Merge,
+    Find,
+    Remove,
+    Quit,
+}
+
+/// A relative of the selected person as shown in the sidebar.
+pub(crate) struct Relative {
+    pub(crate) title: &'static str,
+    pub(crate) id: Option<PersonId>,
+}
+
+pub(crate) struct App {
+    pub(crate) path: PathBuf,
+    pub(crate) tree: FamilyTree,
+    /// Persons by generation, as they are drawn in the app.
+    pub(crate) layers: Vec<Vec<PersonId>>,
+    pub(crate) selected: PersonId,
+    pub(crate) focus: Focus,
+    /// Index into the relatives of the selected person.
+    pub(crate) relative: usize,
+    pub(crate) mode: Mode,
+    pub(crate) status: String,
+    pub(crate) quit: bool,
+}
+
+impl App {
+    pub(crate) fn new(path: PathBuf, tree: FamilyTree) -> Self {
+        let selected = tree.get_persons()[0].id;
+        let mut app = Self {
+            path,
+            tree,
+            layers: Vec::new(),
+            selected,
+            focus: Focus::Tree,
+            relative: 0,
+            mode: Mode::Normal,
+            status: String::new(),
+            quit: false,
+        };
+        app.update_layers();
+        if let Some(pid) = app.layers.iter().flatten().next() {
+            app.selected = *pid;
+        }
+        app
+    }
+
+    /// Whether there are changes, which are not saved.
+    pub(crate) fn is_dirty(&self) -> bool {
+        !self.tree.log().is_empty()
+    }
+
+    pub(crate) fn person(&self, pid: PersonId) -> Option<&Person> {
+        self.tree
+            .get_persons()
+            .iter()
+            .find(|person| person.id == pid)
+    }
+
+    /// The display name or a placeholder for a person.
+    pub(crate) fn name(&self, pid: PersonId) -> String {
+        self.person(pid)
+            .and_then(|person| person.display_name())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    pub(crate) fn parent_relationship(&self) -> Option<&Relationship> {
+        self.tree
+            .get_relationships()
+            .iter()
+            .find(|rel| rel.children.contains(&self.selected))
+    }
+
+    pub(crate) fn own_relationships(&self) -> Vec<&Relationship> {
+        self.tree
+            .get_relationships()
+            .iter()
+            .filter(|rel| rel.parents.contains(&Some(self.selected)))
+            .collect()
+    }
+
+    /// The partner of the selected person in a relationship.
+    pub(crate) fn partner(&self, rel: &Relationship) -> Option<PersonId> {
+        rel.parents
+            .iter()
+            .flatten()
+            .find(|parent| **parent != self.selected)
+            .copied()
+    }
+
+    /// Parents, partners and children of the selected person in this order.
+    pub(crate) fn relatives(&self) -> Vec<Relative> {
+        let mut relatives = Vec::new();
+        if let Some(rel) = self.parent_relationship() {
+            relatives.extend(rel.parents.iter().flatten().map(|pid| Relative {
+                title: "Parent",
+                id: Some(*pid),
+            }));
+        }
+        let own_rels = self.own_relationships();
+        relatives.extend(own_rels.iter().map(|rel| Relative {
+            title: "Partner",
+            id: self.partner(rel),
+        }));
+        relatives.extend(
+            own_rels
+                .iter()
+                .flat_map(|rel| rel.children.iter())
+                .map(|pid| Relative {
+                    title: "Child",
+                    id: Some(*pid),
+                }),
+        );
+        relatives
+    }
+
+    /// Layer and index of the selected person.
+    pub(crate) fn position(&self) -> (usize, usize) {
+        self.layers
+            .iter()
+            .enumerate()
+            .find_map(|(layer, pids)| {
+                pids.iter()
+                    .position(|pid| *pid == self.selected)
+                    .map(|index| (layer, index))
+            })
+            .unwrap_or_default()
+    }
+
+    fn update_layers(&mut self) {
+        let rels = self.tree.get_relationships();
+        self.layers = Graph::new(rels).cut().person_layers(rels);
+        // persons, which are not part of a layer, are shown in an extra one
+        let missing = self
+            .tree
+            .get_persons()
+            .iter()
+            .map(|person| person.id)
+            .filter(|pid| !self.layers.iter().flatten().any(|layered| layered == pid))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            self.layers.push(missing);
+        }
+        if self.person(self.selected).is_none() {
+            self.selected = self.tree.get_persons()[0].id;
+        }
+        self.relative = self.relative.min(self.relatives().len().saturating_sub(1));
+    }
+
+    fn select(&mut self, pid: PersonId) {
+        self.selected = pid;
+        self.relative = 0;
+    }
+
+    /// Apply commands as one transaction and show the error, if they fail.
+    fn apply(&mut self, commands: Vec<Command>) -> Option<Vec<CommandResult>> {
+        match self.tree.apply_all(commands) {
+            Ok(results) => {
+                self.update_layers();
+                Some(results)
+            }
+            Err(err) => {
+                self.status = format!("Error: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Save the tree and append its recorded changes to the log next to it.
+    fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        storage::save(&self.path, &self.tree.save_bytes()?)?;
+        changelog::append_to_file(&changelog::log_path(&self.path), self.tree.log())?;
+        self.tree.take_log();
+        Ok(())
+    }
+
+    fn ask(&mut self, prompt: &str, input: String, pending: Pending) {
+        self.mode = Mode::Input {
+            prompt: prompt.to_string(),
+            input,
+            pending,
+        };
+    }
+
+    fn confirm(&mut self, question: String, pending: Pending) {
+        self.mode = Mode::Confirm { question, pending };
+    }
+
+    pub(crate) fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.status.clear();
+            self.mode = Mode::Normal;
+            self.request_quit();
+            return;
+        }
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal(key),
+            Mode::Input {
+                prompt,
+                mut input,
+                pending,
+            } => match key.code {
+                KeyCode::Enter => self.submit(pending, input.trim().to_string()),
+                KeyCode::Esc => self.status.clear(),
+                code => {
+                    match code {
+                        KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        KeyCode::Char(c) => input.push(c),
+                        _ => {}
+                    }
+                    self.mode = Mode::Input {
+                        prompt,
+                        input,
+                        pending,
+                    };
+                }
+            },
+            Mode::Confirm { question, pending } => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => self.submit(pending, String::new()),
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => self.status.clear(),
+                _ => self.mode = Mode::Confirm { question, pending },
+            },
+        }
+    }
+
+    fn handle_normal(&mut self, key: KeyEvent) {
+        self.status.clear();
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Tree => Focus::Relatives,
+                    Focus::Relatives => Focus::Tree,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(0, -1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(0, 1),
+            KeyCode::Left | KeyCode::Char('h') => self.move_cursor(-1, 0),
+            KeyCode::Right | KeyCode::Char('l') => self.move_cursor(1, 0),
+            KeyCode::Enter if self.focus == Focus::Relatives => {
+                match self.relatives().get(self.relative).and_then(|rel| rel.id) {
+                    Some(pid) => self.select(pid),
+                    None => self.status = "The partner is unknown".to_string(),
+                }
+            }
+            KeyCode::Char('n') => {
+                let (first_name, _) = self.names();
+                self.ask("First name", first_name, Pending::FirstName);
+            }
+            KeyCode::Char('i') => self.ask("Info key", String::new(), Pending::InfoKey),
+            KeyCode::Char('d') => self.ask("Remove info key", String::new(), Pending::RemoveInfo),
+            KeyCode::Char('P') => self.add_parent(),
+            KeyCode::Char('C') => self.add_child(),
+            KeyCode::Char('R') => self.add_partner(),
+            KeyCode::Char('L') => {
+                self.ask("Partner id or name", String::new(), Pending::LinkPartner)
+            }
+            KeyCode::Char('m') => self.ask("Merge with id or name", String::new(), Pending::Merge),
+            KeyCode::Char('x') => {
+                let question = format!("Remove {}? (y/n)", self.name(self.selected));
+                self.confirm(question, Pending::Remove);
+            }
+            KeyCode::Char('/') => self.ask("Find id or name", String::new(), Pending::Find),
+            KeyCode::Char('u') => match self.tree.undo() {
+                Ok(Some(operation)) => {
+                    self.update_layers();
+                    self.status = format!("Reverted {:?}", operation);
+                }
+                Ok(None) => self.status = "Nothing to undo since the last save".to_string(),
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            KeyCode::Char('s') => match self.save() {
+                Ok(()) => self.status = format!("Saved \"{}\"", self.path.display()),
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            KeyCode::Char('q') | KeyCode::Esc => self.request_quit(),
+            _ => {}
+        }
+    }
+
+    /// Quit, after confirming to discard unsaved changes.
+    fn request_quit(&mut self) {
+        if self.is_dirty() {
+            let question = "Quit without saving? (y/n)".to_string();
+            self.confirm(question, Pending::Quit);
+        } else {
+            self.quit = true;
+        }
+    }
+
+    /// Move in the tree between generations and persons or in the list of relatives.
+    fn move_cursor(&mut self, horizontal: isize, vertical: isize) {
+        match self.focus {
+            Focus::Tree => {
+                let (layer, index) = self.position();
+                let layer = layer
+                    .saturating_add_signed(vertical)
+                    .min(self.layers.len() - 1);
+                let pids = &self.layers[layer];
+                if pids.is_empty() {
+                    return;
+                }
+                let index = index.saturating_add_signed(horizontal).min(pids.len() - 1);
+                self.select(pids[index]);
+            }
+            Focus::Relatives => {
+                let len = self.relatives().len();
+                if len > 0 {
+                    self.relative = self
+                        .relative
+                        .saturating_add_signed(vertical + horizontal)
+                        .min(len - 1);
+                }
+            }
+        }
+    }
+
+    /// First and last name from the info of the selected person.
+    fn names(&self) -> (String, String) {
+        let info = self
+            .person(self.selected)
+            .and_then(|person| person.info.as_ref());
+        let get = |key: &str| {
+            info.and_then(|info| info.get(key))
+                .cloned()
+                .unwrap_or_default()
+        };
+        (get("@firstName"), get("@lastName"))
+    }
+
+    fn add_parent(&mut self) {
+        let Some(rel) = self.parent_relationship() else {
+            return;
+        };
+        let command = Command::AddParent {
+            relationship: rel.id,
+        };
+        if let Some(results) = self.apply(vec![command]) {
+            if let [CommandResult::ParentAndRelationship(pid, _)] = results.as_slice() {
+                self.select(*pid);
+            }
+        }
+    }
+
+    fn add_child(&mut self) {
+        let own_rels = self.own_relationships();
+        match own_rels.as_slice() {
+            [] => {
+                // a child needs a relationship to be part of
+                let pid = self.selected;
+                let result = self.tree.transaction(|tree| {
+                    let rid = tree.add_new_relationship(pid)?;
+                    tree.add_child(rid)
+                });
+                self.added(result);
+            }
+            [rel] => {
+                let rid = rel.id;
+                self.add_child_to(rid);
+            }
+            _ => self.ask(
+                &format!("Child with partner number (1-{})", own_rels.len()),
+                String::new(),
+                Pending::ChildWith,
+            ),
+        }
+    }
+
+    fn add_child_to(&mut self, relationship: RelationshipId) {
+        if let Some(results) = self.apply(vec![Command::AddChild { relationship }]) {
+            if let [CommandResult::Person(pid)] = results.as_slice() {
+                self.select(*pid);
+            }
+        }
+    }
+
+    /// Add a relationship with a new partner.
+    fn add_partner(&mut self) {
+        let pid = self.selected;
+        let result = self.tree.transaction(|tree| {
+            let rid = tree.add_new_relationship(pid)?;
+            tree.add_parent(rid).map(|(partner, _)| partner)
+        });
+        self.added(result);
+    }
+
+    /// Select a person added outside of a command or show the error.
+    fn added(&mut self, result: Result<PersonId, LibError>) {
+        match result {
+            Ok(pid) => {
+                self.update_layers();
+                self.select(pid);
+            }
+            Err(err) => self.status = format!("Error: {}", err),
+        }
+    }
+
+    fn submit(&mut self, pending: Pending, input: String) {
+        match pending {
+            Pending::FirstName => {
+                let (_, last_name) = self.names();
+                self.ask(
+                    "Last name",
+                    last_name,
+                    Pending::LastName { first_name: input },
+                );
+            }
+            Pending::LastName { first_name } => {
+                let commands = [("@firstName", first_name), ("@lastName", input)]
+                    .into_iter()
+                    .map(|(key, value)| self.set_info_command(key, value))
+                    .collect::<Vec<_>>();
+                self.apply(commands.into_iter().flatten().collect());
+            }
+            Pending::InfoKey if input.is_empty() => {}
+            Pending::InfoKey => {
+                let value = self
+                    .person(self.selected)
+                    .and_then(|person| person.info.as_ref())
+                    .and_then(|info| info.get(&input))
+                    .cloned()
+                    .unwrap_or_default();
+                let prompt = format!("Value of \"{}\"", input);
+                self.ask(&prompt, value, Pending::InfoValue { key: input });
+            }
+            Pending::InfoValue { key } => {
+                if let Some(command) = self.set_info_command(&key, input) {
+                    self.apply(vec![command]);
+                }
+            }
+            Pending::RemoveInfo => {
+                self.apply(vec![Command::RemoveInfo {
+                    person: self.selected,
+                    key: input,
+                }]);
+            }
+            Pending::ChildWith => {
+                let rid = input
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| number.checked_sub(1))
+                    .and_then(|index| self.own_relationships().get(index).map(|rel| rel.id));
+                match rid {
+                    Some(rid) => self.add_child_to(rid),
+                    None => self.status = format!("There is no partner number \"{}\"", input),
+                }
+            }
+            Pending::LinkPartner => match self.tree.resolve_person(&input) {
+                Ok(partner) => {
+                    self.apply(vec![Command::AddRelationshipWithPartner {
+                        person: self.selected,
+                        partner,
+                    }]);
+                }
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            Pending::Merge => match self.tree.resolve_person(&input) {
+                Ok(other) => {
+                    let name = self.name(other);
+                    let command = Command::MergePerson {
+                        person1: self.selected,
+                        person2: other,
+                    };
+                    if self.apply(vec![command]).is_some() {
+                        self.status = format!("Merged with {}", name);
+                    }
+                }
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            Pending::Find => match self.tree.resolve_person(&input) {
+                Ok(pid) => self.select(pid),
+                Err(err) => self.status = format!("Error: {}", err),
+            },
+            Pending::Remove => {
+                let command = Command::RemovePerson {
+                    person: self.selected,
+                };
+                self.apply(vec![command]);
+            }
+            Pending::Quit => self.quit = true,
+        }
+    }
+
+    /// Command to insert the info or to remove it, if the value is empty.
+    fn set_info_command(&self, key: &str, value: String) -> Option<Command> {
+        let exists = self
+            .person(self.selected)
+            .and_then(|person| person.info.as_ref())
+            .is_some_and(|info| info.contains_key(key));
+        if !value.is_empty() {
+            Some(Command::InsertInfo {
+                person: self.selected,
+                key: key.to_string(),
+                value,
+            })
+        } else if exists {
+            Some(Command::RemoveInfo {
+                person: self.selected,
+                key: key.to_string(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn app() -> App {
+        App::new(PathBuf::from("test.json"), FamilyTree::new())
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn ctrl_c(app: &mut App) {
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn add_parent_and_child() {
+        let mut app = app();
+        let first = app.selected;
+        press(&mut app, KeyCode::Char('P'));
+        let parent = app.selected;
+        assert_ne!(first, parent);
+        assert_eq!(2, app.tree.get_persons().len());
+
+        app.select(first);
+        assert_eq!(Some(parent), app.relatives().first().and_then(|rel| rel.id));
+        press(&mut app, KeyCode::Char('C'));
+        let child = app.selected;
+        assert_eq!(3, app.tree.get_persons().len());
+        assert!(app.own_relationships().is_empty());
+        app.select(first);
+        assert_eq!(vec![child], app.own_relationships()[0].children);
+        assert!(app.status.is_empty());
+    }
+
+    #[test]
+    fn set_names() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('n'));
+        for c in "Anna".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.mode, Mode::Input { .. }));
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.mode, Mode::Normal));
+        assert_eq!("Anna", app.name(app.selected));
+    }
+
+    #[test]
+    fn undo() {
+        let mut app = app();
+        let first = app.selected;
+        press(&mut app, KeyCode::Char('P'));
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(1, app.tree.get_persons().len());
+        assert_eq!(first, app.selected);
+        assert!(!app.is_dirty());
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!("Nothing to undo since the last save", app.status);
+    }
+
+    #[test]
+    fn quit() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.quit);
+
+        let mut app = self::app();
+        press(&mut app, KeyCode::Char('P'));
+        press(&mut app, KeyCode::Char('q'));
+        assert!(!app.quit);
+        assert!(matches!(
+            app.mode,
+            Mode::Confirm {
+                pending: Pending::Quit,
+                ..
+            }
+        ));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(!app.quit);
+        assert!(matches!(app.mode, Mode::Normal));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('y'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn ctrl_c_confirms_unsaved_changes() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('P'));
+        // also while asking for input
+        press(&mut app, KeyCode::Char('n'));
+        ctrl_c(&mut app);
+        assert!(!app.quit);
+        assert!(matches!(
+            app.mode,
+            Mode::Confirm {
+                pending: Pending::Quit,
+                ..
+            }
+        ));
+        press(&mut app, KeyCode::Char('y'));
+        assert!(app.quit);
+
+        let mut app = self::app();
+        ctrl_c(&mut app);
+        assert!(app.quit);
+    }
+}
@@ -0,0 +1,73 @@
+use app::App;
+use baumstamm_lib::FamilyTree;
+use clap::Parser;
+use crossterm::{
+    event::{self, Event, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+};
+
+mod app;
+mod ui;
+
+#[derive(Parser)]
+struct Cli {
+    file: PathBuf,
+
+    /// Create a new tree, if the file does not exist
+    #[arg(short, long)]
+    new: bool,
+
+    /// Author recorded in the change log
+    #[arg(long)]
+    author: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let mut tree = if args.new && !args.file.exists() {
+        FamilyTree::new()
+    } else {
+        let reader = BufReader::new(File::open(&args.file)?);
+        FamilyTree::read_from(reader)?
+    };
+    tree.set_author(args.author);
+    let mut app = App::new(args.file, tree);
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    // restore the terminal, before a panic message is printed
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        hook(info);
+    }));
+    let result = run(&mut app);
+    restore()?;
+    result
+}
+
+fn run(app: &mut App) -> Result<(), Box<dyn Error>> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn restore() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
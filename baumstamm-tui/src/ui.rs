@@ -0,0 +1,149 @@
+//! Drawing of the generations, the sidebar of the selected person and the status line.
+
+use crate::app::{App, Focus, Mode};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+
+const HELP: &str = "tab relatives  n name  i info  d remove info  P parent  C child  R partner  \
+    L link partner  m merge  x remove  / find  u undo  s save  q quit";
+
+pub(crate) fn draw(frame: &mut Frame, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(rows[0]);
+    draw_tree(frame, app, columns[0]);
+    draw_sidebar(frame, app, columns[1]);
+    draw_status(frame, app, rows[1]);
+    frame.render_widget(Paragraph::new(HELP).dim(), rows[2]);
+}
+
+fn border_style(active: bool) -> Style {
+    if active {
+        Style::default().add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().add_modifier(Modifier::DIM)
+    }
+}
+
+/// One line per generation, scrolled so that the selected person is visible.
+fn draw_tree(frame: &mut Frame, app: &App, area: Rect) {
+    let (selected_layer, _) = app.position();
+    let mut column = 0;
+    let lines = app
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(layer, pids)| {
+            let mut spans = vec![Span::from(format!("{:>3}  ", layer + 1)).dim()];
+            for pid in pids {
+                let name = app.name(*pid);
+                if *pid == app.selected {
+                    column =
+                        spans.iter().map(|span| span.width()).sum::<usize>() + name.chars().count();
+                    spans.push(Span::from(name).reversed());
+                } else {
+                    spans.push(Span::from(name));
+                }
+                spans.push(Span::from("  "));
+            }
+            Line::from(spans)
+        })
+        .collect::<Vec<_>>();
+    let height = area.height.saturating_sub(2) as usize;
+    let width = area.width.saturating_sub(2) as usize;
+    let row = (selected_layer + 1).saturating_sub(height);
+    let column = column.saturating_sub(width);
+    let dirty = if app.is_dirty() { " *" } else { "" };
+    let title = format!(" {}{} ", app.path.display(), dirty);
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border_style(app.focus == Focus::Tree));
+    let tree = Paragraph::new(lines)
+        .block(block)
+        .scroll((row as u16, column as u16));
+    frame.render_widget(tree, area);
+}
+
+/// Name, info and relatives of the selected person, like the sidebar of the app.
+fn draw_sidebar(frame: &mut Frame, app: &App, area: Rect) {
+    let person = app.person(app.selected);
+    let mut lines = vec![
+        Line::from(app.name(app.selected).bold()),
+        Line::from(app.selected.to_string().dim()),
+        Line::default(),
+        Line::from("Info".underlined()),
+    ];
+    let mut info = person
+        .and_then(|person| person.info.as_ref())
+        .map(|info| info.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+    info.sort();
+    lines.extend(
+        info.into_iter()
+            .map(|(key, value)| Line::from(vec![format!("{}: ", key).dim(), value.into()])),
+    );
+    let mut title = "";
+    let mut partners = 0;
+    for (index, relative) in app.relatives().iter().enumerate() {
+        if relative.title != title {
+            title = relative.title;
+            lines.push(Line::default());
+            lines.push(Line::from(format!("{}s", title).underlined()));
+        }
+        let name = match relative.id {
+            Some(pid) => app.name(pid),
+            None => "Unknown".to_string(),
+        };
+        // partners are numbered for choosing the other parent of a new child
+        let label = if relative.title == "Partner" {
+            partners += 1;
+            format!("{}. {}", partners, name)
+        } else {
+            name
+        };
+        let span = if app.focus == Focus::Relatives && index == app.relative {
+            Span::from(label).reversed()
+        } else {
+            Span::from(label)
+        };
+        lines.push(Line::from(span));
+    }
+    let block = Block::default()
+        .title(" Person ")
+        .borders(Borders::ALL)
+        .border_style(border_style(app.focus == Focus::Relatives));
+    let sidebar = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    frame.render_widget(sidebar, area);
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let line = match &app.mode {
+        Mode::Normal => Line::from(app.status.as_str()),
+        Mode::Input { prompt, input, .. } => {
+            frame.set_cursor(
+                area.x + (prompt.chars().count() + input.chars().count()) as u16 + 2,
+                area.y,
+            );
+            Line::from(vec![format!("{}: ", prompt).bold(), input.as_str().into()])
+        }
+        Mode::Confirm { question, .. } => Line::from(question.as_str().bold()),
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}